@@ -0,0 +1,64 @@
+"""Example of a local stand-in server receiving the bubbaloop webhooks.
+
+It verifies the signature of the payloads and prints them. It can also fail a
+share of the requests to exercise the retries and the dead-letter log.
+
+Usage:
+    python examples/python-webhooks/receiver.py --port 8080 --secret my-secret --fail-rate 0.5
+"""
+
+import argparse
+import hashlib
+import hmac
+import json
+import random
+from http.server import BaseHTTPRequestHandler, HTTPServer
+
+
+def make_handler(secret: str | None, fail_rate: float):
+    class WebhookHandler(BaseHTTPRequestHandler):
+        def do_POST(self):
+            body = self.rfile.read(int(self.headers.get("Content-Length", 0)))
+
+            if secret is not None:
+                expected = hmac.new(secret.encode(), body, hashlib.sha256).hexdigest()
+                signature = self.headers.get("X-Bubbaloop-Signature", "")
+                if not hmac.compare_digest(signature, f"sha256={expected}"):
+                    print("Rejected a payload with an invalid signature")
+                    self.send_response(401)
+                    self.end_headers()
+                    return
+
+            if random.random() < fail_rate:
+                print(f"Failing delivery {self.headers.get('X-Bubbaloop-Delivery')}")
+                self.send_response(503)
+                self.end_headers()
+                return
+
+            payload = json.loads(body)
+            print(f"[{payload['event']}] {json.dumps(payload['data'])}")
+
+            self.send_response(200)
+            self.end_headers()
+
+        def log_message(self, format, *args):
+            pass
+
+    return WebhookHandler
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--host", type=str, default="0.0.0.0")
+    parser.add_argument("--port", type=int, default=8080)
+    parser.add_argument("--secret", type=str, default=None)
+    parser.add_argument("--fail-rate", type=float, default=0.0)
+    args = parser.parse_args()
+
+    server = HTTPServer((args.host, args.port), make_handler(args.secret, args.fail_rate))
+    print(f"Listening for webhooks on {args.host}:{args.port}")
+    server.serve_forever()
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,438 @@
+use crate::{
+    api::models::{
+        alerts::{Alert, AlertListQuery, AlertRule},
+        inference::InferenceResult,
+        recording::RecordingSessionConfig,
+        webhooks::WebhookEvent,
+    },
+    cu29::msgs::EncodedImage,
+    pipeline::{BroadcastSender, ResultStore},
+};
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The number of alerts kept in memory with their frames
+const MAX_ALERTS: usize = 100;
+
+/// The counter making the recording sessions of the alerts unique within the same millisecond
+static NEXT_ALERT_SESSION: AtomicU64 = AtomicU64::new(0);
+
+/// An alert with the frame it fired on
+type AlertFrame = (Alert, Option<EncodedImage>);
+
+#[derive(Debug)]
+pub enum AlertError {
+    /// The pattern of the rule is not a valid regular expression
+    InvalidPattern(regex::Error),
+    /// The rule has neither a pattern nor keywords
+    EmptyRule,
+}
+
+impl std::fmt::Display for AlertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertError::InvalidPattern(e) => write!(f, "Invalid pattern: {}", e),
+            AlertError::EmptyRule => write!(f, "The rule needs a pattern or keywords"),
+        }
+    }
+}
+
+impl std::error::Error for AlertError {}
+
+/// A rule with its pattern compiled once
+struct CompiledRule {
+    rule: AlertRule,
+    regex: Option<Regex>,
+    // the keywords in lowercase to ignore the case
+    keywords: Vec<String>,
+}
+
+impl CompiledRule {
+    fn new(rule: AlertRule) -> Result<Self, AlertError> {
+        if rule.pattern.is_none() && rule.keywords.is_empty() {
+            return Err(AlertError::EmptyRule);
+        }
+
+        let regex = rule
+            .pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(AlertError::InvalidPattern)?;
+
+        let keywords = rule.keywords.iter().map(|k| k.to_lowercase()).collect();
+
+        Ok(Self {
+            rule,
+            regex,
+            keywords,
+        })
+    }
+
+    fn matches(&self, channel_id: u8, response: &str) -> bool {
+        if self.rule.channel_id.is_some_and(|id| id != channel_id) {
+            return false;
+        }
+
+        let response_lower = response.to_lowercase();
+
+        self.regex
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(response))
+            || self.keywords.iter().any(|k| response_lower.contains(k))
+    }
+}
+
+/// Global store of the alert rules and the alerts they fired
+#[derive(Clone, Default)]
+pub struct AlertStore {
+    // the rules indexed by their id
+    rules: Arc<Mutex<BTreeMap<String, CompiledRule>>>,
+    // the latest alerts with the frame they fired on
+    alerts: Arc<Mutex<VecDeque<AlertFrame>>>,
+    // the wall time in nanoseconds each rule last fired per channel
+    last_fired: Arc<Mutex<HashMap<(String, u8), u64>>>,
+    /// the alerts broadcasted to the event stream
+    pub events: BroadcastSender<Alert>,
+}
+
+impl AlertStore {
+    /// Add a rule or replace the rule with the same id
+    pub fn add_rule(&self, rule: AlertRule) -> Result<(), AlertError> {
+        let compiled = CompiledRule::new(rule)?;
+        self.rules
+            .lock()
+            .unwrap()
+            .insert(compiled.rule.id.clone(), compiled);
+        Ok(())
+    }
+
+    /// Remove a rule, returns false if the rule does not exist
+    pub fn remove_rule(&self, rule_id: &str) -> bool {
+        self.last_fired
+            .lock()
+            .unwrap()
+            .retain(|(id, _), _| id != rule_id);
+        self.rules.lock().unwrap().remove(rule_id).is_some()
+    }
+
+    /// Check if any rule is defined, so that the frames are only kept when needed
+    pub fn has_rules(&self) -> bool {
+        !self.rules.lock().unwrap().is_empty()
+    }
+
+    /// List all the rules
+    pub fn rules(&self) -> Vec<AlertRule> {
+        self.rules
+            .lock()
+            .unwrap()
+            .values()
+            .map(|compiled| compiled.rule.clone())
+            .collect()
+    }
+
+    /// List the latest alerts, the most recent first
+    pub fn alerts(&self, query: &AlertListQuery) -> Vec<Alert> {
+        self.alerts
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .map(|(alert, _)| alert)
+            .filter(|alert| {
+                query.rule_id.as_ref().is_none_or(|id| &alert.rule_id == id)
+                    && query.channel_id.is_none_or(|id| alert.channel_id == id)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The frame the alert fired on
+    pub fn frame(&self, alert_id: &str) -> Option<EncodedImage> {
+        self.alerts
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(alert, _)| alert.alert_id == alert_id)
+            .and_then(|(_, frame)| frame.clone())
+    }
+
+    /// The rules matching the result and out of their cooldown window
+    fn match_rules(&self, result: &InferenceResult, now_ns: u64) -> Vec<AlertRule> {
+        let rules = self.rules.lock().unwrap();
+        let mut last_fired = self.last_fired.lock().unwrap();
+
+        rules
+            .values()
+            .filter(|compiled| compiled.matches(result.channel_id, &result.response))
+            .filter(|compiled| {
+                let key = (compiled.rule.id.clone(), result.channel_id);
+                let cooldown_ns = compiled.rule.cooldown_secs.saturating_mul(1_000_000_000);
+                let is_ready = last_fired
+                    .get(&key)
+                    .is_none_or(|last| now_ns.saturating_sub(*last) >= cooldown_ns);
+                if is_ready {
+                    last_fired.insert(key, now_ns);
+                }
+                is_ready
+            })
+            .map(|compiled| compiled.rule.clone())
+            .collect()
+    }
+
+    /// Keep the alert and send it to the event stream
+    fn publish(&self, alert: Alert, frame: Option<EncodedImage>) {
+        let mut alerts = self.alerts.lock().unwrap();
+        alerts.push_back((alert.clone(), frame));
+        while alerts.len() > MAX_ALERTS {
+            alerts.pop_front();
+        }
+        drop(alerts);
+
+        let _ = self.events.tx.send(alert);
+    }
+}
+
+/// Evaluate the alert rules on a new inference result and fire the matching alerts
+///
+/// The alerts carry the frame the inference ran on and start a recording of the
+/// channel if their rule asks for it.
+pub fn fire_alerts(store: &ResultStore, result: &InferenceResult, frame: Option<&EncodedImage>) {
+    let now_ns = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+
+    for rule in store.alerts.match_rules(result, now_ns) {
+        log::info!(
+            "Alert {} fired on channel {}: {}",
+            rule.id,
+            result.channel_id,
+            result.response
+        );
+
+        // NOTE: the alert id is the rule, the channel and the timestamp in milliseconds
+        let alert_id = format!("{}-{}-{}", rule.id, result.channel_id, now_ns / 1_000_000);
+
+        let frame = frame.cloned();
+
+        let session_id = rule.record_secs.and_then(|duration_secs| {
+            let config = RecordingSessionConfig {
+                // several rules can fire at once, the counter keeps their sessions apart
+                session_id: format!(
+                    "{}-{}",
+                    now_ns / 1_000_000,
+                    NEXT_ALERT_SESSION.fetch_add(1, Ordering::Relaxed)
+                ),
+                name: Some(format!("alert {}", rule.id)),
+                channels: vec![result.channel_id],
+                tags: vec!["alert".to_string(), rule.id.clone()],
+                duration_secs: Some(duration_secs),
+            };
+            let session_id = config.session_id.clone();
+            match store.recording.start_session(config.clone()) {
+                Ok(()) => {
+                    store
+                        .webhooks
+                        .notify(WebhookEvent::RecordingStarted(config));
+                    Some(session_id)
+                }
+                Err(e) => {
+                    log::warn!("Alert {} failed to start recording: {}", rule.id, e);
+                    None
+                }
+            }
+        });
+
+        let alert = Alert {
+            frame: frame
+                .as_ref()
+                .map(|_| format!("/api/v0/alerts/frames/{}", alert_id)),
+            alert_id,
+            rule_id: rule.id,
+            stamp_ns: now_ns,
+            channel_id: result.channel_id,
+            prompt: result.prompt.clone(),
+            response: result.response.clone(),
+            session_id,
+        };
+
+        store.webhooks.notify(WebhookEvent::Alert(alert.clone()));
+        store.alerts.publish(alert, frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND_NS: u64 = 1_000_000_000;
+
+    fn rule(id: &str) -> AlertRule {
+        AlertRule {
+            id: id.to_string(),
+            channel_id: None,
+            pattern: None,
+            keywords: Vec::new(),
+            cooldown_secs: 0,
+            record_secs: None,
+        }
+    }
+
+    fn result(channel_id: u8, response: &str) -> InferenceResult {
+        InferenceResult {
+            stamp_ns: 0,
+            channel_id,
+            prompt: "caption en".to_string(),
+            response: response.to_string(),
+            params: Default::default(),
+            latency_ms: 0,
+            model: "mock".to_string(),
+        }
+    }
+
+    fn fired(store: &AlertStore, result: &InferenceResult, now_ns: u64) -> Vec<String> {
+        store
+            .match_rules(result, now_ns)
+            .into_iter()
+            .map(|rule| rule.id)
+            .collect()
+    }
+
+    #[test]
+    fn rules_without_pattern_and_keywords_are_rejected() {
+        let store = AlertStore::default();
+        assert!(matches!(
+            store.add_rule(rule("empty")),
+            Err(AlertError::EmptyRule)
+        ));
+        assert!(!store.has_rules());
+    }
+
+    #[test]
+    fn rules_with_invalid_pattern_are_rejected() {
+        let store = AlertStore::default();
+        let invalid = AlertRule {
+            pattern: Some("person(".to_string()),
+            ..rule("invalid")
+        };
+        assert!(matches!(
+            store.add_rule(invalid),
+            Err(AlertError::InvalidPattern(_))
+        ));
+        assert!(!store.has_rules());
+    }
+
+    #[test]
+    fn pattern_matches_the_response() {
+        let store = AlertStore::default();
+        store
+            .add_rule(AlertRule {
+                pattern: Some(r"\b\d+ (people|persons)\b".to_string()),
+                ..rule("crowd")
+            })
+            .unwrap();
+
+        assert_eq!(
+            fired(&store, &result(0, "3 people at the door"), 0),
+            ["crowd"]
+        );
+        assert!(fired(&store, &result(0, "a person at the door"), SECOND_NS).is_empty());
+    }
+
+    #[test]
+    fn keywords_ignore_the_case() {
+        let store = AlertStore::default();
+        store
+            .add_rule(AlertRule {
+                keywords: vec!["Fire".to_string(), "smoke".to_string()],
+                ..rule("fire")
+            })
+            .unwrap();
+
+        assert_eq!(
+            fired(&store, &result(0, "A FIRE in the kitchen"), 0),
+            ["fire"]
+        );
+        assert_eq!(
+            fired(&store, &result(0, "Smoke rising"), SECOND_NS),
+            ["fire"]
+        );
+        assert!(fired(&store, &result(0, "An empty kitchen"), 2 * SECOND_NS).is_empty());
+    }
+
+    #[test]
+    fn channel_filter_skips_the_other_channels() {
+        let store = AlertStore::default();
+        store
+            .add_rule(AlertRule {
+                channel_id: Some(1),
+                keywords: vec!["person".to_string()],
+                ..rule("door")
+            })
+            .unwrap();
+        store
+            .add_rule(AlertRule {
+                keywords: vec!["person".to_string()],
+                ..rule("any")
+            })
+            .unwrap();
+
+        assert_eq!(fired(&store, &result(0, "a person"), 0), ["any"]);
+        assert_eq!(fired(&store, &result(1, "a person"), 0), ["any", "door"]);
+    }
+
+    #[test]
+    fn cooldown_is_per_rule_and_channel() {
+        let store = AlertStore::default();
+        store
+            .add_rule(AlertRule {
+                keywords: vec!["person".to_string()],
+                cooldown_secs: 10,
+                ..rule("person")
+            })
+            .unwrap();
+        let response = "a person";
+
+        assert_eq!(
+            fired(&store, &result(0, response), 100 * SECOND_NS),
+            ["person"]
+        );
+        // inside the cooldown window of channel 0, the other channels are not affected
+        assert!(fired(&store, &result(0, response), 105 * SECOND_NS).is_empty());
+        assert!(fired(&store, &result(0, response), 110 * SECOND_NS - 1).is_empty());
+        assert_eq!(
+            fired(&store, &result(1, response), 105 * SECOND_NS),
+            ["person"]
+        );
+        // the window starts again from the last alert, not from the skipped matches
+        assert_eq!(
+            fired(&store, &result(0, response), 110 * SECOND_NS),
+            ["person"]
+        );
+        assert!(fired(&store, &result(0, response), 119 * SECOND_NS).is_empty());
+    }
+
+    #[test]
+    fn removing_a_rule_resets_its_cooldown() {
+        let store = AlertStore::default();
+        let person = AlertRule {
+            keywords: vec!["person".to_string()],
+            cooldown_secs: 60,
+            ..rule("person")
+        };
+        store.add_rule(person.clone()).unwrap();
+        assert_eq!(fired(&store, &result(0, "a person"), 0), ["person"]);
+
+        assert!(store.remove_rule("person"));
+        assert!(!store.remove_rule("person"));
+        store.add_rule(person).unwrap();
+        assert_eq!(fired(&store, &result(0, "a person"), SECOND_NS), ["person"]);
+    }
+}
@@ -0,0 +1,679 @@
+use crate::{
+    api::models::{
+        analytics::{
+            AnalyticsListQuery, CrossDirection, Zone, ZoneAnalytics, ZoneCounters, ZoneEvent,
+            ZoneEventKind, ZoneShape,
+        },
+        webhooks::WebhookEvent,
+    },
+    cu29::msgs::{TrackStatus, TracksMsg},
+    pipeline::{BroadcastSender, ResultStore},
+};
+use std::{
+    collections::{btree_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// The number of zone events kept in memory
+const MAX_EVENTS: usize = 1000;
+
+#[derive(Debug)]
+pub enum AnalyticsError {
+    /// The polygon has less than 3 points
+    TooFewPoints(usize),
+    /// The two points of the line are the same
+    EmptyLine,
+    /// A point is not normalized to the image size
+    OutOfRange([f32; 2]),
+    /// The zones file could not be read
+    Io(std::io::Error),
+    /// The zones file is not a json list of zones
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for AnalyticsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnalyticsError::TooFewPoints(n) => {
+                write!(f, "The polygon needs at least 3 points, got {}", n)
+            }
+            AnalyticsError::EmptyLine => write!(f, "The two points of the line are the same"),
+            AnalyticsError::OutOfRange(p) => {
+                write!(f, "The point {:?} is not normalized to [0, 1]", p)
+            }
+            AnalyticsError::Io(e) => write!(f, "Failed to read zones: {}", e),
+            AnalyticsError::Json(e) => write!(f, "Failed to parse zones: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AnalyticsError {}
+
+/// Load the zones from a json file, e.g. the `zones_path` of the pipeline config
+pub fn load_zones(path: &Path) -> Result<Vec<Zone>, AnalyticsError> {
+    let data = std::fs::read_to_string(path).map_err(AnalyticsError::Io)?;
+    serde_json::from_str(&data).map_err(AnalyticsError::Json)
+}
+
+/// The last known position of a track relative to a zone
+struct TrackPosition {
+    label: String,
+    point: [f32; 2],
+    inside: bool,
+}
+
+/// A zone with its counters and the positions of the tracks of its channel
+struct ZoneState {
+    zone: Zone,
+    counters: ZoneCounters,
+    tracks: HashMap<u64, TrackPosition>,
+}
+
+impl ZoneState {
+    fn new(zone: Zone) -> Result<Self, AnalyticsError> {
+        let points = match &zone.shape {
+            ZoneShape::Polygon { points } => {
+                if points.len() < 3 {
+                    return Err(AnalyticsError::TooFewPoints(points.len()));
+                }
+                points.clone()
+            }
+            ZoneShape::Line { from, to } => {
+                if from == to {
+                    return Err(AnalyticsError::EmptyLine);
+                }
+                vec![*from, *to]
+            }
+        };
+        if let Some(point) = points
+            .into_iter()
+            .find(|p| !p.iter().all(|v| (0.0..=1.0).contains(v)))
+        {
+            return Err(AnalyticsError::OutOfRange(point));
+        }
+
+        Ok(Self {
+            zone,
+            counters: ZoneCounters::default(),
+            tracks: HashMap::new(),
+        })
+    }
+
+    fn analytics(&self) -> ZoneAnalytics {
+        ZoneAnalytics {
+            zone: self.zone.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+
+    /// Update the positions of the tracks and return the events they caused
+    fn update(&mut self, msg: &TracksMsg, wall_time_ns: u64) -> Vec<ZoneEvent> {
+        let mut events = Vec::new();
+        let event = |zone: &Zone, track_id, label: &str, kind, direction| ZoneEvent {
+            zone_id: zone.id.clone(),
+            channel_id: zone.channel_id,
+            track_id,
+            label: label.to_string(),
+            kind,
+            direction,
+            stamp_ns: msg.stamp_ns,
+            wall_time_ns,
+        };
+
+        let mut seen = HashSet::new();
+        for track in &msg.tracks {
+            if !self.zone.labels.is_empty() && !self.zone.labels.contains(&track.label) {
+                continue;
+            }
+            seen.insert(track.track_id);
+
+            // the lost tracks keep their last position until they are detected again
+            if track.status != TrackStatus::Confirmed {
+                continue;
+            }
+
+            let point = anchor(&track.bbox);
+            let previous = self.tracks.get(&track.track_id);
+            let was_inside = previous.is_some_and(|p| p.inside);
+
+            let inside = match &self.zone.shape {
+                ZoneShape::Polygon { points } => {
+                    let inside = point_in_polygon(points, point);
+                    if inside && !was_inside {
+                        self.counters.entered += 1;
+                        self.counters.inside += 1;
+                        events.push(event(
+                            &self.zone,
+                            track.track_id,
+                            &track.label,
+                            ZoneEventKind::Enter,
+                            None,
+                        ));
+                    } else if !inside && was_inside {
+                        self.counters.exited += 1;
+                        self.counters.inside = self.counters.inside.saturating_sub(1);
+                        events.push(event(
+                            &self.zone,
+                            track.track_id,
+                            &track.label,
+                            ZoneEventKind::Exit,
+                            None,
+                        ));
+                    }
+                    inside
+                }
+                ZoneShape::Line { from, to } => {
+                    let direction =
+                        previous.and_then(|p| line_crossing(*from, *to, p.point, point));
+                    if let Some(direction) = direction {
+                        match direction {
+                            CrossDirection::Forward => self.counters.forward += 1,
+                            CrossDirection::Backward => self.counters.backward += 1,
+                        }
+                        events.push(event(
+                            &self.zone,
+                            track.track_id,
+                            &track.label,
+                            ZoneEventKind::Cross,
+                            Some(direction),
+                        ));
+                    }
+                    false
+                }
+            };
+
+            self.tracks.insert(
+                track.track_id,
+                TrackPosition {
+                    label: track.label.clone(),
+                    point,
+                    inside,
+                },
+            );
+        }
+
+        // the tracks removed by the tracker leave the polygons they were in
+        let mut removed = Vec::new();
+        self.tracks.retain(|track_id, position| {
+            if seen.contains(track_id) {
+                return true;
+            }
+            if position.inside {
+                removed.push((*track_id, position.label.clone()));
+            }
+            false
+        });
+        for (track_id, label) in removed {
+            self.counters.exited += 1;
+            self.counters.inside = self.counters.inside.saturating_sub(1);
+            events.push(event(
+                &self.zone,
+                track_id,
+                &label,
+                ZoneEventKind::Exit,
+                None,
+            ));
+        }
+
+        events
+    }
+}
+
+/// The point of a box that is tested against the zones, the middle of its bottom edge
+/// which is where the objects touch the ground
+fn anchor(bbox: &[f32; 4]) -> [f32; 2] {
+    [(bbox[0] + bbox[2]) / 2.0, bbox[3]]
+}
+
+/// The side of a point relative to a line, positive on its right in image coordinates
+fn side(from: [f32; 2], to: [f32; 2], point: [f32; 2]) -> f32 {
+    (to[0] - from[0]) * (point[1] - from[1]) - (to[1] - from[1]) * (point[0] - from[0])
+}
+
+/// Check if a point is inside a polygon with the even-odd rule
+fn point_in_polygon(points: &[[f32; 2]], point: [f32; 2]) -> bool {
+    let [x, y] = point;
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let [xi, yi] = points[i];
+        let [xj, yj] = points[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// The direction the move from `start` to `end` crosses the line segment in, if it does
+fn line_crossing(
+    from: [f32; 2],
+    to: [f32; 2],
+    start: [f32; 2],
+    end: [f32; 2],
+) -> Option<CrossDirection> {
+    let (start_side, end_side) = (side(from, to, start), side(from, to, end));
+    if (start_side < 0.0) == (end_side < 0.0) {
+        return None;
+    }
+    // the move must also pass between the two ends of the line
+    if side(start, end, from) * side(start, end, to) > 0.0 {
+        return None;
+    }
+
+    Some(if start_side < 0.0 {
+        CrossDirection::Forward
+    } else {
+        CrossDirection::Backward
+    })
+}
+
+/// Global store of the zones, their counters and the events of the tracked objects
+#[derive(Clone, Default)]
+pub struct AnalyticsStore {
+    // the zones indexed by their id
+    zones: Arc<Mutex<BTreeMap<String, ZoneState>>>,
+    // the latest events of all the zones
+    latest: Arc<Mutex<VecDeque<ZoneEvent>>>,
+    /// the zone events broadcasted to the event stream
+    pub events: BroadcastSender<ZoneEvent>,
+}
+
+impl AnalyticsStore {
+    /// Add a zone or replace the zone with the same id, its counters start from zero
+    pub fn add_zone(&self, zone: Zone) -> Result<(), AnalyticsError> {
+        let state = ZoneState::new(zone)?;
+        self.zones
+            .lock()
+            .unwrap()
+            .insert(state.zone.id.clone(), state);
+        Ok(())
+    }
+
+    /// Add a zone unless a zone with the same id exists, which keeps its counters
+    ///
+    /// # Returns
+    ///
+    /// False if the existing zone was kept
+    pub fn add_zone_if_absent(&self, zone: Zone) -> Result<bool, AnalyticsError> {
+        let state = ZoneState::new(zone)?;
+        match self.zones.lock().unwrap().entry(state.zone.id.clone()) {
+            Entry::Occupied(_) => Ok(false),
+            Entry::Vacant(entry) => {
+                entry.insert(state);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Remove a zone, returns false if the zone does not exist
+    pub fn remove_zone(&self, zone_id: &str) -> bool {
+        self.zones.lock().unwrap().remove(zone_id).is_some()
+    }
+
+    /// List the zones with their counters
+    pub fn zones(&self, query: &AnalyticsListQuery) -> Vec<ZoneAnalytics> {
+        self.zones
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|state| {
+                query.zone_id.as_ref().is_none_or(|id| &state.zone.id == id)
+                    && query
+                        .channel_id
+                        .is_none_or(|id| state.zone.channel_id == id)
+            })
+            .map(ZoneState::analytics)
+            .collect()
+    }
+
+    /// Reset the counters of the zones, the objects inside the polygons stay counted
+    pub fn reset_counters(&self, query: &AnalyticsListQuery) {
+        for state in self.zones.lock().unwrap().values_mut().filter(|state| {
+            query.zone_id.as_ref().is_none_or(|id| &state.zone.id == id)
+                && query
+                    .channel_id
+                    .is_none_or(|id| state.zone.channel_id == id)
+        }) {
+            state.counters = ZoneCounters {
+                inside: state.counters.inside,
+                ..Default::default()
+            };
+        }
+    }
+
+    /// List the latest events, the most recent first
+    pub fn latest_events(&self, query: &AnalyticsListQuery) -> Vec<ZoneEvent> {
+        self.latest
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|event| {
+                query.zone_id.as_ref().is_none_or(|id| &event.zone_id == id)
+                    && query.channel_id.is_none_or(|id| event.channel_id == id)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Update the zones of the channel with its new tracks
+    fn update(&self, msg: &TracksMsg, wall_time_ns: u64) -> Vec<ZoneEvent> {
+        self.zones
+            .lock()
+            .unwrap()
+            .values_mut()
+            .filter(|state| state.zone.channel_id == msg.channel_id)
+            .flat_map(|state| state.update(msg, wall_time_ns))
+            .collect()
+    }
+
+    /// Keep the event and send it to the event stream
+    fn publish(&self, event: ZoneEvent) {
+        let mut latest = self.latest.lock().unwrap();
+        latest.push_back(event.clone());
+        while latest.len() > MAX_EVENTS {
+            latest.pop_front();
+        }
+        drop(latest);
+
+        let _ = self.events.tx.send(event);
+    }
+}
+
+/// Update the zones with the new tracks of a channel and emit the events they caused
+pub fn update_zones(store: &ResultStore, msg: &TracksMsg) {
+    // NOTE: the stamp of the tracks is the robot clock, the events also get the wall time
+    let wall_time_ns = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+
+    for event in store.analytics.update(msg, wall_time_ns) {
+        log::debug!(
+            "Zone {} {:?} by track {} ({}) on channel {}",
+            event.zone_id,
+            event.kind,
+            event.track_id,
+            event.label,
+            event.channel_id
+        );
+
+        store.webhooks.notify(WebhookEvent::Zone(event.clone()));
+        store.analytics.publish(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cu29::msgs::Track;
+
+    const SQUARE: [[f32; 2]; 4] = [[0.2, 0.2], [0.6, 0.2], [0.6, 0.6], [0.2, 0.6]];
+
+    fn zone(shape: ZoneShape) -> ZoneState {
+        ZoneState::new(Zone {
+            id: "zone".to_string(),
+            channel_id: 0,
+            shape,
+            labels: vec!["person".to_string()],
+        })
+        .unwrap()
+    }
+
+    /// A track whose anchor, the middle of the bottom edge of its box, is at `point`
+    fn track(track_id: u64, point: [f32; 2], status: TrackStatus) -> Track {
+        Track {
+            track_id,
+            label: "person".to_string(),
+            bbox: [point[0] - 0.05, point[1] - 0.2, point[0] + 0.05, point[1]],
+            status,
+            ..Default::default()
+        }
+    }
+
+    fn tracks(stamp_ns: u64, tracks: Vec<Track>) -> TracksMsg {
+        TracksMsg {
+            stamp_ns,
+            channel_id: 0,
+            tracks,
+        }
+    }
+
+    #[test]
+    fn point_in_polygon_of_a_square() {
+        assert!(point_in_polygon(&SQUARE, [0.4, 0.4]));
+        assert!(!point_in_polygon(&SQUARE, [0.1, 0.4]));
+        assert!(!point_in_polygon(&SQUARE, [0.4, 0.7]));
+    }
+
+    #[test]
+    fn point_in_polygon_of_a_concave_polygon() {
+        // a U shape open at the top
+        let points = [
+            [0.0, 0.0],
+            [0.3, 0.0],
+            [0.3, 0.6],
+            [0.6, 0.6],
+            [0.6, 0.0],
+            [0.9, 0.0],
+            [0.9, 0.9],
+            [0.0, 0.9],
+        ];
+        assert!(point_in_polygon(&points, [0.1, 0.3]));
+        assert!(point_in_polygon(&points, [0.8, 0.3]));
+        assert!(point_in_polygon(&points, [0.45, 0.8]));
+        assert!(!point_in_polygon(&points, [0.45, 0.3]));
+    }
+
+    #[test]
+    fn line_crossing_direction() {
+        // looking right along the line, its right side is the bottom of the image
+        let (from, to) = ([0.0, 0.5], [1.0, 0.5]);
+
+        assert_eq!(
+            line_crossing(from, to, [0.5, 0.4], [0.5, 0.6]),
+            Some(CrossDirection::Forward)
+        );
+        assert_eq!(
+            line_crossing(from, to, [0.5, 0.6], [0.5, 0.4]),
+            Some(CrossDirection::Backward)
+        );
+        assert_eq!(line_crossing(from, to, [0.5, 0.4], [0.6, 0.45]), None);
+    }
+
+    #[test]
+    fn line_crossing_outside_of_the_segment() {
+        let (from, to) = ([0.2, 0.5], [0.6, 0.5]);
+        assert_eq!(line_crossing(from, to, [0.8, 0.4], [0.8, 0.6]), None);
+        assert_eq!(
+            line_crossing(from, to, [0.4, 0.4], [0.4, 0.6]),
+            Some(CrossDirection::Forward)
+        );
+    }
+
+    #[test]
+    fn line_crossing_through_a_point_on_the_line_is_counted_once() {
+        // the points on the line count as its right side
+        let (from, to) = ([0.0, 0.5], [1.0, 0.5]);
+        let (above, on, below) = ([0.5, 0.4], [0.5, 0.5], [0.5, 0.6]);
+
+        assert_eq!(
+            line_crossing(from, to, above, on),
+            Some(CrossDirection::Forward)
+        );
+        assert_eq!(line_crossing(from, to, on, below), None);
+
+        assert_eq!(line_crossing(from, to, below, on), None);
+        assert_eq!(
+            line_crossing(from, to, on, above),
+            Some(CrossDirection::Backward)
+        );
+    }
+
+    #[test]
+    fn polygon_counts_the_entries_and_the_exits() {
+        let mut state = zone(ZoneShape::Polygon {
+            points: SQUARE.to_vec(),
+        });
+
+        let outside = [0.4, 0.8];
+        let inside = [0.4, 0.4];
+        assert!(state
+            .update(
+                &tracks(0, vec![track(1, outside, TrackStatus::Confirmed)]),
+                10
+            )
+            .is_empty());
+
+        let events = state.update(
+            &tracks(1, vec![track(1, inside, TrackStatus::Confirmed)]),
+            11,
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, ZoneEventKind::Enter);
+        assert_eq!(events[0].stamp_ns, 1);
+        assert_eq!(events[0].wall_time_ns, 11);
+        assert_eq!(state.counters.inside, 1);
+
+        let events = state.update(
+            &tracks(2, vec![track(1, outside, TrackStatus::Confirmed)]),
+            12,
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, ZoneEventKind::Exit);
+        assert_eq!(state.counters.inside, 0);
+        assert_eq!(state.counters.entered, 1);
+        assert_eq!(state.counters.exited, 1);
+    }
+
+    #[test]
+    fn removed_tracks_exit_the_polygon() {
+        let mut state = zone(ZoneShape::Polygon {
+            points: SQUARE.to_vec(),
+        });
+        let inside = [0.4, 0.4];
+
+        state.update(
+            &tracks(0, vec![track(1, inside, TrackStatus::Confirmed)]),
+            10,
+        );
+
+        // the lost track stays inside at its last position
+        assert!(state
+            .update(
+                &tracks(1, vec![track(1, [0.9, 0.9], TrackStatus::Lost)]),
+                11
+            )
+            .is_empty());
+        assert_eq!(state.counters.inside, 1);
+
+        // the tracker removed the track
+        let events = state.update(&tracks(2, vec![]), 12);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, ZoneEventKind::Exit);
+        assert_eq!(events[0].track_id, 1);
+        assert_eq!(events[0].label, "person");
+        assert_eq!(state.counters.inside, 0);
+        assert_eq!(state.counters.exited, 1);
+
+        assert!(state.update(&tracks(3, vec![]), 13).is_empty());
+    }
+
+    #[test]
+    fn line_counts_the_crossings_of_the_tracks() {
+        let mut state = zone(ZoneShape::Line {
+            from: [0.0, 0.5],
+            to: [1.0, 0.5],
+        });
+
+        let mut other = track(2, [0.5, 0.4], TrackStatus::Confirmed);
+        other.label = "car".to_string();
+        state.update(
+            &tracks(
+                0,
+                vec![track(1, [0.5, 0.4], TrackStatus::Confirmed), other.clone()],
+            ),
+            10,
+        );
+
+        // only the labels of the zone are counted
+        other.bbox = track(2, [0.5, 0.6], TrackStatus::Confirmed).bbox;
+        let events = state.update(
+            &tracks(1, vec![track(1, [0.5, 0.6], TrackStatus::Confirmed), other]),
+            11,
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].track_id, 1);
+        assert_eq!(events[0].kind, ZoneEventKind::Cross);
+        assert_eq!(events[0].direction, Some(CrossDirection::Forward));
+        assert_eq!(state.counters.forward, 1);
+        assert_eq!(state.counters.backward, 0);
+    }
+
+    #[test]
+    fn invalid_zones_are_rejected() {
+        let zone = |shape| Zone {
+            id: "zone".to_string(),
+            channel_id: 0,
+            shape,
+            labels: Vec::new(),
+        };
+
+        assert!(matches!(
+            ZoneState::new(zone(ZoneShape::Polygon {
+                points: SQUARE[..2].to_vec()
+            })),
+            Err(AnalyticsError::TooFewPoints(2))
+        ));
+        assert!(matches!(
+            ZoneState::new(zone(ZoneShape::Line {
+                from: [0.5, 0.5],
+                to: [0.5, 0.5]
+            })),
+            Err(AnalyticsError::EmptyLine)
+        ));
+        assert!(matches!(
+            ZoneState::new(zone(ZoneShape::Line {
+                from: [0.5, 0.5],
+                to: [1.5, 0.5]
+            })),
+            Err(AnalyticsError::OutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn zones_added_if_absent_keep_their_counters() {
+        let store = AnalyticsStore::default();
+        let square = Zone {
+            id: "zone".to_string(),
+            channel_id: 0,
+            shape: ZoneShape::Polygon {
+                points: SQUARE.to_vec(),
+            },
+            labels: vec!["person".to_string()],
+        };
+        assert!(store.add_zone_if_absent(square.clone()).unwrap());
+
+        store.update(
+            &tracks(0, vec![track(1, [0.4, 0.8], TrackStatus::Confirmed)]),
+            10,
+        );
+        store.update(
+            &tracks(1, vec![track(1, [0.4, 0.4], TrackStatus::Confirmed)]),
+            11,
+        );
+        let entered = |store: &AnalyticsStore| store.zones(&Default::default())[0].counters.entered;
+        assert_eq!(entered(&store), 1);
+
+        // the zone of the pipeline config is added again when the pipeline restarts
+        assert!(!store.add_zone_if_absent(square.clone()).unwrap());
+        assert_eq!(entered(&store), 1);
+
+        // the api replaces the zone and restarts its counters
+        store.add_zone(square).unwrap();
+        assert_eq!(entered(&store), 0);
+    }
+}
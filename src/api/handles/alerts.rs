@@ -0,0 +1,113 @@
+use crate::{
+    api::models::alerts::{AlertFrameQuery, AlertListQuery, AlertRule, AlertRuleQuery},
+    pipeline::ResultStore,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Json,
+};
+use serde_json::json;
+use std::convert::Infallible;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+/// Add an alert rule or replace the rule with the same id
+pub async fn post_alert_rule(
+    State(store): State<ResultStore>,
+    Json(rule): Json<AlertRule>,
+) -> impl IntoResponse {
+    log::debug!("Request to add alert rule: {:?}", rule);
+
+    let rule_id = rule.id.clone();
+    if let Err(e) = store.alerts.add_rule(rule) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("Failed to add alert rule {}: {}", rule_id, e)
+            })),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "rule_id": rule_id
+        })),
+    )
+}
+
+/// List all the alert rules
+pub async fn get_alert_rules(State(store): State<ResultStore>) -> impl IntoResponse {
+    log::debug!("Request to list alert rules");
+    Json(store.alerts.rules())
+}
+
+/// Remove an alert rule
+pub async fn delete_alert_rule(
+    Path(query): Path<AlertRuleQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to remove alert rule: {}", query.rule_id);
+
+    if !store.alerts.remove_rule(&query.rule_id) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": format!("Alert rule {} not found", query.rule_id)
+            })),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true
+        })),
+    )
+}
+
+/// List the latest alerts, the most recent first
+pub async fn get_alerts(
+    Query(query): Query<AlertListQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to list alerts: {:?}", query);
+    Json(store.alerts.alerts(&query))
+}
+
+/// Stream the alerts as server-sent events as they fire
+pub async fn get_alert_stream(
+    State(store): State<ResultStore>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    log::debug!("Request to stream alerts");
+
+    // NOTE: the alerts missed by a slow client are skipped
+    let stream = BroadcastStream::new(store.alerts.events.tx.subscribe()).filter_map(|alert| {
+        let alert = alert.ok()?;
+        Event::default()
+            .event("alert")
+            .json_data(alert)
+            .ok()
+            .map(Ok)
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Download the frame of the channel when the alert fired
+pub async fn get_alert_frame(
+    Path(query): Path<AlertFrameQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to download alert frame: {}", query.alert_id);
+
+    let Some(frame) = store.alerts.frame(&query.alert_id) else {
+        return (StatusCode::NOT_FOUND, "Alert frame not found").into_response();
+    };
+
+    ([(header::CONTENT_TYPE, "image/jpeg")], frame.data).into_response()
+}
@@ -0,0 +1,110 @@
+use crate::{
+    api::models::analytics::{AnalyticsListQuery, Zone, ZoneQuery},
+    pipeline::ResultStore,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Json,
+};
+use serde_json::json;
+use std::convert::Infallible;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+/// Get the zones with their counters
+pub async fn get_analytics(
+    Query(query): Query<AnalyticsListQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to get analytics: {:?}", query);
+    Json(store.analytics.zones(&query))
+}
+
+/// Add a zone or replace the zone with the same id
+pub async fn post_analytics_zone(
+    State(store): State<ResultStore>,
+    Json(zone): Json<Zone>,
+) -> impl IntoResponse {
+    log::debug!("Request to add analytics zone: {:?}", zone);
+
+    let zone_id = zone.id.clone();
+    if let Err(e) = store.analytics.add_zone(zone) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("Failed to add zone {}: {}", zone_id, e)
+            })),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "zone_id": zone_id
+        })),
+    )
+}
+
+/// Remove a zone
+pub async fn delete_analytics_zone(
+    Path(query): Path<ZoneQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to remove analytics zone: {}", query.zone_id);
+
+    if !store.analytics.remove_zone(&query.zone_id) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": format!("Zone {} not found", query.zone_id)
+            })),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true
+        })),
+    )
+}
+
+/// Reset the counters of the zones, e.g. at the opening of the store
+pub async fn post_analytics_reset(
+    Query(query): Query<AnalyticsListQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to reset analytics counters: {:?}", query);
+    store.analytics.reset_counters(&query);
+    Json(json!({
+        "success": true
+    }))
+}
+
+/// List the latest zone events, the most recent first
+pub async fn get_analytics_events(
+    Query(query): Query<AnalyticsListQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to list analytics events: {:?}", query);
+    Json(store.analytics.latest_events(&query))
+}
+
+/// Stream the zone events as server-sent events as they happen
+pub async fn get_analytics_stream(
+    State(store): State<ResultStore>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    log::debug!("Request to stream analytics events");
+
+    // NOTE: the events missed by a slow client are skipped
+    let stream = BroadcastStream::new(store.analytics.events.tx.subscribe()).filter_map(|event| {
+        let event = event.ok()?;
+        Event::default().event("zone").json_data(event).ok().map(Ok)
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -1,13 +1,23 @@
 use crate::{
-    api::models::inference::{InferenceResponse, InferenceResultQuery, InferenceSettingsQuery},
+    api::models::inference::{
+        DetectionsResponse, InferenceHistoryQuery, InferenceModelRequest, InferenceQuery,
+        InferenceQueryRequest, InferenceResponse, InferenceResultQuery, InferenceSettingsQuery,
+        ModelStatus, TracksResponse,
+    },
+    inference::decode_image,
     pipeline::ResultStore,
 };
 use axum::{
-    extract::{Path, State},
+    extract::{Multipart, Path, Query, State},
+    http::StatusCode,
     response::IntoResponse,
     Json,
 };
 use serde_json::json;
+use std::time::Duration;
+
+/// The time to wait for the response of an on-demand query if not provided
+const DEFAULT_QUERY_TIMEOUT_SECS: u64 = 30;
 
 pub async fn get_inference_result(
     Path(query): Path<InferenceResultQuery>,
@@ -27,18 +37,316 @@ pub async fn get_inference_result(
     Json(InferenceResponse::Success(result))
 }
 
+/// Get the next detections parsed from the responses of a channel
+pub async fn get_inference_detections(
+    Path(query): Path<InferenceResultQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to get inference detections: {}", query.channel_id);
+    let Ok(result) = store.detections[query.channel_id as usize]
+        .tx
+        .subscribe()
+        .recv()
+        .await
+    else {
+        return Json(DetectionsResponse::Error {
+            error: "Failed to get detections: `just start-pipeline inference`".to_string(),
+        });
+    };
+    Json(DetectionsResponse::Success(result))
+}
+
+/// Get the next tracks of the objects detected on a channel
+pub async fn get_inference_tracks(
+    Path(query): Path<InferenceResultQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to get inference tracks: {}", query.channel_id);
+    let Ok(result) = store.tracks[query.channel_id as usize]
+        .tx
+        .subscribe()
+        .recv()
+        .await
+    else {
+        return Json(TracksResponse::Error {
+            error: "Failed to get tracks: `just start-pipeline inference`".to_string(),
+        });
+    };
+    Json(TracksResponse::Success(result))
+}
+
 pub async fn post_inference_settings(
     State(store): State<ResultStore>,
     Json(query): Json<InferenceSettingsQuery>,
 ) -> impl IntoResponse {
-    log::debug!("Request to post inference settings: {}", query.prompt);
-    let Ok(_) = store.inference_settings.tx.send(query.prompt) else {
+    log::debug!("Request to post inference settings: {:?}", query);
+    let Ok(mut settings) = store.inference_settings.lock() else {
         return Json(json!({
-            "error": "Failed to send inference settings"
+            "error": "Failed to update inference settings"
         }));
     };
 
+    if let Some(prompt) = query.prompt {
+        match query.channel_id {
+            Some(channel_id) => {
+                settings.channel_prompts.insert(channel_id, prompt);
+            }
+            None => settings.default_prompt = prompt,
+        }
+    }
+
+    if let Some(params) = query.params {
+        settings.params = Some(params);
+    }
+
     Json(json!({
         "success": true
     }))
 }
+
+pub async fn get_inference_settings(State(store): State<ResultStore>) -> impl IntoResponse {
+    log::debug!("Request to get inference settings");
+    let settings = store.inference_settings.lock().unwrap().clone();
+    Json(settings)
+}
+
+/// Search the stored inference results, the most recent first
+pub async fn get_inference_history(
+    Query(query): Query<InferenceHistoryQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to search inference history: {:?}", query);
+
+    // the database access is blocking, run it in a separate thread
+    let result = tokio::task::spawn_blocking(move || store.inference_history.query(&query)).await;
+
+    match result {
+        Ok(Ok(entries)) => (StatusCode::OK, Json(json!(entries))),
+        Ok(Err(e)) => {
+            log::error!("Failed to search inference history: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "error": format!("Failed to search inference history: {}", e)
+                })),
+            )
+        }
+        Err(e) => {
+            log::error!("Failed to join inference history search: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "error": "Failed to search inference history"
+                })),
+            )
+        }
+    }
+}
+
+/// Get the scheduling counters of the inference tasks per channel
+pub async fn get_inference_stats(State(store): State<ResultStore>) -> impl IntoResponse {
+    log::debug!("Request to get inference stats");
+    let stats = store.inference_stats.lock().unwrap().clone();
+    Json(stats)
+}
+
+/// Get the loading status of the models of the inference tasks
+pub async fn get_inference_models(State(store): State<ResultStore>) -> impl IntoResponse {
+    log::debug!("Request to get inference models");
+    let models = store
+        .inference_models
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    Json(models)
+}
+
+/// Swap the model of the running inference tasks
+///
+/// The new model is loaded in the background next to the current one, which keeps running
+/// until the new one is ready. The progress is reported by `get_inference_models`.
+pub async fn post_inference_model(
+    State(store): State<ResultStore>,
+    Json(request): Json<InferenceModelRequest>,
+) -> impl IntoResponse {
+    log::debug!("Request to swap inference model: {:?}", request);
+
+    let is_loading = store
+        .inference_models
+        .lock()
+        .unwrap()
+        .values()
+        .any(|model| matches!(model.status, ModelStatus::Loading));
+    if is_loading {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": "A model is already loading, check `/api/v0/inference/models`"
+            })),
+        );
+    }
+
+    let backend = request.backend.clone();
+    let Ok(num_tasks) = store.inference_model_swaps.tx.send(request) else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "error": "No inference task running, try `just start-pipeline inference`"
+            })),
+        );
+    };
+
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "message": format!("Loading backend {} in {} inference tasks", backend, num_tasks)
+        })),
+    )
+}
+
+/// Run the model on an uploaded image or the latest frame of a channel and wait for the response
+///
+/// The multipart form takes an optional `image` file (jpeg or png), a `prompt`,
+/// a `channel_id` to use its latest frame if no image is uploaded and a `timeout_secs`.
+pub async fn post_inference_query(
+    State(store): State<ResultStore>,
+    multipart: Multipart,
+) -> impl IntoResponse {
+    let (request, data) = match parse_query_form(multipart).await {
+        Ok(form) => form,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": format!("Failed to parse query: {}", e)
+                })),
+            );
+        }
+    };
+
+    log::debug!("Request to query inference: {:?}", request);
+
+    // the queries are not picked until a model is loaded
+    let is_ready = store
+        .inference_models
+        .lock()
+        .unwrap()
+        .values()
+        .any(|model| matches!(model.status, ModelStatus::Ready));
+    if !is_ready {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "error": "No inference model ready, check `/api/v0/inference/models`"
+            })),
+        );
+    }
+
+    let timeout = Duration::from_secs(request.timeout_secs.unwrap_or(DEFAULT_QUERY_TIMEOUT_SECS));
+
+    // the uploaded image or the latest frame of the channel
+    let uploaded = data.is_some();
+    let data = match (data, request.channel_id) {
+        (Some(data), _) => data,
+        (None, Some(channel_id)) => {
+            let Some(image) = store
+                .latest_images
+                .lock()
+                .unwrap()
+                .get(&channel_id)
+                .map(|image| image.data.clone())
+            else {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({
+                        "error": format!("No frame received from channel {}", channel_id)
+                    })),
+                );
+            };
+            image
+        }
+        (None, None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "Either an image or a channel_id must be provided"
+                })),
+            );
+        }
+    };
+
+    let image = match decode_image(&data) {
+        Ok(image) => image,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": format!("Failed to decode image: {}", e)
+                })),
+            );
+        }
+    };
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+
+    let Ok(_) = store.inference_queries.tx.send(InferenceQuery {
+        image,
+        channel_id: request.channel_id,
+        uploaded,
+        prompt: request.prompt,
+        reply: reply_tx,
+    }) else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "error": "Failed to send inference query"
+            })),
+        );
+    };
+
+    match tokio::time::timeout(timeout, reply_rx).await {
+        Ok(Ok(Ok(response))) => (StatusCode::OK, Json(json!(response))),
+        Ok(Ok(Err(e))) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "error": format!("Failed to run inference: {}", e)
+            })),
+        ),
+        Ok(Err(_)) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "error": "The inference task stopped before answering the query"
+            })),
+        ),
+        Err(_) => (
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(json!({
+                "error": "Inference query timed out, is the pipeline running? `just start-pipeline inference`"
+            })),
+        ),
+    }
+}
+
+/// Read the fields of the query form and the bytes of the uploaded image
+async fn parse_query_form(
+    mut multipart: Multipart,
+) -> Result<(InferenceQueryRequest, Option<Vec<u8>>), Box<dyn std::error::Error + Send + Sync>> {
+    let mut request = InferenceQueryRequest::default();
+    let mut data = None;
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("image") => data = Some(field.bytes().await?.to_vec()),
+            Some("prompt") => request.prompt = Some(field.text().await?),
+            Some("channel_id") => request.channel_id = Some(field.text().await?.trim().parse()?),
+            Some("timeout_secs") => {
+                request.timeout_secs = Some(field.text().await?.trim().parse()?)
+            }
+            name => log::warn!("Ignoring unknown query field: {:?}", name),
+        }
+    }
+
+    Ok((request, data))
+}
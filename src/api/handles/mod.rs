@@ -1,5 +1,9 @@
+pub mod alerts;
+pub mod analytics;
 pub mod inference;
 pub mod pipeline;
 pub mod recording;
+pub mod search;
 pub mod stats;
 pub mod streaming;
+pub mod webhooks;
@@ -1,7 +1,12 @@
 use crate::{
-    api::models::pipeline::{PipelineStartRequest, PipelineStopRequest},
+    api::models::{
+        pipeline::{PipelineStartRequest, PipelineStopRequest},
+        webhooks::WebhookEvent,
+    },
     cu29,
-    pipeline::{self, PipelineHandle, PipelineInfo, PipelineStatus, PipelineStore},
+    pipeline::{
+        self, PipelineHandle, PipelineInfo, PipelineStatus, PipelineStore, SERVER_GLOBAL_STATE,
+    },
 };
 use axum::{
     extract::State,
@@ -75,6 +80,14 @@ pub async fn start_pipeline(
 
     log::debug!("Pipeline {} started", pipeline_name);
 
+    SERVER_GLOBAL_STATE
+        .result_store
+        .webhooks
+        .notify(WebhookEvent::PipelineStatus {
+            pipeline_id: pipeline_name.clone(),
+            status: PipelineStatus::Running,
+        });
+
     (
         StatusCode::OK,
         Json(json!({
@@ -101,6 +114,14 @@ pub async fn stop_pipeline(
 
     log::debug!("Pipeline {} stopped", request.name);
 
+    SERVER_GLOBAL_STATE
+        .result_store
+        .webhooks
+        .notify(WebhookEvent::PipelineStatus {
+            pipeline_id: request.name.clone(),
+            status: PipelineStatus::Stopped,
+        });
+
     (
         StatusCode::OK,
         Json(json!({ "message": format!("Pipeline {} stopped", request.name) })),
@@ -1,19 +1,454 @@
-use crate::{api::models::recording::RecordingQuery, pipeline::ResultStore};
-use axum::{extract::State, response::IntoResponse, Json};
+use crate::{
+    api::models::{
+        recording::{
+            AnnotationRequest, AnnotationSearchQuery, AnnotationSearchResult, ClipFormat, ClipInfo,
+            ClipQuery, ClipRequest, RecordingAnnotation, RecordingCommand, RecordingSessionConfig,
+            RecordingSessionStatus, RecordingStartRequest, RecordingStopRequest,
+        },
+        webhooks::WebhookEvent,
+    },
+    pipeline::ResultStore,
+    recording::{self, RecordingResult, TimeRange},
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
 use serde_json::json;
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-pub async fn post_recording_command(
+/// The counter making the clip ids unique within the same millisecond
+static NEXT_CLIP: AtomicU64 = AtomicU64::new(0);
+
+/// Start a new recording session and return its id
+pub async fn post_recording_start(
+    State(store): State<ResultStore>,
+    Json(request): Json<RecordingStartRequest>,
+) -> impl IntoResponse {
+    log::debug!("Request to start recording: {:?}", request);
+
+    // NOTE: the session id is the current timestamp in milliseconds
+    // TODO: explore using a UUID
+    let session_id = format!(
+        "{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    );
+
+    let config = RecordingSessionConfig {
+        session_id: session_id.clone(),
+        name: request.name,
+        channels: request.channels,
+        tags: request.tags,
+        duration_secs: request.duration_secs,
+    };
+
+    if let Err(e) = store.recording.start_session(config.clone()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("Failed to send recording: {}", e)
+            })),
+        );
+    }
+
+    store
+        .webhooks
+        .notify(WebhookEvent::RecordingStarted(config));
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "session_id": session_id
+        })),
+    )
+}
+
+/// Stop a recording session or all the sessions if no id is given
+pub async fn post_recording_stop(
+    State(store): State<ResultStore>,
+    Json(request): Json<RecordingStopRequest>,
+) -> impl IntoResponse {
+    log::debug!("Request to stop recording: {:?}", request.session_id);
+
+    if let Some(session_id) = &request.session_id {
+        if !store
+            .recording
+            .sessions
+            .lock()
+            .unwrap()
+            .contains_key(session_id)
+        {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "error": format!("Recording session {} not found", session_id)
+                })),
+            );
+        }
+    }
+
+    let Ok(_) = store.recording.commands.tx.send(RecordingCommand::Stop {
+        session_id: request.session_id.clone(),
+    }) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "Failed to send recording: no recorder running"
+            })),
+        );
+    };
+
+    // the recorders report the session as stopped once the recordings are finalized
+    let mut sessions = store.recording.sessions.lock().unwrap();
+    for (id, session) in sessions.iter_mut() {
+        let is_target = request.session_id.as_ref().is_none_or(|s| s == id);
+        if is_target && matches!(session.status, RecordingSessionStatus::Recording) {
+            session.status = RecordingSessionStatus::Finalizing;
+        }
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true
+        })),
+    )
+}
+
+/// List all the recording sessions
+pub async fn get_recording_sessions(State(store): State<ResultStore>) -> impl IntoResponse {
+    log::debug!("Request to list recording sessions");
+    let sessions = store
+        .recording
+        .sessions
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    Json(sessions)
+}
+
+/// Attach a timestamped annotation to the active recording sessions
+pub async fn post_recording_annotation(
+    State(store): State<ResultStore>,
+    Json(request): Json<AnnotationRequest>,
+) -> impl IntoResponse {
+    log::debug!("Request to annotate recording: {:?}", request);
+
+    let annotation = RecordingAnnotation {
+        stamp_ns: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64,
+        author: request.author,
+        label: request.label,
+        text: request.text,
+    };
+
+    // attach the annotation to the active sessions so that it can be searched
+    let mut sessions = store.recording.sessions.lock().unwrap();
+    let mut annotated = Vec::new();
+    for (id, session) in sessions.iter_mut() {
+        let is_target = request.session_id.as_ref().is_none_or(|s| s == id);
+        if is_target && matches!(session.status, RecordingSessionStatus::Recording) {
+            session.annotations.push(annotation.clone());
+            annotated.push(id.clone());
+        }
+    }
+    drop(sessions);
+
+    if annotated.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": "No active recording session to annotate"
+            })),
+        );
+    }
+
+    let Ok(_) = store
+        .recording
+        .commands
+        .tx
+        .send(RecordingCommand::Annotate {
+            session_id: request.session_id,
+            annotation,
+        })
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "Failed to send annotation: no recorder running"
+            })),
+        );
+    };
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "sessions": annotated
+        })),
+    )
+}
+
+/// Search the annotations of the current sessions and the recordings on disk
+pub async fn get_recording_annotations(
+    Query(query): Query<AnnotationSearchQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to search annotations: {:?}", query);
+
+    let mut results = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (id, session) in store.recording.sessions.lock().unwrap().iter() {
+        seen.insert(id.clone());
+        results.extend(
+            session
+                .annotations
+                .iter()
+                .map(|annotation| AnnotationSearchResult {
+                    session_id: id.clone(),
+                    annotation: annotation.clone(),
+                }),
+        );
+    }
+
+    // the recordings from previous runs of the server
+    let dirs = store
+        .recording
+        .directories
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    for dir in dirs {
+        let Ok(entries) = recording::list_recordings(&dir) else {
+            continue;
+        };
+        for entry in entries {
+            if !seen.insert(entry.manifest.session_id.clone()) {
+                continue;
+            }
+            results.extend(entry.manifest.annotations.into_iter().map(|annotation| {
+                AnnotationSearchResult {
+                    session_id: entry.manifest.session_id.clone(),
+                    annotation,
+                }
+            }));
+        }
+    }
+
+    let q = query.q.map(|q| q.to_lowercase());
+    results.retain(|result| {
+        let annotation = &result.annotation;
+        query
+            .label
+            .as_ref()
+            .is_none_or(|label| &annotation.label == label)
+            && q.as_ref().is_none_or(|q| {
+                annotation.text.to_lowercase().contains(q)
+                    || annotation.label.to_lowercase().contains(q)
+            })
+    });
+    results.sort_by_key(|result| result.annotation.stamp_ns);
+
+    Json(results)
+}
+
+/// Cut the frames of a channel in a time range out of the recordings into a new clip
+pub async fn post_recording_clip(
+    State(store): State<ResultStore>,
+    Json(request): Json<ClipRequest>,
+) -> impl IntoResponse {
+    log::debug!("Request to extract clip: {:?}", request);
+
+    if request.start_ns >= request.end_ns {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "The start time must be before the end time"
+            })),
+        );
+    }
+
+    // NOTE: only the directories of the recorder tasks are read and written
+    let dirs = store
+        .recording
+        .directories
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // NOTE: the clip id is the current timestamp in milliseconds and a counter
+    let clip_id = format!(
+        "clip_{}_{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis(),
+        NEXT_CLIP.fetch_add(1, Ordering::Relaxed)
+    );
+
+    // reading and encoding the frames is blocking, run it in a separate thread
+    let result = tokio::task::spawn_blocking({
+        let clip_id = clip_id.clone();
+        move || extract_clip(&dirs, &clip_id, &request)
+    })
+    .await;
+
+    let (clip_path, segments, frames) = match result {
+        Ok(Ok(Some(clip))) => clip,
+        Ok(Ok(None)) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "error": "No recorded frames found for the channel in the time range"
+                })),
+            );
+        }
+        Ok(Err(e)) => {
+            log::error!("Failed to extract clip: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "error": format!("Failed to extract clip: {}", e)
+                })),
+            );
+        }
+        Err(e) => {
+            log::error!("Failed to join clip extraction: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "error": "Failed to extract clip"
+                })),
+            );
+        }
+    };
+
+    store
+        .recording
+        .clips
+        .lock()
+        .unwrap()
+        .insert(clip_id.clone(), clip_path);
+
+    (
+        StatusCode::OK,
+        Json(json!(ClipInfo {
+            download: format!("/api/v0/recording/clips/{}", clip_id),
+            clip_id,
+            segments,
+            frames,
+        })),
+    )
+}
+
+/// Download a clip previously extracted
+pub async fn get_recording_clip(
+    Path(query): Path<ClipQuery>,
     State(store): State<ResultStore>,
-    Json(query): Json<RecordingQuery>,
 ) -> impl IntoResponse {
-    log::debug!("Request to post recording command: {:?}", query.command);
-    let Ok(_) = store.recording.tx.send(query.command) else {
-        return Json(json!({
-            "error": "Failed to send recording"
-        }));
+    log::debug!("Request to download clip: {}", query.clip_id);
+
+    let Some(clip_path) = store
+        .recording
+        .clips
+        .lock()
+        .unwrap()
+        .get(&query.clip_id)
+        .cloned()
+    else {
+        return (StatusCode::NOT_FOUND, "Clip not found").into_response();
+    };
+
+    let Ok(data) = tokio::fs::read(&clip_path).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read clip").into_response();
+    };
+
+    let content_type = match clip_path.extension().and_then(|ext| ext.to_str()) {
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    };
+
+    let file_name = clip_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+/// Extract the clip and return its path, the source recordings and the number of frames
+fn extract_clip(
+    dirs: &[PathBuf],
+    clip_id: &str,
+    request: &ClipRequest,
+) -> RecordingResult<Option<(PathBuf, Vec<String>, usize)>> {
+    let range = TimeRange {
+        start_ns: Some(request.start_ns as i64),
+        end_ns: Some(request.end_ns as i64),
+    };
+
+    let recordings = recording::find_recordings(dirs, request.channel_id, range)?;
+
+    let mut images = Vec::new();
+    let mut segments = Vec::new();
+    for entry in &recordings {
+        let segment = recording::read_encoded_images(&entry.path, request.channel_id, range)?;
+        if !segment.is_empty() {
+            segments.push(entry.path.display().to_string());
+            images.extend(segment);
+        }
+    }
+
+    // NOTE: the clip is written next to the first recording
+    let Some(clips_dir) = recordings
+        .first()
+        .and_then(|entry| entry.path.parent())
+        .map(|dir| dir.join("clips"))
+    else {
+        return Ok(None);
     };
 
-    Json(json!({
-        "success": true
-    }))
+    if images.is_empty() {
+        return Ok(None);
+    }
+
+    images.sort_by_key(|image| image.stamp_ns);
+
+    std::fs::create_dir_all(&clips_dir)?;
+    let clip_path = clips_dir.join(format!("{}.{}", clip_id, request.format.extension()));
+
+    match request.format {
+        ClipFormat::Rrd => recording::export_rrd(&images, &clip_path)?,
+        // TODO: expose the frame rate in the request
+        ClipFormat::Mp4 => recording::export_mp4(&images, &clip_path, 30)?,
+    }
+
+    Ok(Some((clip_path, segments, images.len())))
 }
@@ -0,0 +1,205 @@
+use crate::{
+    api::models::{
+        recording::{ClipFormat, ClipRequest},
+        search::{
+            EmbeddingInput, EmbeddingQuery, SearchRecording, SearchRequest, SearchResponse,
+            SearchResult,
+        },
+    },
+    inference::decode_image,
+    pipeline::ResultStore,
+    recording::{find_recordings, TimeRange},
+};
+use axum::{
+    extract::{Multipart, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+use std::{sync::atomic::Ordering, time::Duration};
+
+/// The default time to wait for the query to be embedded
+const DEFAULT_SEARCH_TIMEOUT_SECS: u64 = 30;
+
+/// The time around a found frame covered by its clip request
+const CLIP_MARGIN_NS: u64 = 5_000_000_000;
+
+/// Find the sampled frames the most similar to a text or an uploaded image
+pub async fn post_search(
+    State(store): State<ResultStore>,
+    multipart: Multipart,
+) -> impl IntoResponse {
+    let (request, data) = match parse_search_form(multipart).await {
+        Ok(form) => form,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": format!("Failed to parse search: {}", e)
+                })),
+            );
+        }
+    };
+
+    log::debug!("Request to search frames: {:?}", request);
+
+    let input = match (data, &request.text) {
+        (Some(data), None) => match decode_image(&data) {
+            Ok(image) => EmbeddingInput::Image(image),
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "error": format!("Failed to decode image: {}", e)
+                    })),
+                );
+            }
+        },
+        (None, Some(text)) if !text.trim().is_empty() => EmbeddingInput::Text(text.clone()),
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "Either a text or an image must be provided"
+                })),
+            );
+        }
+    };
+
+    // the queries are only answered by the embedding tasks with a loaded model
+    if store.embedding_tasks.load(Ordering::Relaxed) == 0 {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "error": "No embedding task is running, try `just start-pipeline inference` with the embedder enabled"
+            })),
+        );
+    }
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+
+    let Ok(_) = store.embedding_queries.tx.send(EmbeddingQuery {
+        input,
+        reply: reply_tx,
+    }) else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "error": "Failed to send search query"
+            })),
+        );
+    };
+
+    // the query is embedded by the pipeline with the model of the indexed frames
+    let timeout = Duration::from_secs(request.timeout_secs.unwrap_or(DEFAULT_SEARCH_TIMEOUT_SECS));
+    let (model, vector) = match tokio::time::timeout(timeout, reply_rx).await {
+        Ok(Ok(Ok(embedding))) => embedding,
+        Ok(Ok(Err(e))) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "error": format!("Failed to embed query: {}", e)
+                })),
+            );
+        }
+        Ok(Err(_)) => {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({
+                    "error": "The embedding task stopped before answering the query"
+                })),
+            );
+        }
+        Err(_) => {
+            return (
+                StatusCode::GATEWAY_TIMEOUT,
+                Json(json!({
+                    "error": "Search query timed out, the embedding task is busy"
+                })),
+            );
+        }
+    };
+
+    let dirs = store
+        .recording
+        .directories
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let results = store
+        .embedding_index
+        .search(&vector, &model, &request)
+        .into_iter()
+        .map(|(score, frame)| {
+            let time_ns = frame.wall_time_ns as i64;
+            let range = TimeRange {
+                start_ns: Some(time_ns),
+                end_ns: Some(time_ns),
+            };
+            let recordings = find_recordings(&dirs, frame.channel_id, range)
+                .unwrap_or_else(|e| {
+                    log::warn!("Failed to find recordings: {}", e);
+                    Vec::new()
+                })
+                .into_iter()
+                .map(|entry| SearchRecording {
+                    offset_ms: frame
+                        .wall_time_ns
+                        .saturating_sub(entry.manifest.start_time_ns)
+                        / 1_000_000,
+                    session_id: entry.manifest.session_id,
+                    recording: entry.path.display().to_string(),
+                })
+                .collect();
+
+            SearchResult {
+                score,
+                frame_id: frame.id,
+                wall_time_ns: frame.wall_time_ns,
+                stamp_ns: frame.stamp_ns,
+                channel_id: frame.channel_id,
+                recordings,
+                clip: ClipRequest {
+                    channel_id: frame.channel_id,
+                    start_ns: frame.wall_time_ns.saturating_sub(CLIP_MARGIN_NS),
+                    end_ns: frame.wall_time_ns + CLIP_MARGIN_NS,
+                    format: ClipFormat::default(),
+                },
+            }
+        })
+        .collect();
+
+    (
+        StatusCode::OK,
+        Json(json!(SearchResponse { model, results })),
+    )
+}
+
+/// Read the fields of the search form and the bytes of the uploaded image
+async fn parse_search_form(
+    mut multipart: Multipart,
+) -> Result<(SearchRequest, Option<Vec<u8>>), Box<dyn std::error::Error + Send + Sync>> {
+    let mut request = SearchRequest::default();
+    let mut data = None;
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("image") => data = Some(field.bytes().await?.to_vec()),
+            Some("text") => request.text = Some(field.text().await?),
+            Some("channel_id") => request.channel_id = Some(field.text().await?.trim().parse()?),
+            Some("from") => request.from = Some(field.text().await?.trim().parse()?),
+            Some("to") => request.to = Some(field.text().await?.trim().parse()?),
+            Some("limit") => request.limit = Some(field.text().await?.trim().parse()?),
+            Some("timeout_secs") => {
+                request.timeout_secs = Some(field.text().await?.trim().parse()?)
+            }
+            name => log::warn!("Ignoring unknown search field: {:?}", name),
+        }
+    }
+
+    Ok((request, data))
+}
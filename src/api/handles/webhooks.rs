@@ -0,0 +1,68 @@
+use crate::{
+    api::models::webhooks::{WebhookConfig, WebhookQuery},
+    pipeline::ResultStore,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+
+/// Add a webhook or replace the webhook with the same id
+pub async fn post_webhook(
+    State(store): State<ResultStore>,
+    Json(hook): Json<WebhookConfig>,
+) -> impl IntoResponse {
+    log::debug!("Request to add webhook: {} -> {}", hook.id, hook.url);
+
+    if let Err(e) = reqwest::Url::parse(&hook.url) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("Invalid webhook url {}: {}", hook.url, e)
+            })),
+        );
+    }
+
+    let webhook_id = hook.id.clone();
+    store.webhooks.add_hook(hook);
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "webhook_id": webhook_id
+        })),
+    )
+}
+
+/// List all the webhooks, without their secrets
+pub async fn get_webhooks(State(store): State<ResultStore>) -> impl IntoResponse {
+    log::debug!("Request to list webhooks");
+    Json(store.webhooks.hooks())
+}
+
+/// Remove a webhook
+pub async fn delete_webhook(
+    Path(query): Path<WebhookQuery>,
+    State(store): State<ResultStore>,
+) -> impl IntoResponse {
+    log::debug!("Request to remove webhook: {}", query.webhook_id);
+
+    if !store.webhooks.remove_hook(&query.webhook_id) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": format!("Webhook {} not found", query.webhook_id)
+            })),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true
+        })),
+    )
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// A rule matched against the responses of the inference tasks
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AlertRule {
+    /// the id of the rule, a rule with the same id is replaced
+    pub id: String,
+    /// only match the responses of this channel, all the channels if not provided
+    #[serde(default)]
+    pub channel_id: Option<u8>,
+    /// a regular expression matched against the response
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// keywords searched in the response ignoring the case, any of them matches
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// the minimum time between two alerts of the rule on the same channel
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    /// start a recording of the channel for this duration when the alert fires
+    #[serde(default)]
+    pub record_secs: Option<u64>,
+}
+
+/// An alert fired by a rule
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Alert {
+    /// the id of the alert
+    pub alert_id: String,
+    /// the rule that fired the alert
+    pub rule_id: String,
+    /// the wall time in nanoseconds when the alert fired
+    pub stamp_ns: u64,
+    /// the channel of the matching response
+    pub channel_id: u8,
+    /// the prompt of the matching response
+    pub prompt: String,
+    /// the matching response
+    pub response: String,
+    /// the link to download the frame the inference ran on
+    pub frame: Option<String>,
+    /// the recording session started by the alert
+    pub session_id: Option<String>,
+}
+
+/// The query to list the alerts
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AlertListQuery {
+    /// only return the alerts of this rule
+    #[serde(default)]
+    pub rule_id: Option<String>,
+    /// only return the alerts of this channel
+    #[serde(default)]
+    pub channel_id: Option<u8>,
+}
+
+/// The query to get or delete a rule
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AlertRuleQuery {
+    pub rule_id: String,
+}
+
+/// The query to download the frame of an alert
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AlertFrameQuery {
+    pub alert_id: String,
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+/// The shape of a zone, with its points normalized to the image size
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ZoneShape {
+    /// an area, the objects entering and leaving it are counted
+    Polygon { points: Vec<[f32; 2]> },
+    /// a tripwire, the objects crossing it are counted in each direction
+    Line { from: [f32; 2], to: [f32; 2] },
+}
+
+/// A zone of a channel the tracked objects are counted in
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Zone {
+    /// the id of the zone, a zone with the same id is replaced
+    pub id: String,
+    /// the channel of the tracks
+    pub channel_id: u8,
+    #[serde(flatten)]
+    pub shape: ZoneShape,
+    /// only count the objects with these labels, all of them if empty
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// The kind of a zone event
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoneEventKind {
+    /// an object entered a polygon
+    Enter,
+    /// an object left a polygon or its track was removed inside it
+    Exit,
+    /// an object crossed a line
+    Cross,
+}
+
+/// The direction an object crossed a line in
+///
+/// Looking from the first point of the line to the second one, `forward` goes from the
+/// left to the right of the line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossDirection {
+    Forward,
+    Backward,
+}
+
+/// An event of a tracked object in a zone
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ZoneEvent {
+    pub zone_id: String,
+    pub channel_id: u8,
+    pub track_id: u64,
+    pub label: String,
+    pub kind: ZoneEventKind,
+    /// the direction of the crossing, only for the lines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<CrossDirection>,
+    /// the timestamp of the frame of the event in the pipeline clock
+    pub stamp_ns: u64,
+    /// the wall time in nanoseconds since the unix epoch when the event was detected
+    pub wall_time_ns: u64,
+}
+
+/// The counters of a zone since it was added or reset
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ZoneCounters {
+    /// the number of objects currently in the polygon
+    pub inside: u32,
+    pub entered: u64,
+    pub exited: u64,
+    /// the number of crossings of the line in each direction
+    pub forward: u64,
+    pub backward: u64,
+}
+
+/// A zone with its counters
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ZoneAnalytics {
+    pub zone: Zone,
+    pub counters: ZoneCounters,
+}
+
+/// The query to list the zones or their events
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AnalyticsListQuery {
+    /// only return this zone
+    #[serde(default)]
+    pub zone_id: Option<String>,
+    /// only return the zones of this channel
+    #[serde(default)]
+    pub channel_id: Option<u8>,
+}
+
+/// The query to delete a zone
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ZoneQuery {
+    pub zone_id: String,
+}
@@ -1,9 +1,56 @@
+use crate::{
+    cu29::msgs::{DetectionsMsg, ImageRgb8, TracksMsg},
+    inference::{GenerationParams, MockConfig, ModelConfig},
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The default prompt to use if no prompt is provided
+// NOTE: check the original prompt instructions
+// https://ai.google.dev/gemma/docs/paligemma/prompt-system-instructions
+pub const DEFAULT_PROMPT: &str = "cap en";
 
 /// The query for the inference request
 #[derive(Debug, Deserialize, Serialize)]
 pub struct InferenceSettingsQuery {
-    pub prompt: String,
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// the channel to set the prompt for, the default prompt if not provided
+    #[serde(default)]
+    pub channel_id: Option<u8>,
+    /// the generation parameters, overriding the ones of the task config
+    #[serde(default)]
+    pub params: Option<GenerationParams>,
+}
+
+/// The inference settings shared by all the inference tasks
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InferenceSettings {
+    /// the prompt used by the channels without their own prompt
+    pub default_prompt: String,
+    /// the prompts per channel
+    pub channel_prompts: BTreeMap<u8, String>,
+    /// the generation parameters, the ones of the task config if not set
+    pub params: Option<GenerationParams>,
+}
+
+impl Default for InferenceSettings {
+    fn default() -> Self {
+        Self {
+            default_prompt: DEFAULT_PROMPT.to_string(),
+            channel_prompts: BTreeMap::new(),
+            params: None,
+        }
+    }
+}
+
+impl InferenceSettings {
+    /// The prompt to use for the given channel
+    pub fn prompt(&self, channel_id: u8) -> &str {
+        self.channel_prompts
+            .get(&channel_id)
+            .unwrap_or(&self.default_prompt)
+    }
 }
 
 /// The query for the inference request
@@ -13,12 +60,49 @@ pub struct InferenceResultQuery {
 }
 
 /// The result of the inference request
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct InferenceResult {
     pub stamp_ns: u64,
     pub channel_id: u8,
     pub prompt: String,
     pub response: String,
+    /// the generation parameters the response was produced with
+    pub params: GenerationParams,
+    /// the time spent running the model
+    pub latency_ms: u64,
+    /// the model that produced the response
+    #[serde(default)]
+    pub model: String,
+}
+
+/// The query to search the inference history
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct InferenceHistoryQuery {
+    /// only return the results of this channel
+    #[serde(default)]
+    pub channel: Option<u8>,
+    /// the wall-clock start time in nanoseconds since the unix epoch
+    #[serde(default)]
+    pub from: Option<u64>,
+    /// the wall-clock end time in nanoseconds since the unix epoch
+    #[serde(default)]
+    pub to: Option<u64>,
+    /// text to search in the prompt and the response
+    #[serde(default)]
+    pub q: Option<String>,
+    /// the maximum number of results, 100 by default
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// A result stored in the inference history
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InferenceHistoryEntry {
+    pub id: i64,
+    /// the wall time in nanoseconds when the result was stored
+    pub wall_time_ns: u64,
+    #[serde(flatten)]
+    pub result: InferenceResult,
 }
 
 /// The response of the inference request
@@ -27,3 +111,119 @@ pub enum InferenceResponse {
     Success(InferenceResult),
     Error { error: String },
 }
+
+/// The response of the detections request
+#[derive(Debug, Serialize)]
+pub enum DetectionsResponse {
+    Success(DetectionsMsg),
+    Error { error: String },
+}
+
+/// The response of the tracks request
+#[derive(Debug, Serialize)]
+pub enum TracksResponse {
+    Success(TracksMsg),
+    Error { error: String },
+}
+
+/// The scheduling counters of a channel of the inference tasks
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ChannelStats {
+    /// the number of frames sent to the model
+    pub processed: u64,
+    /// the number of frames dropped while the model was busy
+    pub dropped: u64,
+    /// the number of frames waiting in the queue
+    pub queued: usize,
+}
+
+/// The loading status of an inference model
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ModelStatus {
+    /// The model is loading in the background, the frames are dropped meanwhile
+    Loading,
+    /// The model is loaded and running inference
+    Ready,
+    /// The model failed to load
+    Failed(String),
+}
+
+/// The role of a model in its inference task
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelSlot {
+    /// the model running the inferences
+    Active,
+    /// the model loading to replace the active one, or the failure to load it
+    Pending,
+}
+
+/// A model loaded by an inference task
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ModelInfo {
+    /// the inference task running the model
+    pub task_id: String,
+    /// the model of the task this entry describes
+    pub slot: ModelSlot,
+    /// the name of the model
+    pub name: String,
+    /// the backend running the model
+    pub backend: String,
+    /// the loading status of the model
+    pub status: ModelStatus,
+}
+
+/// The request to swap the model of the running inference tasks
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InferenceModelRequest {
+    /// the name of the backend, e.g. `paligemma` or `mock`
+    pub backend: String,
+    /// the weights of the model
+    #[serde(default)]
+    pub model: ModelConfig,
+    /// the configuration of the mock backend
+    #[serde(default)]
+    pub mock: MockConfig,
+}
+
+/// The form fields of an on-demand inference query besides the image
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct InferenceQueryRequest {
+    /// the prompt to run, the prompt of the channel or the default one if not provided
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// the channel to take the latest frame from if no image is uploaded
+    #[serde(default)]
+    pub channel_id: Option<u8>,
+    /// the time to wait for the response, 30 seconds by default
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// The response of an on-demand inference query
+#[derive(Clone, Debug, Serialize)]
+pub struct InferenceQueryResponse {
+    pub prompt: String,
+    pub response: String,
+    /// the generation parameters the response was produced with
+    pub params: GenerationParams,
+    /// the time spent running the model
+    pub latency_ms: u64,
+    /// the model that produced the response
+    #[serde(default)]
+    pub model: String,
+}
+
+/// An on-demand inference query sent to the inference tasks
+pub struct InferenceQuery {
+    /// the image to run the inference on
+    pub image: ImageRgb8,
+    /// the channel the image comes from or whose settings are used, if any
+    pub channel_id: Option<u8>,
+    /// the image was uploaded, so its result is kept out of the history of the channel
+    pub uploaded: bool,
+    /// the prompt to run, resolved from the settings if not provided
+    pub prompt: Option<String>,
+    /// the channel to send the response or the error back to the server
+    pub reply: tokio::sync::oneshot::Sender<Result<InferenceQueryResponse, String>>,
+}
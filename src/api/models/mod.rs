@@ -1,4 +1,8 @@
+pub mod alerts;
+pub mod analytics;
 pub mod inference;
 pub mod pipeline;
 pub mod recording;
+pub mod search;
 pub mod streaming;
+pub mod webhooks;
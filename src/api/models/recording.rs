@@ -1,14 +1,245 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-/// The command for the recording request
+/// The command broadcasted to the recorder tasks
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum RecordingCommand {
-    Start,
-    Stop,
+    /// Start a new recording session
+    Start(RecordingSessionConfig),
+    /// Stop the given session or all the sessions if `None`
+    Stop { session_id: Option<String> },
+    /// Attach an annotation to the given session or all the sessions if `None`
+    Annotate {
+        session_id: Option<String>,
+        annotation: RecordingAnnotation,
+    },
 }
 
-/// The query for the recording request
+/// The configuration of a recording session
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct RecordingQuery {
-    pub command: RecordingCommand,
+pub struct RecordingSessionConfig {
+    /// the id of the recording session
+    pub session_id: String,
+    /// an optional human readable name of the session
+    pub name: Option<String>,
+    /// the channels to record, all the channels if empty
+    pub channels: Vec<u8>,
+    /// user supplied tags stored in the session manifest
+    pub tags: Vec<String>,
+    /// stop the session automatically after this duration
+    pub duration_secs: Option<u64>,
+}
+
+impl RecordingSessionConfig {
+    /// Check if the session records the given channel
+    pub fn records_channel(&self, channel_id: u8) -> bool {
+        self.channels.is_empty() || self.channels.contains(&channel_id)
+    }
+}
+
+/// The request to start a recording session
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RecordingStartRequest {
+    /// an optional human readable name of the session
+    #[serde(default)]
+    pub name: Option<String>,
+    /// the channels to record, all the channels if empty
+    #[serde(default)]
+    pub channels: Vec<u8>,
+    /// user supplied tags stored in the session manifest
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// stop the session automatically after this duration
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+}
+
+/// The request to stop a recording session
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RecordingStopRequest {
+    /// the session to stop, all the sessions if not provided
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// The current status of a recording session
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum RecordingSessionStatus {
+    /// The session is being recorded
+    Recording,
+    /// The session is stopping, the recorders are finalizing its recordings
+    Finalizing,
+    /// The session is stopped and its recordings are finalized
+    Stopped,
+    /// A recorder failed to start the session, with the reason
+    Failed(String),
+}
+
+/// The information of a recording session managed by the server
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordingSessionInfo {
+    /// the configuration of the session
+    pub config: RecordingSessionConfig,
+    /// the current status of the session
+    pub status: RecordingSessionStatus,
+    /// the paths to the manifests of the finalized recordings
+    pub manifests: Vec<String>,
+    /// the number of recorders that did not finalize their recording yet
+    #[serde(default)]
+    pub pending_recorders: usize,
+    /// the annotations attached to the session
+    pub annotations: Vec<RecordingAnnotation>,
+}
+
+/// A timestamped annotation attached to a recording session
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordingAnnotation {
+    /// the wall time in nanoseconds when the annotation was created
+    pub stamp_ns: u64,
+    /// the author of the annotation
+    pub author: String,
+    /// a short label to categorize the annotation
+    pub label: String,
+    /// the free text of the annotation
+    pub text: String,
+}
+
+/// The request to annotate a recording session
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AnnotationRequest {
+    /// the session to annotate, all the active sessions if not provided
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// the author of the annotation
+    #[serde(default)]
+    pub author: String,
+    /// a short label to categorize the annotation
+    #[serde(default)]
+    pub label: String,
+    /// the free text of the annotation
+    #[serde(default)]
+    pub text: String,
+}
+
+/// The query to search the annotations
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AnnotationSearchQuery {
+    /// text to search in the label and the text of the annotations
+    #[serde(default)]
+    pub q: Option<String>,
+    /// only return the annotations with this label
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// An annotation found by the search
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AnnotationSearchResult {
+    /// the session the annotation belongs to
+    pub session_id: String,
+    /// the annotation
+    pub annotation: RecordingAnnotation,
+}
+
+/// The manifest written next to each recording when it is finalized
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RecordingManifest {
+    /// the id of the recording session
+    pub session_id: String,
+    /// the human readable name of the session
+    pub name: Option<String>,
+    /// the name of the host that produced the recording
+    pub host: String,
+    /// the file name of the recording
+    pub recording: String,
+    /// the pipeline that produced the recording
+    pub pipeline: PipelineManifest,
+    /// the channels that were recorded
+    pub channels: Vec<u8>,
+    /// the wall time in nanoseconds when the recording started
+    pub start_time_ns: u64,
+    /// the wall time in nanoseconds when the recording stopped
+    pub stop_time_ns: Option<u64>,
+    /// the number of frames recorded per channel
+    pub frame_counts: BTreeMap<u8, u64>,
+    /// user supplied tags passed in the start command
+    pub tags: Vec<String>,
+    /// the annotations attached during the recording
+    #[serde(default)]
+    pub annotations: Vec<RecordingAnnotation>,
+}
+
+/// The pipeline description stored in the recording manifest
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PipelineManifest {
+    /// the name of the pipeline
+    pub name: String,
+    /// the tasks of the pipeline and their configuration
+    pub tasks: Vec<TaskManifest>,
+}
+
+/// A task description stored in the recording manifest
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TaskManifest {
+    /// the id of the task in the pipeline
+    pub id: String,
+    /// the type of the task
+    #[serde(rename = "type")]
+    pub task_type: String,
+    /// the configuration of the task
+    pub config: serde_json::Value,
+}
+
+/// The format of an extracted clip
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipFormat {
+    /// a rerun recording
+    #[default]
+    Rrd,
+    /// an mp4 video
+    Mp4,
+}
+
+impl ClipFormat {
+    /// The file extension of the clip
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ClipFormat::Rrd => "rrd",
+            ClipFormat::Mp4 => "mp4",
+        }
+    }
+}
+
+/// The request to extract a clip from the recordings
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClipRequest {
+    /// the camera channel to extract
+    pub channel_id: u8,
+    /// the wall-clock start time in nanoseconds since the unix epoch
+    pub start_ns: u64,
+    /// the wall-clock end time in nanoseconds since the unix epoch
+    pub end_ns: u64,
+    /// the format of the clip
+    #[serde(default)]
+    pub format: ClipFormat,
+}
+
+/// The query to download a clip
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClipQuery {
+    pub clip_id: String,
+}
+
+/// The result of a clip extraction
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClipInfo {
+    /// the id of the clip
+    pub clip_id: String,
+    /// the recordings the clip was cut from
+    pub segments: Vec<String>,
+    /// the number of frames in the clip
+    pub frames: usize,
+    /// the link to download the clip
+    pub download: String,
 }
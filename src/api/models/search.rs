@@ -0,0 +1,75 @@
+use crate::{api::models::recording::ClipRequest, cu29::msgs::ImageRgb8};
+use serde::{Deserialize, Serialize};
+
+/// The fields of the search form, next to an optional image to search with
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SearchRequest {
+    /// the text describing the frames to find, if no image is uploaded
+    #[serde(default)]
+    pub text: Option<String>,
+    /// only return the frames of this channel
+    #[serde(default)]
+    pub channel_id: Option<u8>,
+    /// the wall-clock start time in nanoseconds since the unix epoch
+    #[serde(default)]
+    pub from: Option<u64>,
+    /// the wall-clock end time in nanoseconds since the unix epoch
+    #[serde(default)]
+    pub to: Option<u64>,
+    /// the maximum number of frames, 10 by default
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// the time to wait for the query to be embedded, 30 seconds by default
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// What a query is embedded from
+pub enum EmbeddingInput {
+    Text(String),
+    Image(ImageRgb8),
+}
+
+/// A search query sent to the embedding task to embed it with its model
+pub struct EmbeddingQuery {
+    pub input: EmbeddingInput,
+    /// the channel to send the model name and the vector or the error back to the server
+    pub reply: tokio::sync::oneshot::Sender<Result<(String, Vec<f32>), String>>,
+}
+
+/// A recording containing a found frame
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SearchRecording {
+    /// the recording session
+    pub session_id: String,
+    /// the path to the `.rrd` file
+    pub recording: String,
+    /// the time of the frame since the start of the recording
+    pub offset_ms: u64,
+}
+
+/// A frame matching the search
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SearchResult {
+    /// the cosine similarity between the query and the frame
+    pub score: f32,
+    pub frame_id: i64,
+    /// the wall time in nanoseconds when the frame was sampled
+    pub wall_time_ns: u64,
+    /// the timestamp of the frame in the pipeline
+    pub stamp_ns: u64,
+    pub channel_id: u8,
+    /// the recordings of the channel at the time of the frame
+    pub recordings: Vec<SearchRecording>,
+    /// the request to extract a clip around the frame from the recordings
+    pub clip: ClipRequest,
+}
+
+/// The response of a search
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SearchResponse {
+    /// the model the query and the frames were embedded with
+    pub model: String,
+    /// the best-matching frames, the most similar first
+    pub results: Vec<SearchResult>,
+}
@@ -0,0 +1,104 @@
+use crate::{
+    api::models::{alerts::Alert, analytics::ZoneEvent, recording::RecordingSessionConfig},
+    pipeline::PipelineStatus,
+};
+use serde::{Deserialize, Serialize};
+
+/// The configuration of an outbound webhook
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookConfig {
+    /// the id of the webhook, a webhook with the same id is replaced
+    pub id: String,
+    /// the url the events are posted to
+    pub url: String,
+    /// the secret used to sign the payloads, not signed if not provided
+    // NOTE: the secret is never sent back by the api
+    #[serde(default, skip_serializing)]
+    pub secret: Option<String>,
+    /// the kinds of events to send, e.g. `alert`, all the events if empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// the number of retries before the event is written to the dead-letter log
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+impl WebhookConfig {
+    /// Check if the webhook subscribes to the given kind of event
+    pub fn accepts(&self, kind: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|event| event == kind)
+    }
+}
+
+/// An event pushed to the webhooks
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum WebhookEvent {
+    /// An alert fired by a rule
+    Alert(Alert),
+    /// A pipeline changed its status
+    PipelineStatus {
+        pipeline_id: String,
+        status: PipelineStatus,
+    },
+    /// A recording session started
+    RecordingStarted(RecordingSessionConfig),
+    /// A recording of a session was finalized
+    RecordingStopped {
+        session_id: String,
+        manifest: String,
+    },
+    /// A tracked object entered, left or crossed a zone
+    Zone(ZoneEvent),
+}
+
+impl WebhookEvent {
+    /// The kind of the event used to filter and label the payloads
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WebhookEvent::Alert(_) => "alert",
+            WebhookEvent::PipelineStatus { .. } => "pipeline_status",
+            WebhookEvent::RecordingStarted(_) => "recording_started",
+            WebhookEvent::RecordingStopped { .. } => "recording_stopped",
+            WebhookEvent::Zone(_) => "zone",
+        }
+    }
+}
+
+/// The json payload posted to the webhooks
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookPayload {
+    /// the id of the delivery, the same for all the retries
+    pub delivery_id: String,
+    /// the wall time in nanoseconds when the event happened
+    pub stamp_ns: u64,
+    /// the kind of the event
+    pub event: String,
+    /// the content of the event
+    pub data: WebhookEvent,
+}
+
+/// A delivery that failed after all its retries, appended to the dead-letter log
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookDeadLetter {
+    /// the webhook the delivery was sent to
+    pub webhook_id: String,
+    /// the url the delivery was sent to
+    pub url: String,
+    /// the number of attempts
+    pub attempts: u32,
+    /// the last error
+    pub error: String,
+    /// the payload that could not be delivered
+    pub payload: WebhookPayload,
+}
+
+/// The query to remove a webhook
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookQuery {
+    pub webhook_id: String,
+}
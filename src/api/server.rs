@@ -1,9 +1,13 @@
 use crate::{api::handles, pipeline::ServerGlobalState};
 use axum::{
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    routing::{delete, get, post},
     Router,
 };
 
+/// The maximum size of the images uploaded to the inference query and search endpoints
+const QUERY_BODY_LIMIT: usize = 16 * 1024 * 1024;
+
 #[derive(Default)]
 pub struct ApiServer;
 
@@ -33,9 +37,22 @@ impl ApiServer {
                     get(handles::streaming::get_streaming_image),
                 ),
             )
-            .route(
+            .nest(
                 "/api/v0/recording",
-                post(handles::recording::post_recording_command),
+                Router::new()
+                    .route("/start", post(handles::recording::post_recording_start))
+                    .route("/stop", post(handles::recording::post_recording_stop))
+                    .route("/sessions", get(handles::recording::get_recording_sessions))
+                    .route(
+                        "/annotations",
+                        get(handles::recording::get_recording_annotations)
+                            .post(handles::recording::post_recording_annotation),
+                    )
+                    .route("/clips", post(handles::recording::post_recording_clip))
+                    .route(
+                        "/clips/{clip_id}",
+                        get(handles::recording::get_recording_clip),
+                    ),
             )
             .nest(
                 "/api/v0/inference",
@@ -44,9 +61,82 @@ impl ApiServer {
                         "/result/{channel_id}",
                         get(handles::inference::get_inference_result),
                     )
+                    .route(
+                        "/detections/{channel_id}",
+                        get(handles::inference::get_inference_detections),
+                    )
+                    .route(
+                        "/tracks/{channel_id}",
+                        get(handles::inference::get_inference_tracks),
+                    )
                     .route(
                         "/settings",
-                        post(handles::inference::post_inference_settings),
+                        get(handles::inference::get_inference_settings)
+                            .post(handles::inference::post_inference_settings),
+                    )
+                    .route("/stats", get(handles::inference::get_inference_stats))
+                    .route(
+                        "/models",
+                        get(handles::inference::get_inference_models)
+                            .post(handles::inference::post_inference_model),
+                    )
+                    .route("/history", get(handles::inference::get_inference_history))
+                    .route(
+                        "/query",
+                        post(handles::inference::post_inference_query)
+                            // NOTE: the default limit of 2MB is too small for camera frames
+                            .layer(DefaultBodyLimit::max(QUERY_BODY_LIMIT)),
+                    ),
+            )
+            .nest(
+                "/api/v0/alerts",
+                Router::new()
+                    .route(
+                        "/rules",
+                        get(handles::alerts::get_alert_rules)
+                            .post(handles::alerts::post_alert_rule),
+                    )
+                    .route(
+                        "/rules/{rule_id}",
+                        delete(handles::alerts::delete_alert_rule),
+                    )
+                    .route("/list", get(handles::alerts::get_alerts))
+                    .route("/stream", get(handles::alerts::get_alert_stream))
+                    .route("/frames/{alert_id}", get(handles::alerts::get_alert_frame)),
+            )
+            .nest(
+                "/api/v0/analytics",
+                Router::new()
+                    .route("/", get(handles::analytics::get_analytics))
+                    .route(
+                        "/zones",
+                        get(handles::analytics::get_analytics)
+                            .post(handles::analytics::post_analytics_zone),
+                    )
+                    .route(
+                        "/zones/{zone_id}",
+                        delete(handles::analytics::delete_analytics_zone),
+                    )
+                    .route("/reset", post(handles::analytics::post_analytics_reset))
+                    .route("/events", get(handles::analytics::get_analytics_events))
+                    .route("/stream", get(handles::analytics::get_analytics_stream)),
+            )
+            .route(
+                "/api/v0/search",
+                post(handles::search::post_search)
+                    // NOTE: the default limit of 2MB is too small for camera frames
+                    .layer(DefaultBodyLimit::max(QUERY_BODY_LIMIT)),
+            )
+            .nest(
+                "/api/v0/webhooks",
+                Router::new()
+                    .route(
+                        "/hooks",
+                        get(handles::webhooks::get_webhooks).post(handles::webhooks::post_webhook),
+                    )
+                    .route(
+                        "/hooks/{webhook_id}",
+                        delete(handles::webhooks::delete_webhook),
                     ),
             )
             .nest(
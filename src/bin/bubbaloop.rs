@@ -1,4 +1,10 @@
 use argh::FromArgs;
+use bubbaloop::{
+    api::models::inference::DEFAULT_PROMPT,
+    inference,
+    recording::{self, TimeRange},
+};
+use std::{io::Write, path::PathBuf};
 
 // defaults for the server
 const DEFAULT_HOST: &str = "0.0.0.0";
@@ -22,11 +28,187 @@ struct CLIArgs {
 #[derive(FromArgs)]
 #[argh(subcommand)]
 enum Commands {
+    Eval(EvalCommand),
+    Export(ExportCommand),
+    Infer(InferCommand),
+    Models(ModelsCommand),
     Pipeline(PipelineCommand),
     Recording(RecordingCommand),
     Stats(StatsCommand),
 }
 
+#[derive(FromArgs)]
+#[argh(subcommand, name = "export")]
+/// Export the frames of a recording to mp4, jpeg files or a contact sheet
+struct ExportCommand {
+    #[argh(option, short = 'i')]
+    /// the path to the .rrd recording
+    input: PathBuf,
+
+    #[argh(option, short = 'o')]
+    /// the output path: a file for mp4 and contact-sheet, a folder for jpeg
+    output: PathBuf,
+
+    #[argh(option, short = 'c', default = "0")]
+    /// the channel to export
+    channel: u8,
+
+    #[argh(option, short = 'f', default = "ExportFormat::Mp4")]
+    /// the output format: mp4, jpeg or contact-sheet
+    format: ExportFormat,
+
+    #[argh(option)]
+    /// the start of the time range in unix seconds
+    from: Option<f64>,
+
+    #[argh(option)]
+    /// the end of the time range in unix seconds
+    to: Option<f64>,
+
+    #[argh(option, default = "30")]
+    /// the frames per second of the mp4 video
+    fps: u32,
+
+    #[argh(option, default = "16")]
+    /// the number of thumbnails of the contact sheet
+    thumbnails: usize,
+}
+
+enum ExportFormat {
+    Mp4,
+    Jpeg,
+    ContactSheet,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mp4" => Ok(Self::Mp4),
+            "jpeg" => Ok(Self::Jpeg),
+            "contact-sheet" => Ok(Self::ContactSheet),
+            _ => Err(format!(
+                "Invalid format {}, try 'mp4', 'jpeg' or 'contact-sheet'",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "eval")]
+/// Evaluate a backend against the ground truth of a dataset manifest
+struct EvalCommand {
+    #[argh(option, short = 'm')]
+    /// the JSON manifest of the images with their expected captions, keywords or boxes
+    manifest: PathBuf,
+
+    #[argh(option, short = 'o')]
+    /// the folder to write report.json and report.md to
+    output: PathBuf,
+
+    #[argh(option)]
+    /// a JSON file with the backend configuration (default: paligemma)
+    config: Option<PathBuf>,
+
+    #[argh(option, short = 'b')]
+    /// the inference backend, overriding the one of the configuration
+    backend: Option<String>,
+
+    #[argh(option, short = 'p')]
+    /// the prompt of the samples without their own prompt (default: "cap en")
+    prompt: Option<String>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "infer")]
+/// Run prompts offline over a folder of images or a recorded channel
+struct InferCommand {
+    #[argh(option, short = 'i')]
+    /// a folder of jpeg or png images, or the path to a .rrd recording
+    input: PathBuf,
+
+    #[argh(option, short = 'o')]
+    /// the JSONL file to write the results to (default: stdout)
+    output: Option<PathBuf>,
+
+    #[argh(option, short = 'p')]
+    /// a prompt to run on every image, can be repeated (default: "cap en")
+    prompt: Vec<String>,
+
+    #[argh(option, short = 'c', default = "0")]
+    /// the channel of the recording to run on
+    channel: u8,
+
+    #[argh(option)]
+    /// the start of the time range of the recording in unix seconds
+    from: Option<f64>,
+
+    #[argh(option)]
+    /// the end of the time range of the recording in unix seconds
+    to: Option<f64>,
+
+    #[argh(
+        option,
+        short = 'b',
+        default = "inference::DEFAULT_BACKEND.to_string()"
+    )]
+    /// the inference backend: paligemma or mock
+    backend: String,
+
+    #[argh(option)]
+    /// the repository of the model weights on the Hugging Face hub
+    model_repo: Option<String>,
+
+    #[argh(option)]
+    /// a directory with the model files
+    model_dir: Option<PathBuf>,
+
+    #[argh(option)]
+    /// a Hugging Face cache root to read the model files from
+    model_cache: Option<PathBuf>,
+
+    #[argh(option)]
+    /// the behaviour of the mock backend: echo, canned or stats
+    mock_mode: Option<inference::MockMode>,
+
+    #[argh(option)]
+    /// the file with the canned responses of the mock backend
+    mock_responses: Option<String>,
+
+    #[argh(option, default = "50")]
+    /// the maximum number of tokens to generate
+    max_new_tokens: usize,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "models")]
+/// Inspect the locally cached models
+struct ModelsCommand {
+    #[argh(subcommand)]
+    mode: ModelsMode,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum ModelsMode {
+    List(ModelsListCommand),
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "list")]
+/// List the cached models and their sizes
+struct ModelsListCommand {
+    #[argh(option)]
+    /// the Hugging Face cache root (default: $HF_HOME or ~/.cache/huggingface)
+    cache: Option<PathBuf>,
+
+    #[argh(switch)]
+    /// also verify the presence and the checksums of the model files
+    verify: bool,
+}
+
 #[derive(FromArgs)]
 #[argh(subcommand, name = "stats")]
 /// Get stats about the server
@@ -65,17 +247,65 @@ struct RecordingCommand {
 enum RecordingMode {
     Start(RecordingStartCommand),
     Stop(RecordingStopCommand),
+    List(RecordingListCommand),
+    Annotate(RecordingAnnotateCommand),
 }
 
 #[derive(FromArgs)]
 #[argh(subcommand, name = "start")]
 /// Start recording
-struct RecordingStartCommand {}
+struct RecordingStartCommand {
+    #[argh(option, short = 'n')]
+    /// the name of the recording session
+    name: Option<String>,
+
+    #[argh(option, short = 'c')]
+    /// a channel to record, can be repeated (default: all)
+    channel: Vec<u8>,
+
+    #[argh(option, short = 't')]
+    /// a tag to store in the recording manifest, can be repeated
+    tag: Vec<String>,
+
+    #[argh(option, short = 'd')]
+    /// stop the recording automatically after this many seconds
+    duration: Option<u64>,
+}
 
 #[derive(FromArgs)]
 #[argh(subcommand, name = "stop")]
 /// Stop recording
-struct RecordingStopCommand {}
+struct RecordingStopCommand {
+    #[argh(option, short = 's')]
+    /// the recording session to stop (default: all)
+    session_id: Option<String>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "list")]
+/// List recording sessions
+struct RecordingListCommand {}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "annotate")]
+/// Annotate the active recording sessions
+struct RecordingAnnotateCommand {
+    #[argh(option, short = 'l', default = "String::from(\"event\")")]
+    /// a short label to categorize the annotation
+    label: String,
+
+    #[argh(positional)]
+    /// the free text of the annotation
+    text: String,
+
+    #[argh(option, short = 'a', default = "whoami::username()")]
+    /// the author of the annotation (default: current user)
+    author: String,
+
+    #[argh(option, short = 's')]
+    /// the recording session to annotate (default: all active)
+    session_id: Option<String>,
+}
 
 #[derive(FromArgs)]
 #[argh(subcommand, name = "pipeline")]
@@ -126,6 +356,202 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = format!("{}:{}", args.host, args.port);
 
     match args.commands {
+        Commands::Export(export_command) => {
+            let range = TimeRange {
+                start_ns: export_command.from.map(|secs| (secs * 1e9) as i64),
+                end_ns: export_command.to.map(|secs| (secs * 1e9) as i64),
+            };
+
+            let images = recording::read_encoded_images(
+                &export_command.input,
+                export_command.channel,
+                range,
+            )
+            .map_err(|e| e.to_string())?;
+
+            println!(
+                "Exporting {} frames from channel {}",
+                images.len(),
+                export_command.channel
+            );
+
+            match export_command.format {
+                ExportFormat::Mp4 => {
+                    recording::export_mp4(&images, &export_command.output, export_command.fps)
+                }
+                ExportFormat::Jpeg => {
+                    recording::export_jpeg_folder(&images, &export_command.output)
+                }
+                ExportFormat::ContactSheet => recording::export_contact_sheet(
+                    &images,
+                    &export_command.output,
+                    4,
+                    export_command.thumbnails,
+                    320,
+                ),
+            }
+            .map_err(|e| e.to_string())?;
+
+            println!("Exported to {}", export_command.output.display());
+        }
+        Commands::Eval(eval_command) => {
+            let manifest = inference::EvalManifest::load(&eval_command.manifest)
+                .map_err(|e| format!("{}: {}", eval_command.manifest.display(), e))?;
+
+            let mut config = match &eval_command.config {
+                Some(path) => serde_json::from_str::<inference::BackendConfig>(
+                    &std::fs::read_to_string(path)?,
+                )?,
+                None => inference::BackendConfig::default(),
+            };
+            if let Some(backend) = eval_command.backend {
+                config.backend = backend;
+            }
+
+            eprintln!(
+                "Evaluating {} on {} samples",
+                config.model_name(),
+                manifest.samples.len()
+            );
+
+            let mut backend = inference::create_backend(&config).map_err(|e| e.to_string())?;
+
+            let report = inference::run_eval(
+                backend.as_mut(),
+                &config,
+                &manifest,
+                &eval_command.manifest,
+                eval_command.prompt.as_deref().unwrap_or(DEFAULT_PROMPT),
+                |sample| match &sample.error {
+                    Some(error) => eprintln!("Failed on {}: {}", sample.image, error),
+                    None => eprintln!(
+                        "{} -- {} ms -- {}",
+                        sample.image, sample.latency_ms, sample.response
+                    ),
+                },
+            );
+
+            std::fs::create_dir_all(&eval_command.output)?;
+            std::fs::write(
+                eval_command.output.join("report.json"),
+                serde_json::to_string_pretty(&report)?,
+            )?;
+            std::fs::write(eval_command.output.join("report.md"), report.to_markdown())?;
+
+            println!("{}", report.to_markdown());
+            eprintln!("Wrote the report to {}", eval_command.output.display());
+        }
+        Commands::Infer(infer_command) => {
+            let images = if infer_command.input.is_dir() {
+                inference::list_image_files(&infer_command.input)?
+            } else {
+                let range = TimeRange {
+                    start_ns: infer_command.from.map(|secs| (secs * 1e9) as i64),
+                    end_ns: infer_command.to.map(|secs| (secs * 1e9) as i64),
+                };
+                recording::read_encoded_images(&infer_command.input, infer_command.channel, range)
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .map(inference::BatchImage::Frame)
+                    .collect()
+            };
+
+            let prompts = if infer_command.prompt.is_empty() {
+                vec![DEFAULT_PROMPT.to_string()]
+            } else {
+                infer_command.prompt
+            };
+
+            let mut config = inference::BackendConfig {
+                backend: infer_command.backend,
+                ..Default::default()
+            };
+            config.params.max_new_tokens = infer_command.max_new_tokens;
+            if let Some(repo) = infer_command.model_repo {
+                config.model.repo = repo;
+            }
+            config.model.source = match (infer_command.model_dir, infer_command.model_cache) {
+                (Some(_), Some(_)) => {
+                    return Err("Either --model-dir or --model-cache can be set, not both".into())
+                }
+                (Some(dir), None) => inference::ModelSource::Dir(dir),
+                (None, Some(cache)) => inference::ModelSource::Cache(cache),
+                (None, None) => inference::ModelSource::Hub,
+            };
+            if let Some(mode) = infer_command.mock_mode {
+                config.mock.mode = mode;
+            }
+            config.mock.responses_path = infer_command.mock_responses;
+
+            eprintln!(
+                "Running {} prompts on {} images with {}",
+                prompts.len(),
+                images.len(),
+                config.model_name()
+            );
+
+            let mut backend = inference::create_backend(&config).map_err(|e| e.to_string())?;
+
+            let mut output: Box<dyn std::io::Write> = match &infer_command.output {
+                Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+                None => Box::new(std::io::stdout().lock()),
+            };
+
+            let mut count = 0;
+            inference::run_batch(
+                backend.as_mut(),
+                &config.model_name(),
+                images,
+                &prompts,
+                &config.params,
+                |record| -> std::io::Result<()> {
+                    if let Some(error) = &record.error {
+                        eprintln!(
+                            "Failed on {}: {}",
+                            record
+                                .file
+                                .clone()
+                                .or(record.stamp_ns.map(|stamp_ns| stamp_ns.to_string()))
+                                .unwrap_or_default(),
+                            error
+                        );
+                    }
+                    writeln!(output, "{}", serde_json::to_string(&record)?)?;
+                    count += 1;
+                    Ok(())
+                },
+            )?;
+            output.flush()?;
+
+            eprintln!("Wrote {} results", count);
+        }
+        Commands::Models(models_command) => match models_command.mode {
+            ModelsMode::List(models_list_command) => {
+                let root = models_list_command
+                    .cache
+                    .unwrap_or_else(inference::default_cache_root);
+                let models = inference::list_cached_models(&root).map_err(|e| e.to_string())?;
+
+                println!("Models cached in {}", root.display());
+                for model in models {
+                    let status = if models_list_command.verify {
+                        match inference::validate_model_files(&model.path) {
+                            Ok(()) => " ok".to_string(),
+                            Err(e) => format!(" {}", e),
+                        }
+                    } else {
+                        String::new()
+                    };
+                    println!(
+                        "{:<48} {:<12} {:>10}{}",
+                        model.repo,
+                        model.revision.chars().take(12).collect::<String>(),
+                        format_size(model.size_bytes),
+                        status
+                    );
+                }
+            }
+        },
         Commands::Stats(stats_command) => match stats_command.mode {
             StatsMode::Whoami(_) => {
                 let response = client
@@ -147,11 +573,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         },
         Commands::Recording(recording_command) => match recording_command.mode {
-            RecordingMode::Start(_) => {
+            RecordingMode::Start(recording_start_command) => {
                 let response = client
-                    .post(format!("http://{}/api/v0/recording", addr))
-                    .json(&bubbaloop::api::models::recording::RecordingQuery {
-                        command: bubbaloop::api::models::recording::RecordingCommand::Start,
+                    .post(format!("http://{}/api/v0/recording/start", addr))
+                    .json(&bubbaloop::api::models::recording::RecordingStartRequest {
+                        name: recording_start_command.name,
+                        channels: recording_start_command.channel,
+                        tags: recording_start_command.tag,
+                        duration_secs: recording_start_command.duration,
                     })
                     .send()
                     .await?;
@@ -159,11 +588,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let result = response.json::<serde_json::Value>().await?;
                 println!("Result: {}", serde_json::to_string_pretty(&result)?);
             }
-            RecordingMode::Stop(_) => {
+            RecordingMode::Stop(recording_stop_command) => {
                 let response = client
-                    .post(format!("http://{}/api/v0/recording", addr))
-                    .json(&bubbaloop::api::models::recording::RecordingQuery {
-                        command: bubbaloop::api::models::recording::RecordingCommand::Stop,
+                    .post(format!("http://{}/api/v0/recording/stop", addr))
+                    .json(&bubbaloop::api::models::recording::RecordingStopRequest {
+                        session_id: recording_stop_command.session_id,
                     })
                     .send()
                     .await?;
@@ -171,6 +600,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let result = response.json::<serde_json::Value>().await?;
                 println!("Result: {}", serde_json::to_string_pretty(&result)?);
             }
+            RecordingMode::Annotate(recording_annotate_command) => {
+                let response = client
+                    .post(format!("http://{}/api/v0/recording/annotations", addr))
+                    .json(&bubbaloop::api::models::recording::AnnotationRequest {
+                        session_id: recording_annotate_command.session_id,
+                        author: recording_annotate_command.author,
+                        label: recording_annotate_command.label,
+                        text: recording_annotate_command.text,
+                    })
+                    .send()
+                    .await?;
+
+                let result = response.json::<serde_json::Value>().await?;
+                println!("Result: {}", serde_json::to_string_pretty(&result)?);
+            }
+            RecordingMode::List(_) => {
+                let response = client
+                    .get(format!("http://{}/api/v0/recording/sessions", addr))
+                    .send()
+                    .await?;
+
+                let result = response.json::<serde_json::Value>().await?;
+                println!("Result: {}", serde_json::to_string_pretty(&result)?);
+            }
         },
         Commands::Pipeline(pipeline_command) => match pipeline_command.mode {
             PipelineMode::Start(pipeline_start_command) => {
@@ -211,3 +664,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Format a size in bytes with a binary unit, e.g. `5.4 GiB`
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -3,6 +3,9 @@ use argh::FromArgs;
 // defaults for the server
 const DEFAULT_HOST: &str = "0.0.0.0";
 const DEFAULT_PORT: u16 = 3000;
+const DEFAULT_HISTORY_PATH: &str = "/tmp/bubbaloop_history.db";
+const DEFAULT_EMBEDDINGS_PATH: &str = "/tmp/bubbaloop_embeddings.db";
+const DEFAULT_DEAD_LETTER_PATH: &str = "/tmp/bubbaloop_webhooks_dead.jsonl";
 
 #[derive(FromArgs)]
 #[argh(description = "Bubbaloop server")]
@@ -14,6 +17,22 @@ struct CLIArgs {
     #[argh(option, short = 'p', default = "DEFAULT_PORT")]
     /// the port to listen on
     port: u16,
+
+    #[argh(option, default = "DEFAULT_HISTORY_PATH.to_string()")]
+    /// the path to the database storing the inference results
+    history: String,
+
+    #[argh(option, default = "DEFAULT_EMBEDDINGS_PATH.to_string()")]
+    /// the path to the database storing the frame embeddings searched by the api
+    embeddings: String,
+
+    #[argh(option)]
+    /// a json file with the list of webhooks to notify
+    webhooks: Option<String>,
+
+    #[argh(option, default = "DEFAULT_DEAD_LETTER_PATH.to_string()")]
+    /// the jsonl file where the failed webhook deliveries are written
+    dead_letter: String,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -26,10 +45,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let global_state = bubbaloop::pipeline::SERVER_GLOBAL_STATE.clone();
 
+    // open the inference history shared with the pipelines
+    global_state
+        .result_store
+        .inference_history
+        .open(std::path::Path::new(&args.history))?;
+
+    // open the index of the frames embedded by the pipelines
+    global_state
+        .result_store
+        .embedding_index
+        .open(std::path::Path::new(&args.embeddings))?;
+
+    // register the webhooks given at startup, more can be added via the api
+    if let Some(path) = &args.webhooks {
+        let hooks: Vec<bubbaloop::api::models::webhooks::WebhookConfig> =
+            serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        for hook in hooks {
+            log::info!("🔔 Notifying webhook {} at {}", hook.id, hook.url);
+            global_state.result_store.webhooks.add_hook(hook);
+        }
+    }
+
     // start the api server
     let api = bubbaloop::api::ApiServer;
     let runtime = tokio::runtime::Runtime::new()?;
     runtime.block_on(async move {
+        global_state
+            .result_store
+            .webhooks
+            .spawn_dispatcher(args.dead_letter.into());
         api.start(addr, global_state).await.unwrap();
     });
 
@@ -1,6 +1,7 @@
+use crate::inference::GenerationParams;
 use serde::{ser::SerializeStruct, Deserialize, Serialize};
 
-type ImageRgb8 = kornia::image::Image<u8, 3>;
+pub type ImageRgb8 = kornia::image::Image<u8, 3>;
 
 #[derive(Clone)]
 pub struct ImageRgb8Msg {
@@ -125,4 +126,74 @@ pub struct PromptResponseMsg {
     pub channel_id: u8,
     pub prompt: String,
     pub response: String,
+    pub params: GenerationParams,
+    /// the time spent running the model
+    pub latency_ms: u64,
+    /// the model that produced the response
+    pub model: String,
+    /// the jpeg frame the response was produced from, only kept when alert rules exist
+    pub frame: Option<EncodedImage>,
+}
+
+/// An object detected in an image
+#[derive(Clone, Debug, Default, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct Detection {
+    pub label: String,
+    /// the box as `[x_min, y_min, x_max, y_max]` normalized to the image size
+    pub bbox: [f32; 4],
+    /// the raw `<segXXX>` codes of the object for `segment` prompts
+    // NOTE: the codes are not decoded, the 64x64 mask needs the PaliGemma VQ-VAE decoder
+    pub seg_codes: Option<Vec<u16>>,
+    /// the confidence of the detector, the VLM responses do not provide one
+    pub score: Option<f32>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct DetectionsMsg {
+    pub stamp_ns: u64,
+    pub channel_id: u8,
+    pub prompt: String,
+    pub detections: Vec<Detection>,
+}
+
+/// The lifecycle of a track
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, bincode::Encode, bincode::Decode,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackStatus {
+    /// the object was seen in too few frames to be trusted yet
+    #[default]
+    Tentative,
+    /// the object is tracked
+    Confirmed,
+    /// the object was not seen in the last frames, its box is predicted
+    Lost,
+}
+
+/// An object tracked across frames
+#[derive(Clone, Debug, Default, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct Track {
+    /// the identifier of the object, stable across frames
+    pub track_id: u64,
+    pub label: String,
+    /// the filtered box as `[x_min, y_min, x_max, y_max]` normalized to the image size
+    pub bbox: [f32; 4],
+    /// the velocity of the box center in normalized units per second
+    pub velocity: [f32; 2],
+    pub status: TrackStatus,
+    /// the number of frames the object was detected in
+    pub hits: u32,
+    /// the time since the object was first seen
+    pub age_ms: u64,
+    /// the time since the object was last seen
+    pub lost_ms: u64,
+}
+
+/// The tracks of a channel after a frame of detections
+#[derive(Clone, Debug, Default, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct TracksMsg {
+    pub stamp_ns: u64,
+    pub channel_id: u8,
+    pub tracks: Vec<Track>,
 }
@@ -1,4 +1,4 @@
-use crate::pipeline::PipelineResult;
+use crate::pipeline::{report_pipeline_exit, PipelineResult};
 use cu29::prelude::*;
 use cu29_helpers::basic_copper_setup;
 use std::{
@@ -13,6 +13,9 @@ const SLAB_SIZE: Option<usize> = Some(150 * 1024 * 1024);
 #[copper_runtime(config = "src/cu29/pipelines/inference.ron")]
 struct InferenceApp {}
 
+// NOTE: keep in sync with the config file used by the copper runtime above
+pub(crate) const PIPELINE_CONFIG: &str = include_str!("inference.ron");
+
 pub struct InferencePipeline(pub InferenceApp);
 
 impl InferencePipeline {
@@ -45,25 +48,29 @@ impl InferencePipeline {
 pub fn spawn_inference_pipeline(
     stop_signal: Arc<AtomicBool>,
 ) -> std::thread::JoinHandle<PipelineResult> {
-    std::thread::spawn({
-        move || -> PipelineResult {
-            // parse the ron config string and create the pipeline
-            let mut app = InferencePipeline::new()?;
+    std::thread::spawn(move || {
+        let result = run_pipeline(&stop_signal);
+        report_pipeline_exit("inference", result)
+    })
+}
 
-            // create the pipeline and start the tasks
-            app.start_all_tasks()?;
+/// Runs the pipeline until the stop signal is set
+fn run_pipeline(stop_signal: &AtomicBool) -> PipelineResult {
+    // parse the ron config string and create the pipeline
+    let mut app = InferencePipeline::new()?;
 
-            while !stop_signal.load(std::sync::atomic::Ordering::Relaxed) {
-                // we run the pipeline iteration step by step
-                app.run_one_iteration()?;
-            }
+    // create the pipeline and start the tasks
+    app.start_all_tasks()?;
 
-            // stop the pipeline and wait for the tasks to finish
-            app.stop_all_tasks()?;
+    while !stop_signal.load(std::sync::atomic::Ordering::Relaxed) {
+        // we run the pipeline iteration step by step
+        app.run_one_iteration()?;
+    }
 
-            Ok(())
-        }
-    })
+    // stop the pipeline and wait for the tasks to finish
+    app.stop_all_tasks()?;
+
+    Ok(())
 }
 
 impl std::ops::Deref for InferencePipeline {
@@ -4,3 +4,12 @@ pub use cameras::spawn_cameras_pipeline;
 // EXPERIMENTAL
 mod inference;
 pub use inference::spawn_inference_pipeline;
+
+/// Returns the ron config string the given pipeline was compiled with
+pub fn pipeline_config(name: &str) -> Option<&'static str> {
+    match name {
+        "cameras" => Some(cameras::PIPELINE_CONFIG),
+        "inference" => Some(inference::PIPELINE_CONFIG),
+        _ => None,
+    }
+}
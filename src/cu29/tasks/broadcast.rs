@@ -1,31 +1,44 @@
 use crate::{
+    alerts, analytics,
     api::models::inference::InferenceResult,
-    cu29::msgs::{EncodedImage, PromptResponseMsg},
+    cu29::msgs::{DetectionsMsg, EncodedImage, PromptResponseMsg, TracksMsg},
     pipeline::SERVER_GLOBAL_STATE,
 };
 use cu29::prelude::*;
 
-pub struct ImageBroadcast;
+pub struct ImageBroadcast {
+    // the channel to publish on instead of the channel of the image, e.g. for annotated streams
+    channel_id: Option<u8>,
+}
 
 impl Freezable for ImageBroadcast {}
 
 impl<'cl> CuSinkTask<'cl> for ImageBroadcast {
     type Input = input_msg!('cl, EncodedImage);
 
-    fn new(_config: Option<&ComponentConfig>) -> Result<Self, CuError>
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError>
     where
         Self: Sized,
     {
-        Ok(Self {})
+        let channel_id = config.and_then(|config| config.get::<u8>("channel_id"));
+        Ok(Self { channel_id })
     }
 
     fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> Result<(), CuError> {
         // broadcast the image
         if let Some(msg) = input.payload() {
+            let mut msg = msg.clone();
+            msg.channel_id = self.channel_id.unwrap_or(msg.channel_id);
+            SERVER_GLOBAL_STATE
+                .result_store
+                .latest_images
+                .lock()
+                .unwrap()
+                .insert(msg.channel_id, msg.clone());
             // send the camera image to the global state
             let _ = SERVER_GLOBAL_STATE.result_store.images[msg.channel_id as usize]
                 .tx
-                .send(msg.clone());
+                .send(msg);
         }
         Ok(())
     }
@@ -47,14 +60,106 @@ impl<'cl> CuSinkTask<'cl> for InferenceBroadcast {
             return Ok(());
         };
 
+        let result = InferenceResult {
+            stamp_ns: prompt.stamp_ns,
+            channel_id: prompt.channel_id,
+            prompt: prompt.prompt.clone(),
+            response: prompt.response.clone(),
+            params: prompt.params.clone(),
+            latency_ms: prompt.latency_ms,
+            model: prompt.model.clone(),
+        };
+
+        // keep every result, the broadcast channel only holds the latest ones
+        if let Err(e) = SERVER_GLOBAL_STATE
+            .result_store
+            .inference_history
+            .insert(&result)
+        {
+            log::warn!("Failed to store inference result in the history: {}", e);
+        }
+
+        // the alert rules are evaluated as the results come out of the inference
+        alerts::fire_alerts(
+            &SERVER_GLOBAL_STATE.result_store,
+            &result,
+            prompt.frame.as_ref(),
+        );
+
         let _ = SERVER_GLOBAL_STATE.result_store.inference[prompt.channel_id as usize]
             .tx
-            .send(InferenceResult {
-                stamp_ns: prompt.stamp_ns,
-                channel_id: prompt.channel_id,
-                prompt: prompt.prompt.clone(),
-                response: prompt.response.clone(),
-            });
+            .send(result);
+
+        Ok(())
+    }
+}
+
+pub struct DetectionBroadcast;
+
+impl Freezable for DetectionBroadcast {}
+
+impl<'cl> CuSinkTask<'cl> for DetectionBroadcast {
+    type Input = input_msg!('cl, DetectionsMsg);
+
+    fn new(_config: Option<&ComponentConfig>) -> Result<Self, CuError> {
+        Ok(Self {})
+    }
+
+    fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> Result<(), CuError> {
+        let Some(msg) = input.payload() else {
+            return Ok(());
+        };
+
+        let _ = SERVER_GLOBAL_STATE.result_store.detections[msg.channel_id as usize]
+            .tx
+            .send(msg.clone());
+
+        Ok(())
+    }
+}
+
+pub struct TrackBroadcast;
+
+impl Freezable for TrackBroadcast {}
+
+impl<'cl> CuSinkTask<'cl> for TrackBroadcast {
+    type Input = input_msg!('cl, TracksMsg);
+
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError> {
+        // the zones of the pipeline config, more can be added via the api
+        if let Some(zones_path) = config.and_then(|config| config.get::<String>("zones_path")) {
+            let zones = analytics::load_zones(std::path::Path::new(&zones_path))
+                .map_err(|e| CuError::new_with_cause("Failed to load zones", e))?;
+            for zone in zones {
+                let zone_id = zone.id.clone();
+                // NOTE: the zones of a restarted pipeline keep counting from where they were
+                let is_added = SERVER_GLOBAL_STATE
+                    .result_store
+                    .analytics
+                    .add_zone_if_absent(zone)
+                    .map_err(|e| {
+                        CuError::new_with_cause(&format!("Invalid zone {}", zone_id), e)
+                    })?;
+                if !is_added {
+                    log::debug!("Keeping the existing zone {} and its counters", zone_id);
+                }
+            }
+        }
+
+        Ok(Self {})
+    }
+
+    fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> Result<(), CuError> {
+        let Some(msg) = input.payload() else {
+            return Ok(());
+        };
+
+        // the zones are evaluated as the tracks come out of the tracker
+        analytics::update_zones(&SERVER_GLOBAL_STATE.result_store, msg);
+
+        let _ = SERVER_GLOBAL_STATE.result_store.tracks[msg.channel_id as usize]
+            .tx
+            .send(msg.clone());
 
         Ok(())
     }
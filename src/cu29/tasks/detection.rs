@@ -0,0 +1,55 @@
+use crate::{
+    cu29::msgs::{DetectionsMsg, PromptResponseMsg},
+    inference::{is_detection_prompt, parse_detections},
+};
+use cu29::prelude::*;
+
+/// Task that turns the detection and segmentation responses of the model into detections
+///
+/// The segmentation responses give the box and the raw `seg_codes` of each object, the masks
+/// are not decoded.
+pub struct DetectionParser;
+
+impl Freezable for DetectionParser {}
+
+impl<'cl> CuTask<'cl> for DetectionParser {
+    type Input = input_msg!('cl, PromptResponseMsg);
+    type Output = output_msg!('cl, DetectionsMsg);
+
+    fn new(_config: Option<&ComponentConfig>) -> Result<Self, CuError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {})
+    }
+
+    fn process(
+        &mut self,
+        _clock: &RobotClock,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> Result<(), CuError> {
+        // clear the output payload to avoid any previous payload to be forwarded
+        output.clear_payload();
+
+        let Some(msg) = input.payload() else {
+            return Ok(());
+        };
+
+        let detections = parse_detections(&msg.response);
+
+        // NOTE: an empty result of a detection prompt means that nothing was found
+        if detections.is_empty() && !is_detection_prompt(&msg.prompt) {
+            return Ok(());
+        }
+
+        output.set_payload(DetectionsMsg {
+            stamp_ns: msg.stamp_ns,
+            channel_id: msg.channel_id,
+            prompt: msg.prompt.clone(),
+            detections,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{
+    cu29::msgs::{DetectionsMsg, ImageRgb8Msg},
+    inference::{DetectorConfig, OnnxDetector},
+};
+use cu29::prelude::*;
+
+/// Task that runs a lightweight ONNX object detector on every frame on CPU
+pub struct ObjectDetector {
+    detector: OnnxDetector,
+    // the prompt reported with the detections, e.g. `detect person ; car`
+    prompt: String,
+}
+
+impl Freezable for ObjectDetector {}
+
+impl<'cl> CuTask<'cl> for ObjectDetector {
+    type Input = input_msg!('cl, ImageRgb8Msg);
+    type Output = output_msg!('cl, DetectionsMsg);
+
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError>
+    where
+        Self: Sized,
+    {
+        let config = config.expect("config is required");
+
+        let model_path = config
+            .get::<String>("model_path")
+            .ok_or_else(|| CuError::from("model_path is required"))?;
+
+        let mut detector_config = DetectorConfig::new(model_path);
+        if let Some(input_size) = config.get::<u32>("input_size") {
+            detector_config.input_size = input_size as usize;
+        }
+        if let Some(score_threshold) = config.get::<f64>("score_threshold") {
+            detector_config.score_threshold = score_threshold as f32;
+        }
+        if let Some(iou_threshold) = config.get::<f64>("iou_threshold") {
+            detector_config.iou_threshold = iou_threshold as f32;
+        }
+        if let Some(labels_path) = config.get::<String>("labels_path") {
+            // one label per line, in the order of the classes of the model
+            detector_config.labels = std::fs::read_to_string(&labels_path)
+                .map_err(|e| CuError::new_with_cause("Failed to read labels", e))?
+                .lines()
+                .map(|line| line.trim().to_string())
+                .collect();
+        }
+        if let Some(classes) = config.get::<String>("classes") {
+            detector_config.classes = classes
+                .split(',')
+                .map(|class| class.trim().to_string())
+                .filter(|class| !class.is_empty())
+                .collect();
+        }
+
+        let prompt = if detector_config.classes.is_empty() {
+            "detect".to_string()
+        } else {
+            format!("detect {}", detector_config.classes.join(" ; "))
+        };
+
+        log::debug!(
+            "Loading object detector {} -- {}",
+            detector_config.model_path.display(),
+            prompt
+        );
+
+        let detector = OnnxDetector::new(detector_config)
+            .map_err(|e| CuError::new_with_cause("Failed to load object detector", e))?;
+
+        Ok(Self { detector, prompt })
+    }
+
+    fn process(
+        &mut self,
+        _clock: &RobotClock,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> Result<(), CuError> {
+        // clear the output payload to avoid any previous payload to be forwarded
+        output.clear_payload();
+
+        let Some(img) = input.payload() else {
+            return Ok(());
+        };
+
+        // NOTE: a failed frame is skipped instead of stopping the pipeline
+        let detections = match self.detector.detect(&img.image) {
+            Ok(detections) => detections,
+            Err(e) => {
+                log::error!(
+                    "Failed to detect objects on channel {}: {}",
+                    img.channel_id,
+                    e
+                );
+                return Ok(());
+            }
+        };
+
+        output.set_payload(DetectionsMsg {
+            stamp_ns: img.stamp_ns,
+            channel_id: img.channel_id,
+            prompt: self.prompt.clone(),
+            detections,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    api::models::search::{EmbeddingInput, EmbeddingQuery},
+    cu29::msgs::ImageRgb8Msg,
+    inference::{create_embedding_backend, EmbeddingConfig},
+    pipeline::SERVER_GLOBAL_STATE,
+};
+use cu29::prelude::*;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// The default interval between two embedded frames of a channel
+const DEFAULT_INTERVAL_MS: u32 = 5000;
+
+/// The work sent to the embedding thread
+enum EmbeddingJob {
+    /// a sampled frame with its wall time, stored in the index
+    Frame(ImageRgb8Msg, u64),
+    /// a search query, sent back to the server
+    Query(EmbeddingQuery),
+}
+
+/// Task that embeds sampled frames into the search index and answers the search queries
+pub struct FrameEmbedder {
+    // the minimum time between two embedded frames of a channel
+    interval: Duration,
+    last_sampled: HashMap<u8, Instant>,
+    is_busy: Arc<AtomicBool>,
+    job_tx: Option<Sender<EmbeddingJob>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Freezable for FrameEmbedder {}
+
+impl<'cl> CuSinkTask<'cl> for FrameEmbedder {
+    type Input = input_msg!('cl, ImageRgb8Msg);
+
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError>
+    where
+        Self: Sized,
+    {
+        let embedding_config = EmbeddingConfig::from_component_config(config)
+            .map_err(|e| CuError::new_with_cause("Failed to parse embedding config", e))?;
+        let interval_ms = config
+            .and_then(|config| config.get::<u32>("interval_ms"))
+            .unwrap_or(DEFAULT_INTERVAL_MS);
+
+        log::debug!(
+            "Loading embedding backend: {} -- interval: {} ms",
+            embedding_config.backend,
+            interval_ms
+        );
+
+        let (job_tx, job_rx) = std::sync::mpsc::channel::<EmbeddingJob>();
+        let is_busy = Arc::new(AtomicBool::new(false));
+
+        // NOTE: the model is loaded in the embedding thread to not block the pipeline start
+        let handle = std::thread::spawn({
+            let is_busy = is_busy.clone();
+            move || {
+                let model_name = embedding_config.model_name();
+                let mut backend = match create_embedding_backend(&embedding_config) {
+                    Ok(backend) => backend,
+                    Err(e) => {
+                        log::error!("Failed to load embedding model {}: {}", model_name, e);
+                        return;
+                    }
+                };
+                log::debug!("Loaded embedding model {}", model_name);
+
+                let store = &SERVER_GLOBAL_STATE.result_store;
+                store.embedding_tasks.fetch_add(1, Ordering::Relaxed);
+
+                let index = &store.embedding_index;
+                while let Ok(job) = job_rx.recv() {
+                    match job {
+                        EmbeddingJob::Frame(msg, wall_time_ns) => {
+                            let result = backend.embed_image(&msg.image).map_err(|e| e.to_string());
+                            let result = result.and_then(|vector| {
+                                index
+                                    .insert(
+                                        wall_time_ns,
+                                        msg.stamp_ns,
+                                        msg.channel_id,
+                                        &model_name,
+                                        vector,
+                                    )
+                                    .map_err(|e| e.to_string())
+                            });
+                            if let Err(e) = result {
+                                log::warn!(
+                                    "Failed to index frame of channel {}: {}",
+                                    msg.channel_id,
+                                    e
+                                );
+                            }
+                        }
+                        // the queries get the errors back instead of stopping the thread
+                        EmbeddingJob::Query(query) => {
+                            let vector = match &query.input {
+                                EmbeddingInput::Text(text) => backend.embed_text(text),
+                                EmbeddingInput::Image(image) => backend.embed_image(image),
+                            };
+                            let _ = query.reply.send(
+                                vector
+                                    .map(|vector| (model_name.clone(), vector))
+                                    .map_err(|e| e.to_string()),
+                            );
+                        }
+                    }
+                    is_busy.store(false, Ordering::Relaxed);
+                }
+
+                store.embedding_tasks.fetch_sub(1, Ordering::Relaxed);
+            }
+        });
+
+        Ok(Self {
+            interval: Duration::from_millis(interval_ms as u64),
+            last_sampled: HashMap::new(),
+            is_busy,
+            job_tx: Some(job_tx),
+            handle: Some(handle),
+        })
+    }
+
+    fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> Result<(), CuError> {
+        // the thread stops if the model failed to load, leave the queries to other tasks
+        let is_running = self
+            .handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished());
+        if !is_running || self.is_busy.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        // the search queries are answered before the frames are sampled
+        let job = if let Some(query) = try_recv_query() {
+            EmbeddingJob::Query(query)
+        } else {
+            let Some(msg) = input.payload() else {
+                return Ok(());
+            };
+            let is_due = self
+                .last_sampled
+                .get(&msg.channel_id)
+                .is_none_or(|last| last.elapsed() >= self.interval);
+            if !is_due {
+                return Ok(());
+            }
+            self.last_sampled.insert(msg.channel_id, Instant::now());
+
+            // the frame is searched by the time it was sampled, not when it was embedded
+            let wall_time_ns = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as u64;
+
+            // TODO: verify that we are not doing a deep copy of the image
+            EmbeddingJob::Frame(msg.clone(), wall_time_ns)
+        };
+
+        if let Some(job_tx) = &self.job_tx {
+            self.is_busy.store(true, Ordering::Relaxed);
+            if job_tx.send(job).is_err() {
+                self.is_busy.store(false, Ordering::Relaxed);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for FrameEmbedder {
+    fn drop(&mut self) {
+        // closing the channel stops the embedding thread
+        self.job_tx.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn try_recv_query() -> Option<EmbeddingQuery> {
+    let rx = SERVER_GLOBAL_STATE
+        .result_store
+        .embedding_queries
+        .rx
+        .lock()
+        .unwrap();
+    // skip the queries that timed out before being picked
+    rx.try_iter().find(|query| !query.reply.is_closed())
+}
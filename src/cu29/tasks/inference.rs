@@ -1,46 +1,307 @@
 use crate::{
-    cu29::msgs::{ImageRgb8Msg, PromptResponseMsg},
+    api::models::inference::{
+        InferenceModelRequest, InferenceQuery, InferenceQueryResponse, InferenceResult, ModelInfo,
+        ModelSlot, ModelStatus,
+    },
+    cu29::msgs::{EncodedImage, ImageRgb8Msg, PromptResponseMsg},
+    inference::{
+        create_backend, resize_input, BackendConfig, ChannelScheduler, GenerationParams,
+        InferenceError, SchedulingConfig,
+    },
     pipeline::SERVER_GLOBAL_STATE,
 };
 use cu29::prelude::*;
-use kornia_paligemma::{Paligemma, PaligemmaConfig, PaligemmaError};
+use kornia::io::jpeg::ImageEncoder;
 use std::{
     sync::{
-        atomic::AtomicBool,
+        atomic::{AtomicBool, AtomicUsize},
         mpsc::{Receiver, Sender},
         Arc, Mutex,
     },
     thread::JoinHandle,
+    time::Instant,
 };
 
-/// The default prompt to use if no prompt is provided
-// NOTE: check the original prompt instructions
-// https://ai.google.dev/gemma/docs/paligemma/prompt-system-instructions
-const DEFAULT_PROMPT: &str = "cap en";
+/// The counter naming the inference tasks without a `task_id` in their config
+static NEXT_TASK_ID: AtomicUsize = AtomicUsize::new(0);
 
-/// Task that runs inference on an image
-pub struct Inference {
-    current_prompt: String,
+/// The inference worker shared by the single and multi-channel inference tasks
+struct InferenceCore {
+    // the id of the task in the model registry of the server
+    task_id: String,
+    // the generation parameters of the task config
+    params: GenerationParams,
     scheduler: InferenceScheduler,
+    // the model loading in the background to replace the current one
+    pending: Option<InferenceScheduler>,
+    // the requests to swap the model sent through the api
+    model_swaps: tokio::sync::broadcast::Receiver<InferenceModelRequest>,
+    // the frames waiting for the inference worker
+    queue: ChannelScheduler<ImageRgb8Msg>,
+}
+
+impl InferenceCore {
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError> {
+        let backend_config = BackendConfig::from_component_config(config)
+            .map_err(|e| CuError::new_with_cause("Failed to parse inference config", e))?;
+        let scheduling_config = SchedulingConfig::from_component_config(config)
+            .map_err(|e| CuError::new_with_cause("Failed to parse scheduling config", e))?;
+
+        log::debug!(
+            "Loading inference backend: {} -- scheduling: {:?}",
+            backend_config.backend,
+            scheduling_config
+        );
+
+        let task_id = config
+            .and_then(|config| config.get::<String>("task_id"))
+            .unwrap_or_else(|| {
+                format!(
+                    "inference_{}",
+                    NEXT_TASK_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                )
+            });
+
+        // NOTE: the model is loaded in the inference thread to not block the pipeline start
+        let scheduler =
+            InferenceScheduler::new(&task_id, ModelSlot::Active, backend_config.clone());
+
+        Ok(Self {
+            task_id,
+            params: backend_config.params,
+            scheduler,
+            pending: None,
+            // every inference task subscribes to the swaps so that all of them receive them
+            model_swaps: SERVER_GLOBAL_STATE
+                .result_store
+                .inference_model_swaps
+                .tx
+                .subscribe(),
+            queue: ChannelScheduler::new(scheduling_config),
+        })
+    }
+
+    fn process(
+        &mut self,
+        clock: &RobotClock,
+        images: &[Option<&ImageRgb8Msg>],
+    ) -> Option<PromptResponseMsg> {
+        // drop the frames until the model is loaded
+        if !self.scheduler.is_ready() {
+            self.poll_model_swap();
+            return None;
+        }
+
+        // queue the new frames, the scheduling config decides which ones are kept
+        // NOTE: the frames are only copied if they can be picked by the worker
+        let now = Instant::now();
+        let is_busy = self.scheduler.is_processing();
+        for img in images.iter().flatten() {
+            self.queue
+                .offer(img.channel_id, now, is_busy, || (*img).clone());
+        }
+
+        // check if we are already processing an inference to not block the main thread
+        if is_busy {
+            self.publish_stats();
+            return None;
+        }
+
+        // check first if we have a response from the previous inference
+        let response = self.scheduler.try_poll_response().map(|reply| {
+            log::debug!(
+                "Received response from inference thread for channel: {} -- prompt: {} -- response: {}",
+                reply.channel_id,
+                reply.prompt,
+                reply.response
+            );
+
+            PromptResponseMsg {
+                stamp_ns: clock.now().as_nanos(),
+                channel_id: reply.channel_id,
+                prompt: reply.prompt,
+                response: reply.response,
+                params: reply.params,
+                latency_ms: reply.latency_ms,
+                model: reply.model,
+                frame: reply.frame,
+            }
+        });
+
+        // switch to the new model between two inferences, once it is loaded
+        self.poll_model_swap();
+
+        // the on-demand queries take precedence over the camera frames
+        if let Some(query) = try_recv_query() {
+            let (prompt, params) = self.settings(query.channel_id, query.prompt);
+
+            let img = ImageRgb8Msg {
+                stamp_ns: clock.now().as_nanos(),
+                channel_id: query.channel_id.unwrap_or_default(),
+                image: query.image,
+            };
+
+            // only the frames of a channel are kept in its history
+            let reply = QueryReply {
+                history_channel: query.channel_id.filter(|_| !query.uploaded),
+                sender: query.reply,
+            };
+            self.scheduler
+                .schedule_inference(img, &prompt, &params, Some(reply));
+        }
+        // pick the next frame to run the inference on
+        else if let Some((channel_id, img)) = self.queue.next(now) {
+            let (prompt, params) = self.settings(Some(channel_id), None);
+
+            // send the request to the thread to schedule the inference
+            self.scheduler
+                .schedule_inference(img, &prompt, &params, None);
+        }
+
+        self.publish_stats();
+
+        response
+    }
+
+    /// Start loading the requested models and switch to them once they are ready
+    fn poll_model_swap(&mut self) {
+        loop {
+            match self.model_swaps.try_recv() {
+                Ok(request) => {
+                    if self.pending.is_some() {
+                        log::warn!(
+                            "Ignoring model {}, another model is already loading",
+                            request.backend
+                        );
+                        continue;
+                    }
+                    let config = BackendConfig {
+                        backend: request.backend,
+                        params: self.params.clone(),
+                        model: request.model,
+                        mock: request.mock,
+                    };
+                    log::debug!(
+                        "Loading inference model {} to replace {}",
+                        config.model_name(),
+                        self.scheduler.model_name
+                    );
+                    self.pending = Some(InferenceScheduler::new(
+                        &self.task_id,
+                        ModelSlot::Pending,
+                        config,
+                    ));
+                }
+                Err(tokio::sync::broadcast::error::TryRecvError::Lagged(n)) => {
+                    log::warn!("Inference lagged behind, {} model swaps were dropped", n);
+                }
+                Err(_) => break,
+            }
+        }
+
+        let Some(pending) = self.pending.as_ref() else {
+            return;
+        };
+
+        match pending.status() {
+            ModelStatus::Loading => {}
+            ModelStatus::Ready => {
+                // NOTE: the current model is not processing, so no response is lost
+                let Some(pending) = self.pending.take() else {
+                    return;
+                };
+                let mut previous = std::mem::replace(&mut self.scheduler, pending);
+                previous.stop();
+
+                // the new model only takes the active slot now, so one stays ready meanwhile
+                set_model_status(
+                    &self.task_id,
+                    ModelSlot::Active,
+                    &self.scheduler.model_name,
+                    &self.scheduler.backend,
+                    ModelStatus::Ready,
+                );
+                remove_model_status(&self.task_id, ModelSlot::Pending);
+                log::info!(
+                    "Swapped inference model {} for {}",
+                    previous.model_name,
+                    self.scheduler.model_name
+                );
+            }
+            ModelStatus::Failed(e) => {
+                // keep running the current model, the failure stays listed in the pending slot
+                log::error!(
+                    "Failed to swap inference model {}: {}",
+                    pending.model_name,
+                    e
+                );
+                self.pending = None;
+            }
+        }
+    }
+
+    /// The prompt and the generation parameters to run the inference with
+    fn settings(
+        &self,
+        channel_id: Option<u8>,
+        prompt: Option<String>,
+    ) -> (String, GenerationParams) {
+        // the settings are read from the server, so that updates reach all the tasks
+        let settings = SERVER_GLOBAL_STATE
+            .result_store
+            .inference_settings
+            .lock()
+            .unwrap();
+
+        let prompt = prompt.unwrap_or_else(|| match channel_id {
+            Some(channel_id) => settings.prompt(channel_id).to_string(),
+            None => settings.default_prompt.clone(),
+        });
+
+        let params = settings
+            .params
+            .clone()
+            .unwrap_or_else(|| self.params.clone());
+
+        (prompt, params)
+    }
+
+    fn publish_stats(&self) {
+        let mut stats = SERVER_GLOBAL_STATE
+            .result_store
+            .inference_stats
+            .lock()
+            .unwrap();
+        stats.extend(self.queue.stats());
+    }
+}
+
+impl Drop for InferenceCore {
+    fn drop(&mut self) {
+        // the models are unloaded with the task
+        self.scheduler.stop();
+        if let Some(mut pending) = self.pending.take() {
+            pending.stop();
+        }
+        remove_model_status(&self.task_id, ModelSlot::Active);
+        remove_model_status(&self.task_id, ModelSlot::Pending);
+    }
 }
 
+/// Task that runs inference on the images of a single channel
+pub struct Inference(InferenceCore);
+
 impl Freezable for Inference {}
 
 impl<'cl> CuTask<'cl> for Inference {
     type Input = input_msg!('cl, ImageRgb8Msg);
     type Output = output_msg!('cl, PromptResponseMsg);
 
-    fn new(_config: Option<&ComponentConfig>) -> Result<Self, CuError>
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError>
     where
         Self: Sized,
     {
-        let scheduler = InferenceScheduler::new()
-            .map_err(|e| CuError::new_with_cause("Failed to create Paligemma scheduler", e))?;
-
-        Ok(Self {
-            current_prompt: DEFAULT_PROMPT.to_string(),
-            scheduler,
-        })
+        Ok(Self(InferenceCore::new(config)?))
     }
 
     fn process(
@@ -52,82 +313,353 @@ impl<'cl> CuTask<'cl> for Inference {
         // clear the output payload to avoid any previous payload to be forwarded
         output.clear_payload();
 
-        // check first if we should update the prompt
-        if let Ok(prompt) = SERVER_GLOBAL_STATE
-            .result_store
-            .inference_settings
-            .rx
-            .lock()
-            .unwrap()
-            .try_recv()
-        {
-            log::debug!("Updating prompt to: {}", prompt);
-            self.current_prompt = prompt;
+        if let Some(msg) = self.0.process(clock, &[input.payload()]) {
+            output.set_payload(msg);
         }
 
-        // check if we are already processing an inference to not block the main thread
-        if self.scheduler.is_processing() {
-            return Ok(());
+        Ok(())
+    }
+}
+
+/// Task that runs inference on the images of two channels with a single model
+pub struct InferenceTwo(InferenceCore);
+
+impl Freezable for InferenceTwo {}
+
+impl<'cl> CuTask<'cl> for InferenceTwo {
+    type Input = input_msg!('cl, ImageRgb8Msg, ImageRgb8Msg);
+    type Output = output_msg!('cl, PromptResponseMsg);
+
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError> {
+        Ok(Self(InferenceCore::new(config)?))
+    }
+
+    fn process(
+        &mut self,
+        clock: &RobotClock,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> Result<(), CuError> {
+        output.clear_payload();
+
+        let (msg1, msg2) = input;
+        if let Some(msg) = self.0.process(clock, &[msg1.payload(), msg2.payload()]) {
+            output.set_payload(msg);
         }
 
-        // check first if we have a response from the previous inference
-        if let Some((channel_id, prompt, response)) = self.scheduler.try_poll_response() {
-            log::debug!(
-                "Received response from inference thread for channel: {} -- prompt: {} -- response: {}",
-                channel_id,
-                prompt,
-                response
-            );
+        Ok(())
+    }
+}
 
-            output.set_payload(PromptResponseMsg {
-                stamp_ns: clock.now().as_nanos(),
-                channel_id,
-                prompt,
-                response,
-            });
+/// Task that runs inference on the images of three channels with a single model
+pub struct InferenceThree(InferenceCore);
+
+impl Freezable for InferenceThree {}
+
+impl<'cl> CuTask<'cl> for InferenceThree {
+    type Input = input_msg!('cl, ImageRgb8Msg, ImageRgb8Msg, ImageRgb8Msg);
+    type Output = output_msg!('cl, PromptResponseMsg);
+
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError> {
+        Ok(Self(InferenceCore::new(config)?))
+    }
+
+    fn process(
+        &mut self,
+        clock: &RobotClock,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> Result<(), CuError> {
+        output.clear_payload();
+
+        let (msg1, msg2, msg3) = input;
+        if let Some(msg) = self
+            .0
+            .process(clock, &[msg1.payload(), msg2.payload(), msg3.payload()])
+        {
+            output.set_payload(msg);
         }
 
-        // check if we have a new image and schedule the inference
-        let Some(img) = input.payload() else {
-            return Ok(());
-        };
+        Ok(())
+    }
+}
+
+/// Task that runs inference on the images of four channels with a single model
+pub struct InferenceFour(InferenceCore);
 
-        // send the request to the thread to schedule the inference
-        self.scheduler.schedule_inference(img, &self.current_prompt);
+impl Freezable for InferenceFour {}
+
+impl<'cl> CuTask<'cl> for InferenceFour {
+    type Input = input_msg!('cl, ImageRgb8Msg, ImageRgb8Msg, ImageRgb8Msg, ImageRgb8Msg);
+    type Output = output_msg!('cl, PromptResponseMsg);
+
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError> {
+        Ok(Self(InferenceCore::new(config)?))
+    }
+
+    fn process(
+        &mut self,
+        clock: &RobotClock,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> Result<(), CuError> {
+        output.clear_payload();
+
+        let (msg1, msg2, msg3, msg4) = input;
+        if let Some(msg) = self.0.process(
+            clock,
+            &[
+                msg1.payload(),
+                msg2.payload(),
+                msg3.payload(),
+                msg4.payload(),
+            ],
+        ) {
+            output.set_payload(msg);
+        }
 
         Ok(())
     }
 }
 
+/// Take the next on-demand query whose requester is still waiting
+fn try_recv_query() -> Option<InferenceQuery> {
+    let rx = SERVER_GLOBAL_STATE
+        .result_store
+        .inference_queries
+        .rx
+        .lock()
+        .unwrap();
+    // skip the queries that timed out before being picked
+    rx.try_iter().find(|query| !query.reply.is_closed())
+}
+
+/// Report the loading status of the model in a slot of a task to the server
+fn set_model_status(
+    task_id: &str,
+    slot: ModelSlot,
+    name: &str,
+    backend: &str,
+    status: ModelStatus,
+) {
+    SERVER_GLOBAL_STATE
+        .result_store
+        .inference_models
+        .lock()
+        .unwrap()
+        .insert(
+            (task_id.to_string(), slot),
+            ModelInfo {
+                task_id: task_id.to_string(),
+                slot,
+                name: name.to_string(),
+                backend: backend.to_string(),
+                status,
+            },
+        );
+}
+
+/// Encode a frame to jpeg, creating the encoder on the first call
+fn encode_frame(encoder: &mut Option<ImageEncoder>, image: &ImageRgb8Msg) -> Option<EncodedImage> {
+    if encoder.is_none() {
+        *encoder = ImageEncoder::new()
+            .map_err(|e| log::warn!("Failed to create jpeg encoder: {}", e))
+            .ok();
+    }
+    let data = encoder
+        .as_mut()?
+        .encode(&image.image)
+        .map_err(|e| log::warn!("Failed to encode inference frame: {}", e))
+        .ok()?;
+    Some(EncodedImage {
+        stamp_ns: image.stamp_ns,
+        channel_id: image.channel_id,
+        data,
+        encoding: "jpeg".to_string(),
+    })
+}
+
+/// Store the response of an on-demand query in the inference history of a channel
+fn store_query_result(stamp_ns: u64, channel_id: u8, response: &InferenceQueryResponse) {
+    let result = InferenceResult {
+        stamp_ns,
+        channel_id,
+        prompt: response.prompt.clone(),
+        response: response.response.clone(),
+        params: response.params.clone(),
+        latency_ms: response.latency_ms,
+        model: response.model.clone(),
+    };
+    if let Err(e) = SERVER_GLOBAL_STATE
+        .result_store
+        .inference_history
+        .insert(&result)
+    {
+        log::warn!("Failed to store query result in the history: {}", e);
+    }
+}
+
+fn remove_model_status(task_id: &str, slot: ModelSlot) {
+    SERVER_GLOBAL_STATE
+        .result_store
+        .inference_models
+        .lock()
+        .unwrap()
+        .remove(&(task_id.to_string(), slot));
+}
+
+/// A request to the inference thread
+struct InferenceJob {
+    image: ImageRgb8Msg,
+    prompt: String,
+    params: GenerationParams,
+    // the channel to reply to an on-demand query instead of the pipeline
+    reply: Option<QueryReply>,
+}
+
+/// The reply of an on-demand query
+struct QueryReply {
+    // the channel to store the result in, none for the uploaded images
+    history_channel: Option<u8>,
+    sender: tokio::sync::oneshot::Sender<Result<InferenceQueryResponse, String>>,
+}
+
+/// A response from the inference thread
+struct InferenceReply {
+    channel_id: u8,
+    prompt: String,
+    response: String,
+    params: GenerationParams,
+    latency_ms: u64,
+    model: String,
+    // the jpeg frame of the inference for the alerts
+    frame: Option<EncodedImage>,
+}
+
 struct InferenceScheduler {
+    // the name of the model in the server registry
+    model_name: String,
+    backend: String,
+    // the loading status of the model, shared with the inference thread
+    status: Arc<Mutex<ModelStatus>>,
     is_processing: Arc<Mutex<AtomicBool>>,
-    req_tx: Option<Sender<(ImageRgb8Msg, String)>>,
-    rep_rx: Receiver<(u8, String, String)>,
-    inference_handle: Option<JoinHandle<Result<(), PaligemmaError>>>,
+    req_tx: Option<Sender<InferenceJob>>,
+    rep_rx: Receiver<InferenceReply>,
+    inference_handle: Option<JoinHandle<Result<(), InferenceError>>>,
 }
 
 impl InferenceScheduler {
-    pub fn new() -> Result<Self, PaligemmaError> {
-        // NOTE: in future should be able to schedule multiple models
-        let mut paligemma = Paligemma::new(PaligemmaConfig::default())?;
+    pub fn new(task_id: &str, slot: ModelSlot, config: BackendConfig) -> Self {
+        let (req_tx, req_rx) = std::sync::mpsc::channel::<InferenceJob>();
+        let (rep_tx, rep_rx) = std::sync::mpsc::channel::<InferenceReply>();
 
-        let (req_tx, req_rx) = std::sync::mpsc::channel::<(ImageRgb8Msg, String)>();
-        let (rep_tx, rep_rx) = std::sync::mpsc::channel::<(u8, String, String)>();
+        let model_name = config.model_name();
+        let backend = config.backend.clone();
+        set_model_status(task_id, slot, &model_name, &backend, ModelStatus::Loading);
 
+        let status = Arc::new(Mutex::new(ModelStatus::Loading));
         let is_processing = Arc::new(Mutex::new(AtomicBool::new(false)));
 
         let inference_handle = std::thread::spawn({
+            let task_id = task_id.to_string();
+            let model_name = model_name.clone();
+            let status = status.clone();
             let is_processing = is_processing.clone();
-            move || -> Result<(), PaligemmaError> {
+            move || -> Result<(), InferenceError> {
+                let start = Instant::now();
+                let mut backend = match create_backend(&config) {
+                    Ok(backend) => backend,
+                    Err(e) => {
+                        log::error!("Failed to load inference model {}: {}", model_name, e);
+                        let failed = ModelStatus::Failed(e.to_string());
+                        set_model_status(
+                            &task_id,
+                            slot,
+                            &model_name,
+                            &config.backend,
+                            failed.clone(),
+                        );
+                        *status.lock().unwrap() = failed;
+                        return Err(e);
+                    }
+                };
+
+                log::debug!(
+                    "Loaded inference model {} in {} ms",
+                    model_name,
+                    start.elapsed().as_millis()
+                );
+                set_model_status(
+                    &task_id,
+                    slot,
+                    &model_name,
+                    &config.backend,
+                    ModelStatus::Ready,
+                );
+                *status.lock().unwrap() = ModelStatus::Ready;
+
+                // created on the first frame kept for the alerts
+                let mut encoder = None;
+
                 // block the thread until the inference is stopped
-                while let Ok((img_msg, prompt)) = req_rx.recv() {
+                while let Ok(job) = req_rx.recv() {
                     log::trace!("Scheduling a new inference");
 
-                    let response = paligemma.inference(&img_msg.image, &prompt, 50, false)?;
+                    let start = Instant::now();
+                    let output = resize_input(&job.image.image, &job.params)
+                        .and_then(|image| backend.infer(&image, &job.prompt, &job.params));
+                    let latency_ms = start.elapsed().as_millis() as u64;
+
+                    log::trace!("Inference completed in {} ms", latency_ms);
+
+                    match job.reply {
+                        // the queries get the errors back instead of stopping the thread
+                        Some(reply) => {
+                            let response = output.map(|output| InferenceQueryResponse {
+                                prompt: job.prompt,
+                                response: output.response,
+                                params: job.params,
+                                latency_ms,
+                                model: model_name.clone(),
+                            });
 
-                    log::trace!("Inference completed");
+                            // the queries on the frame of a channel are kept in its history
+                            if let (Ok(response), Some(channel_id)) =
+                                (&response, reply.history_channel)
+                            {
+                                store_query_result(job.image.stamp_ns, channel_id, response);
+                            }
 
-                    let _ = rep_tx.send((img_msg.channel_id, prompt, response));
+                            let _ = reply.sender.send(response.map_err(|e| e.to_string()));
+                        }
+                        // a failed inference only skips the frame, the thread keeps running
+                        None => match output {
+                            Ok(output) => {
+                                // the alerts attach the frame the response was produced from
+                                let frame = if SERVER_GLOBAL_STATE.result_store.alerts.has_rules() {
+                                    encode_frame(&mut encoder, &job.image)
+                                } else {
+                                    None
+                                };
+                                let _ = rep_tx.send(InferenceReply {
+                                    channel_id: job.image.channel_id,
+                                    prompt: job.prompt,
+                                    response: output.response,
+                                    params: job.params,
+                                    latency_ms,
+                                    model: model_name.clone(),
+                                    frame,
+                                });
+                            }
+                            Err(e) => {
+                                log::error!(
+                                    "Inference failed on channel {}: {}",
+                                    job.image.channel_id,
+                                    e
+                                );
+                            }
+                        },
+                    }
                     is_processing
                         .lock()
                         .unwrap()
@@ -137,12 +669,23 @@ impl InferenceScheduler {
             }
         });
 
-        Ok(Self {
+        Self {
+            model_name,
+            backend,
+            status,
             is_processing,
             req_tx: Some(req_tx),
             rep_rx,
             inference_handle: Some(inference_handle),
-        })
+        }
+    }
+
+    pub fn status(&self) -> ModelStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        matches!(self.status(), ModelStatus::Ready)
     }
 
     pub fn is_processing(&self) -> bool {
@@ -152,23 +695,38 @@ impl InferenceScheduler {
             .load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    pub fn try_poll_response(&self) -> Option<(u8, String, String)> {
+    fn try_poll_response(&self) -> Option<InferenceReply> {
         self.rep_rx.try_recv().ok()
     }
 
-    pub fn schedule_inference(&self, img: &ImageRgb8Msg, prompt: &str) {
-        // SAFETY: we are created the channel in the constructor
-        // TODO: verify that we are not doing a deep copy of the image
-        let _ = self
-            .req_tx
-            .as_ref()
-            .unwrap()
-            .send((img.clone(), prompt.to_string()));
-        // set the processing flag to true as we are scheduling an inference
+    pub fn schedule_inference(
+        &self,
+        img: ImageRgb8Msg,
+        prompt: &str,
+        params: &GenerationParams,
+        reply: Option<QueryReply>,
+    ) {
+        // NOTE: set the processing flag before sending, the worker clears it when it is done
+        // and a fast inference could otherwise finish before the flag is set
         self.is_processing
             .lock()
             .unwrap()
             .store(true, std::sync::atomic::Ordering::Relaxed);
+
+        // SAFETY: we are created the channel in the constructor
+        let sent = self.req_tx.as_ref().unwrap().send(InferenceJob {
+            image: img,
+            prompt: prompt.to_string(),
+            params: params.clone(),
+            reply,
+        });
+        if sent.is_err() {
+            log::error!("Failed to schedule inference, the inference thread stopped");
+            self.is_processing
+                .lock()
+                .unwrap()
+                .store(false, std::sync::atomic::Ordering::Relaxed);
+        }
     }
 
     pub fn stop(&mut self) {
@@ -189,3 +747,124 @@ impl Drop for InferenceScheduler {
         self.stop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cu29::msgs::ImageRgb8,
+        inference::{MockConfig, SchedulingConfig},
+    };
+    use std::time::Duration;
+
+    fn mock_scheduler(task_id: &str, latency_ms: u64) -> InferenceScheduler {
+        InferenceScheduler::new(
+            task_id,
+            ModelSlot::Active,
+            BackendConfig {
+                backend: "mock".to_string(),
+                mock: MockConfig {
+                    latency_ms,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+    }
+
+    fn frame(channel_id: u8, stamp_ns: u64) -> ImageRgb8Msg {
+        ImageRgb8Msg {
+            stamp_ns,
+            channel_id,
+            image: ImageRgb8::new([4, 4].into(), vec![0; 4 * 4 * 3]).unwrap(),
+        }
+    }
+
+    /// Poll the condition until it holds or the timeout expires
+    fn wait_until(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        condition()
+    }
+
+    fn wait_reply(scheduler: &InferenceScheduler) -> InferenceReply {
+        let mut reply = None;
+        assert!(
+            wait_until(Duration::from_secs(5), || {
+                reply = scheduler.try_poll_response();
+                reply.is_some()
+            }),
+            "no reply from the inference thread"
+        );
+        reply.unwrap()
+    }
+
+    #[test]
+    fn scheduler_returns_the_mock_responses() {
+        // without latency the worker can finish before `schedule_inference` returns
+        let scheduler = mock_scheduler("test_mock_results", 0);
+        assert!(wait_until(Duration::from_secs(5), || scheduler.is_ready()));
+
+        let params = GenerationParams::default();
+        for i in 0..100 {
+            let prompt = format!("caption {}", i);
+            scheduler.schedule_inference(frame(i % 2, i as u64), &prompt, &params, None);
+
+            let reply = wait_reply(&scheduler);
+            assert_eq!(reply.channel_id, i % 2);
+            assert_eq!(reply.prompt, prompt);
+            assert_eq!(reply.response, prompt);
+            assert_eq!(reply.model, "mock");
+
+            // the worker is free again once the reply is sent
+            assert!(
+                wait_until(Duration::from_secs(1), || !scheduler.is_processing()),
+                "the processing flag is stuck after inference {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn frames_are_dropped_while_the_mock_is_busy() {
+        let scheduler = mock_scheduler("test_mock_busy", 200);
+        assert!(wait_until(Duration::from_secs(5), || scheduler.is_ready()));
+
+        let params = GenerationParams::default();
+        let mut queue = ChannelScheduler::new(SchedulingConfig::default());
+
+        scheduler.schedule_inference(frame(0, 0), "first", &params, None);
+        assert!(scheduler.is_processing());
+
+        // the frames are dropped while the model is busy, a newer one follows anyway
+        for stamp_ns in 1..=3 {
+            let is_busy = scheduler.is_processing();
+            assert!(is_busy);
+            assert!(!queue.offer(0, Instant::now(), is_busy, || frame(0, stamp_ns)));
+        }
+        assert!(scheduler.try_poll_response().is_none());
+        assert!(queue.next(Instant::now()).is_none());
+        assert_eq!(queue.stats()[&0].dropped, 3);
+
+        let reply = wait_reply(&scheduler);
+        assert_eq!(reply.response, "first");
+        assert!(wait_until(Duration::from_secs(1), || !scheduler.is_processing()));
+
+        // the next frame is queued once the model is free
+        assert!(
+            queue.offer(0, Instant::now(), scheduler.is_processing(), || {
+                frame(0, 4)
+            })
+        );
+        let (channel_id, next) = queue.next(Instant::now()).unwrap();
+        assert_eq!((channel_id, next.stamp_ns), (0, 4));
+
+        scheduler.schedule_inference(next, "second", &params, None);
+        assert_eq!(wait_reply(&scheduler).response, "second");
+    }
+}
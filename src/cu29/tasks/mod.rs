@@ -1,15 +1,32 @@
 mod broadcast;
 pub use broadcast::*;
 
+mod detection;
+pub use detection::*;
+
+#[cfg(feature = "onnx")]
+mod detector;
+#[cfg(feature = "onnx")]
+pub use detector::*;
+
+mod embedding;
+pub use embedding::*;
+
 mod image_encoder;
 pub use image_encoder::*;
 
 mod inference;
 pub use inference::*;
 
+mod overlay;
+pub use overlay::*;
+
 mod recorder;
 pub use recorder::*;
 
+mod tracker;
+pub use tracker::*;
+
 mod video_capture;
 pub use video_capture::*;
 
@@ -0,0 +1,284 @@
+use crate::{
+    cu29::msgs::{DetectionsMsg, ImageRgb8, ImageRgb8Msg, PromptResponseMsg},
+    draw::{self, Rgb},
+    inference::is_detection_prompt,
+};
+use cu29::prelude::*;
+use std::collections::HashMap;
+
+/// The colors of the boxes, picked from the label
+const PALETTE: [Rgb; 6] = [
+    [230, 25, 75],
+    [60, 180, 75],
+    [255, 225, 25],
+    [0, 130, 200],
+    [245, 130, 48],
+    [145, 30, 180],
+];
+
+/// The maximum number of lines of the caption banner
+const MAX_CAPTION_LINES: usize = 3;
+
+/// Task that draws the latest detections, caption and a timestamp onto the frames
+pub struct ImageOverlay {
+    // the latest detections per channel
+    detections: HashMap<u8, DetectionsMsg>,
+    // the latest caption per channel
+    captions: HashMap<u8, PromptResponseMsg>,
+    // the results older than this are not drawn anymore
+    max_age_ns: u64,
+    // the size of a font pixel in image pixels
+    font_scale: usize,
+}
+
+impl Freezable for ImageOverlay {}
+
+impl<'cl> CuTask<'cl> for ImageOverlay {
+    type Input = input_msg!('cl, ImageRgb8Msg, DetectionsMsg, PromptResponseMsg);
+    type Output = output_msg!('cl, ImageRgb8Msg);
+
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError>
+    where
+        Self: Sized,
+    {
+        let max_age_ms = config
+            .and_then(|config| config.get::<u32>("max_age_ms"))
+            .unwrap_or(5000);
+        let font_scale = config
+            .and_then(|config| config.get::<u32>("font_scale"))
+            .unwrap_or(2);
+        // the text layout divides by the size of the font pixels
+        if font_scale == 0 {
+            return Err(CuError::from("font_scale must be at least 1"));
+        }
+
+        Ok(Self {
+            detections: HashMap::new(),
+            captions: HashMap::new(),
+            max_age_ns: max_age_ms as u64 * 1_000_000,
+            font_scale: font_scale as usize,
+        })
+    }
+
+    fn process(
+        &mut self,
+        _clock: &RobotClock,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> Result<(), CuError> {
+        // clear the output payload to avoid any previous payload to be forwarded
+        output.clear_payload();
+
+        let (img_msg, detections_msg, caption_msg) = input;
+
+        // keep the latest results, they arrive much less often than the frames
+        if let Some(msg) = detections_msg.payload() {
+            self.detections.insert(msg.channel_id, msg.clone());
+        }
+        if let Some(msg) = caption_msg.payload() {
+            // NOTE: the detection responses are drawn as boxes instead
+            if !is_detection_prompt(&msg.prompt) {
+                self.captions.insert(msg.channel_id, msg.clone());
+            }
+        }
+
+        let Some(msg) = img_msg.payload() else {
+            return Ok(());
+        };
+
+        // TODO: draw in place once copper supports mutable inputs
+        let mut image = msg.image.clone();
+
+        let is_recent = |stamp_ns: u64| msg.stamp_ns.saturating_sub(stamp_ns) <= self.max_age_ns;
+
+        if let Some(detections) = self
+            .detections
+            .get(&msg.channel_id)
+            .filter(|d| is_recent(d.stamp_ns))
+        {
+            self.draw_detections(&mut image, detections);
+        }
+
+        if let Some(caption) = self
+            .captions
+            .get(&msg.channel_id)
+            .filter(|c| is_recent(c.stamp_ns))
+        {
+            self.draw_caption(&mut image, &caption.response);
+        }
+
+        self.draw_timestamp(&mut image, msg.channel_id);
+
+        output.set_payload(ImageRgb8Msg {
+            stamp_ns: msg.stamp_ns,
+            channel_id: msg.channel_id,
+            image,
+        });
+
+        Ok(())
+    }
+}
+
+impl ImageOverlay {
+    fn draw_detections(&self, image: &mut ImageRgb8, msg: &DetectionsMsg) {
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        let thickness = self.font_scale as i64;
+        let (_, text_height) = draw::text_size("", self.font_scale);
+
+        for detection in &msg.detections {
+            let color = label_color(&detection.label);
+            let [x_min, y_min, x_max, y_max] = detection.bbox;
+            let (x0, y0) = ((x_min * width) as i64, (y_min * height) as i64);
+            let (x1, y1) = ((x_max * width) as i64, (y_max * height) as i64);
+
+            draw::draw_rect(image, x0, y0, x1, y1, thickness, color);
+
+            // the label sits on top of the box, or inside if the box touches the top
+            let (text_width, _) = draw::text_size(&detection.label, self.font_scale);
+            let pad = self.font_scale as i64;
+            let label_height = text_height as i64 + 2 * pad;
+            let label_y = if y0 >= label_height {
+                y0 - label_height
+            } else {
+                y0
+            };
+            draw::fill_rect(
+                image,
+                x0,
+                label_y,
+                x0 + text_width as i64 + 2 * pad,
+                label_y + label_height,
+                color,
+            );
+            draw::draw_text(
+                image,
+                x0 + pad,
+                label_y + pad,
+                &detection.label,
+                self.font_scale,
+                [0, 0, 0],
+            );
+        }
+    }
+
+    fn draw_caption(&self, image: &mut ImageRgb8, caption: &str) {
+        let pad = 2 * self.font_scale;
+        let (char_width, line_height) = draw::text_size(" ", self.font_scale);
+        let max_chars = (image.width().saturating_sub(2 * pad) / char_width).max(1);
+
+        let lines = wrap_text(caption.trim(), max_chars, MAX_CAPTION_LINES);
+        if lines.is_empty() {
+            return;
+        }
+
+        // the banner is anchored at the bottom of the image
+        let banner_height = lines.len() * (line_height + pad) + pad;
+        let banner_y = image.height().saturating_sub(banner_height) as i64;
+        draw::fill_rect(
+            image,
+            0,
+            banner_y,
+            image.width() as i64,
+            image.height() as i64,
+            [0, 0, 0],
+        );
+
+        for (i, line) in lines.iter().enumerate() {
+            draw::draw_text(
+                image,
+                pad as i64,
+                banner_y + (pad + i * (line_height + pad)) as i64,
+                line,
+                self.font_scale,
+                [255, 255, 255],
+            );
+        }
+    }
+
+    fn draw_timestamp(&self, image: &mut ImageRgb8, channel_id: u8) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let text = format!("CH{} {}", channel_id, format_utc(now));
+
+        let pad = self.font_scale;
+        let (text_width, text_height) = draw::text_size(&text, self.font_scale);
+        draw::fill_rect(
+            image,
+            0,
+            0,
+            (text_width + 2 * pad) as i64,
+            (text_height + 2 * pad) as i64,
+            [0, 0, 0],
+        );
+        draw::draw_text(
+            image,
+            pad as i64,
+            pad as i64,
+            &text,
+            self.font_scale,
+            [255, 255, 255],
+        );
+    }
+}
+
+/// Pick a stable color for a label
+fn label_color(label: &str) -> Rgb {
+    let hash = label
+        .bytes()
+        .fold(0usize, |acc, b| acc.wrapping_add(b as usize));
+    PALETTE[hash % PALETTE.len()]
+}
+
+/// Split the text into lines of at most `max_chars`, the last line is truncated
+fn wrap_text(text: &str, max_chars: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let word = word.chars().take(max_chars).collect::<String>();
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            let keep = last.chars().count().saturating_sub(3);
+            *last = last.chars().take(keep).collect::<String>() + "...";
+        }
+    }
+
+    lines
+}
+
+/// Format the seconds since the unix epoch as `YYYY-MM-DD HH:MM:SS` in UTC
+fn format_utc(secs: u64) -> String {
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    let (hours, minutes, seconds) = (rem / 3600, rem % 3600 / 60, rem % 60);
+
+    // NOTE: convert the days to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, hours, minutes, seconds
+    )
+}
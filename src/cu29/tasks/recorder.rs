@@ -1,135 +1,328 @@
 use crate::{
-    api::models::recording::RecordingCommand, cu29::msgs::EncodedImage,
+    api::models::{
+        recording::{
+            PipelineManifest, RecordingAnnotation, RecordingCommand, RecordingManifest,
+            RecordingSessionConfig, RecordingSessionStatus, TaskManifest,
+        },
+        webhooks::WebhookEvent,
+    },
+    cu29::{msgs::EncodedImage, pipelines::pipeline_config},
     pipeline::SERVER_GLOBAL_STATE,
+    recording::WALL_TIME_TIMELINE,
 };
 use cu29::prelude::*;
-use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast::{error::TryRecvError, Receiver};
+
+/// A recording session writing to a rerun file and its manifest
+struct RecordingSession {
+    rec: rerun::RecordingStream,
+    config: RecordingSessionConfig,
+    manifest: RecordingManifest,
+    manifest_path: PathBuf,
+    deadline: Option<Instant>,
+}
+
+impl RecordingSession {
+    fn start(
+        path: &Path,
+        pipeline: &PipelineManifest,
+        config: RecordingSessionConfig,
+    ) -> Result<Self, CuError> {
+        let (rec, rec_path) = create_recording_stream(path, &config.session_id, &pipeline.name)?;
+        log::info!(
+            "Started recording session {} to {}",
+            config.session_id,
+            rec_path.display()
+        );
+
+        let manifest = RecordingManifest {
+            session_id: config.session_id.clone(),
+            name: config.name.clone(),
+            host: whoami::fallible::hostname().unwrap_or_else(|_| "unknown".to_string()),
+            recording: rec_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            pipeline: pipeline.clone(),
+            start_time_ns: wall_time_ns(),
+            tags: config.tags.clone(),
+            ..Default::default()
+        };
+
+        Ok(Self {
+            rec,
+            deadline: config
+                .duration_secs
+                .map(|secs| Instant::now() + Duration::from_secs(secs)),
+            config,
+            manifest,
+            manifest_path: rec_path.with_extension("json"),
+        })
+    }
+
+    fn is_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn log_image(&mut self, image: &EncodedImage) -> Result<(), CuError> {
+        if !self.config.records_channel(image.channel_id) {
+            return Ok(());
+        }
+        log_image_encoded(&self.rec, &format!("/cam/{}", image.channel_id), image)?;
+        *self
+            .manifest
+            .frame_counts
+            .entry(image.channel_id)
+            .or_default() += 1;
+        Ok(())
+    }
+
+    fn annotate(&mut self, annotation: &RecordingAnnotation) -> Result<(), CuError> {
+        // place the annotation at the time it was created, not when the recorder got it
+        self.rec
+            .set_time_nanos(WALL_TIME_TIMELINE, annotation.stamp_ns as i64);
+        self.rec
+            .log(
+                "/annotations",
+                &rerun::TextLog::new(format!(
+                    "[{}] {}: {}",
+                    annotation.label, annotation.author, annotation.text
+                )),
+            )
+            .map_err(|e| CuError::new_with_cause("Failed to log annotation", e))?;
+        self.manifest.annotations.push(annotation.clone());
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), CuError> {
+        self.rec.flush_blocking();
+
+        self.manifest.stop_time_ns = Some(wall_time_ns());
+        // the requested channels are listed even if they got no frames
+        self.manifest.channels = if self.config.channels.is_empty() {
+            self.manifest.frame_counts.keys().copied().collect()
+        } else {
+            self.config.channels.clone()
+        };
+
+        let file = std::fs::File::create(&self.manifest_path)
+            .map_err(|e| CuError::new_with_cause("Failed to create recording manifest", e))?;
+        serde_json::to_writer_pretty(file, &self.manifest)
+            .map_err(|e| CuError::new_with_cause("Failed to write recording manifest", e))?;
+
+        // let the server know that the recording is finalized
+        SERVER_GLOBAL_STATE.result_store.recording.finalize_session(
+            &self.config.session_id,
+            Some(self.manifest_path.display().to_string()),
+        );
+
+        SERVER_GLOBAL_STATE
+            .result_store
+            .webhooks
+            .notify(WebhookEvent::RecordingStopped {
+                session_id: self.config.session_id.clone(),
+                manifest: self.manifest_path.display().to_string(),
+            });
+
+        log::info!(
+            "Stopped recording session {}, manifest written to {}",
+            self.config.session_id,
+            self.manifest_path.display()
+        );
 
-enum RecorderState {
-    Stopped,
-    Recording(rerun::RecordingStream),
+        Ok(())
+    }
 }
 
-pub struct RecorderOne {
-    state: RecorderState,
+/// The shared logic of the recorder tasks
+struct Recorder {
+    commands: Receiver<RecordingCommand>,
+    sessions: HashMap<String, RecordingSession>,
     path: PathBuf,
+    pipeline: PipelineManifest,
 }
 
-impl Freezable for RecorderOne {}
-
-impl<'cl> CuSinkTask<'cl> for RecorderOne {
-    type Input = input_msg!('cl, EncodedImage);
-
-    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError>
-    where
-        Self: Sized,
-    {
+impl Recorder {
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError> {
         let config = config.expect("config is required");
         let path = config.get::<String>("path").expect("path is required");
+        let pipeline_name = config
+            .get::<String>("pipeline")
+            .unwrap_or_else(|| "unknown".to_string());
+
+        // register the directory so that the server can find the recordings
+        SERVER_GLOBAL_STATE
+            .result_store
+            .recording
+            .directories
+            .lock()
+            .unwrap()
+            .insert(PathBuf::from(&path));
 
         Ok(Self {
-            state: RecorderState::Stopped,
+            // every recorder subscribes to the commands so that all of them receive them
+            commands: SERVER_GLOBAL_STATE
+                .result_store
+                .recording
+                .commands
+                .tx
+                .subscribe(),
+            sessions: HashMap::new(),
             path: PathBuf::from(path),
+            pipeline: pipeline_manifest(&pipeline_name),
         })
     }
 
-    fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> Result<(), CuError> {
+    fn process(&mut self, images: &[Option<&EncodedImage>]) -> Result<(), CuError> {
         // check if we should start or stop recording
-        let maybe_command = SERVER_GLOBAL_STATE
-            .result_store
-            .recording
-            .rx
-            .lock()
-            .expect("Failed to lock recording")
-            .try_recv();
-
-        match &mut self.state {
-            RecorderState::Stopped => {
-                if let Ok(RecordingCommand::Start) = maybe_command {
-                    let (rec, rec_path) = create_recording_stream(&self.path)?;
-                    self.state = RecorderState::Recording(rec);
-                    log::info!("Started recording to {}", rec_path.display());
+        loop {
+            match self.commands.try_recv() {
+                Ok(command) => self.handle_command(command)?,
+                Err(TryRecvError::Lagged(n)) => {
+                    log::warn!("Recorder lagged behind, {} commands were dropped", n);
                 }
+                Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => break,
             }
-            RecorderState::Recording(rec) => {
-                if let Ok(RecordingCommand::Stop) = maybe_command {
-                    rec.flush_blocking();
-                    self.state = RecorderState::Stopped;
-                    log::info!("Stopped recording");
+        }
+
+        // stop the sessions that reached their duration
+        let expired = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.is_expired())
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for session_id in expired {
+            self.stop_session(&session_id);
+        }
+
+        for session in self.sessions.values_mut() {
+            for image in images.iter().flatten() {
+                session.log_image(image)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_command(&mut self, command: RecordingCommand) -> Result<(), CuError> {
+        match command {
+            RecordingCommand::Start(config) => {
+                if self.sessions.contains_key(&config.session_id) {
+                    log::warn!("Recording session {} already started", config.session_id);
                     return Ok(());
                 }
-
-                if let Some(image) = input.payload() {
-                    log_image_encoded(rec, &format!("/cam/{}", image.channel_id), image)?;
+                let session_id = config.session_id.clone();
+                match RecordingSession::start(&self.path, &self.pipeline, config) {
+                    Ok(session) => {
+                        self.sessions.insert(session_id, session);
+                    }
+                    Err(e) => {
+                        // a failed session must not take the pipeline down with it
+                        log::error!("Failed to start recording session {}: {}", session_id, e);
+                        let recording = &SERVER_GLOBAL_STATE.result_store.recording;
+                        if let Some(session) =
+                            recording.sessions.lock().unwrap().get_mut(&session_id)
+                        {
+                            session.status = RecordingSessionStatus::Failed(format!(
+                                "{} ({})",
+                                e, self.pipeline.name
+                            ));
+                        }
+                        // this recorder has nothing to finalize
+                        recording.finalize_session(&session_id, None);
+                    }
                 }
             }
+            RecordingCommand::Stop {
+                session_id: Some(session_id),
+            } => {
+                self.stop_session(&session_id);
+            }
+            RecordingCommand::Stop { session_id: None } => {
+                self.stop()?;
+            }
+            RecordingCommand::Annotate {
+                session_id,
+                annotation,
+            } => {
+                for (id, session) in self.sessions.iter_mut() {
+                    if session_id.as_ref().is_none_or(|s| s == id) {
+                        session.annotate(&annotation)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_session(&mut self, session_id: &str) {
+        if let Some(session) = self.sessions.remove(session_id) {
+            finish_session(session);
         }
+    }
 
+    fn stop(&mut self) -> Result<(), CuError> {
+        // every session gets its manifest, a failed one must not stop the others
+        for (_, session) in self.sessions.drain() {
+            finish_session(session);
+        }
         Ok(())
     }
 }
 
-pub struct RecorderTwo {
-    state: RecorderState,
-    path: PathBuf,
+pub struct RecorderOne(Recorder);
+
+impl Freezable for RecorderOne {}
+
+impl<'cl> CuSinkTask<'cl> for RecorderOne {
+    type Input = input_msg!('cl, EncodedImage);
+
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError>
+    where
+        Self: Sized,
+    {
+        Ok(Self(Recorder::new(config)?))
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> Result<(), CuError> {
+        self.0.stop()
+    }
+
+    fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> Result<(), CuError> {
+        self.0.process(&[input.payload()])
+    }
 }
 
+pub struct RecorderTwo(Recorder);
+
 impl Freezable for RecorderTwo {}
 
 impl<'cl> CuSinkTask<'cl> for RecorderTwo {
     type Input = input_msg!('cl, EncodedImage, EncodedImage);
 
     fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError> {
-        let config = config.expect("config is required");
-        let path = config.get::<String>("path").expect("path is required");
+        Ok(Self(Recorder::new(config)?))
+    }
 
-        Ok(Self {
-            state: RecorderState::Stopped,
-            path: PathBuf::from(path),
-        })
+    fn stop(&mut self, _clock: &RobotClock) -> Result<(), CuError> {
+        self.0.stop()
     }
 
     fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> Result<(), CuError> {
-        let maybe_command = SERVER_GLOBAL_STATE
-            .result_store
-            .recording
-            .rx
-            .lock()
-            .expect("Failed to lock recording")
-            .try_recv();
-
-        match &mut self.state {
-            RecorderState::Stopped => {
-                if let Ok(RecordingCommand::Start) = maybe_command {
-                    let (rec, rec_path) = create_recording_stream(&self.path)?;
-                    self.state = RecorderState::Recording(rec);
-                    log::info!("Started recording to {}", rec_path.display());
-                }
-            }
-            RecorderState::Recording(rec) => {
-                if let Ok(RecordingCommand::Stop) = maybe_command {
-                    rec.flush_blocking();
-                    self.state = RecorderState::Stopped;
-                    log::info!("Stopped recording");
-                    return Ok(());
-                } else {
-                    let (msg1, msg2) = input;
-                    if let (Some(image1), Some(image2)) = (msg1.payload(), msg2.payload()) {
-                        log_image_encoded(rec, &format!("/cam/{}", image1.channel_id), image1)?;
-                        log_image_encoded(rec, &format!("/cam/{}", image2.channel_id), image2)?;
-                    }
-                }
-            }
-        }
-
-        Ok(())
+        let (msg1, msg2) = input;
+        self.0.process(&[msg1.payload(), msg2.payload()])
     }
 }
 
-pub struct RecorderThree {
-    state: RecorderState,
-    path: PathBuf,
-}
+pub struct RecorderThree(Recorder);
 
 impl Freezable for RecorderThree {}
 
@@ -137,59 +330,21 @@ impl<'cl> CuSinkTask<'cl> for RecorderThree {
     type Input = input_msg!('cl, EncodedImage, EncodedImage, EncodedImage);
 
     fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError> {
-        let config = config.expect("config is required");
-        let path = config.get::<String>("path").expect("path is required");
+        Ok(Self(Recorder::new(config)?))
+    }
 
-        Ok(Self {
-            state: RecorderState::Stopped,
-            path: PathBuf::from(path),
-        })
+    fn stop(&mut self, _clock: &RobotClock) -> Result<(), CuError> {
+        self.0.stop()
     }
 
     fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> Result<(), CuError> {
-        let maybe_command = SERVER_GLOBAL_STATE
-            .result_store
-            .recording
-            .rx
-            .lock()
-            .expect("Failed to lock recording")
-            .try_recv();
-
-        match &mut self.state {
-            RecorderState::Stopped => {
-                if let Ok(RecordingCommand::Start) = maybe_command {
-                    let (rec, rec_path) = create_recording_stream(&self.path)?;
-                    self.state = RecorderState::Recording(rec);
-                    log::info!("Started recording to {}", rec_path.display());
-                }
-            }
-            RecorderState::Recording(rec) => {
-                if let Ok(RecordingCommand::Stop) = maybe_command {
-                    rec.flush_blocking();
-                    self.state = RecorderState::Stopped;
-                    log::info!("Stopped recording");
-                    return Ok(());
-                } else {
-                    let (msg1, msg2, msg3) = input;
-                    if let (Some(image1), Some(image2), Some(image3)) =
-                        (msg1.payload(), msg2.payload(), msg3.payload())
-                    {
-                        log_image_encoded(rec, &format!("/cam/{}", image1.channel_id), image1)?;
-                        log_image_encoded(rec, &format!("/cam/{}", image2.channel_id), image2)?;
-                        log_image_encoded(rec, &format!("/cam/{}", image3.channel_id), image3)?;
-                    }
-                }
-            }
-        }
-
-        Ok(())
+        let (msg1, msg2, msg3) = input;
+        self.0
+            .process(&[msg1.payload(), msg2.payload(), msg3.payload()])
     }
 }
 
-pub struct RecorderFour {
-    state: RecorderState,
-    path: PathBuf,
-}
+pub struct RecorderFour(Recorder);
 
 impl Freezable for RecorderFour {}
 
@@ -197,67 +352,100 @@ impl<'cl> CuSinkTask<'cl> for RecorderFour {
     type Input = input_msg!('cl, EncodedImage, EncodedImage, EncodedImage, EncodedImage);
 
     fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError> {
-        let config = config.expect("config is required");
-        let path = config.get::<String>("path").expect("path is required");
+        Ok(Self(Recorder::new(config)?))
+    }
 
-        Ok(Self {
-            state: RecorderState::Stopped,
-            path: PathBuf::from(path),
-        })
+    fn stop(&mut self, _clock: &RobotClock) -> Result<(), CuError> {
+        self.0.stop()
     }
 
     fn process(&mut self, _clock: &RobotClock, input: Self::Input) -> Result<(), CuError> {
-        let maybe_command = SERVER_GLOBAL_STATE
-            .result_store
-            .recording
-            .rx
-            .lock()
-            .expect("Failed to lock recording")
-            .try_recv();
-
-        match &mut self.state {
-            RecorderState::Stopped => {
-                if let Ok(RecordingCommand::Start) = maybe_command {
-                    let (rec, rec_path) = create_recording_stream(&self.path)?;
-                    self.state = RecorderState::Recording(rec);
-                    log::info!("Started recording to {}", rec_path.display());
-                }
-            }
-            RecorderState::Recording(rec) => {
-                if let Ok(RecordingCommand::Stop) = maybe_command {
-                    rec.flush_blocking();
-                    self.state = RecorderState::Stopped;
-                    log::info!("Stopped recording");
-                    return Ok(());
-                } else {
-                    let (msg1, msg2, msg3, msg4) = input;
-                    if let (Some(image1), Some(image2), Some(image3), Some(image4)) = (
-                        msg1.payload(),
-                        msg2.payload(),
-                        msg3.payload(),
-                        msg4.payload(),
-                    ) {
-                        log_image_encoded(rec, &format!("/cam/{}", image1.channel_id), image1)?;
-                        log_image_encoded(rec, &format!("/cam/{}", image2.channel_id), image2)?;
-                        log_image_encoded(rec, &format!("/cam/{}", image3.channel_id), image3)?;
-                        log_image_encoded(rec, &format!("/cam/{}", image4.channel_id), image4)?;
-                    }
-                }
-            }
-        }
+        let (msg1, msg2, msg3, msg4) = input;
+        self.0.process(&[
+            msg1.payload(),
+            msg2.payload(),
+            msg3.payload(),
+            msg4.payload(),
+        ])
+    }
+}
 
-        Ok(())
+/// Finalize a session, logging the failure instead of stopping the pipeline
+fn finish_session(session: RecordingSession) {
+    let session_id = session.config.session_id.clone();
+    if let Err(e) = session.finish() {
+        log::error!("Failed to finish recording session {}: {}", session_id, e);
     }
 }
 
-fn create_recording_stream(path: &Path) -> Result<(rerun::RecordingStream, PathBuf), CuError> {
-    let rec_path = {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        path.join(format!("{}.rrd", timestamp))
-    };
+/// Builds the pipeline description from the ron config the pipeline was compiled with
+fn pipeline_manifest(name: &str) -> PipelineManifest {
+    // NOTE: we only need the tasks, the rest of the config is ignored
+    #[derive(Deserialize)]
+    struct RonPipeline {
+        tasks: Vec<RonTask>,
+    }
+
+    #[derive(Deserialize)]
+    struct RonTask {
+        id: String,
+        #[serde(rename = "type")]
+        task_type: String,
+        #[serde(default)]
+        config: Option<ron::Value>,
+    }
+
+    let tasks = pipeline_config(name)
+        .and_then(|config| {
+            ron::from_str::<RonPipeline>(config)
+                .map_err(|e| log::warn!("Failed to parse pipeline {} config: {}", name, e))
+                .ok()
+        })
+        .map(|pipeline| {
+            pipeline
+                .tasks
+                .into_iter()
+                .map(|task| TaskManifest {
+                    id: task.id,
+                    task_type: task.task_type,
+                    config: task
+                        .config
+                        .and_then(|config| serde_json::to_value(config).ok())
+                        .unwrap_or_default(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    PipelineManifest {
+        name: name.to_string(),
+        tasks,
+    }
+}
+
+fn wall_time_ns() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+/// Creates the rerun file of a session, named after the session and the pipeline
+///
+/// Several recorders can write to the same directory, so a counter is appended to the
+/// name if the file already exists rather than overwriting it.
+fn create_recording_stream(
+    path: &Path,
+    session_id: &str,
+    pipeline: &str,
+) -> Result<(rerun::RecordingStream, PathBuf), CuError> {
+    let stem = format!("{}_{}", session_id, pipeline);
+    let mut rec_path = path.join(format!("{}.rrd", stem));
+    let mut index = 1;
+    while rec_path.exists() || rec_path.with_extension("json").exists() {
+        rec_path = path.join(format!("{}_{}.rrd", stem, index));
+        index += 1;
+    }
 
     let rec = rerun::RecordingStreamBuilder::new("rerun_logger")
         .save(&rec_path)
@@ -271,6 +459,8 @@ fn log_image_encoded(
     name: &str,
     msg: &EncodedImage,
 ) -> Result<(), CuError> {
+    // the same timeline as the exported clips, so the reader handles both alike
+    rec.set_time_nanos(WALL_TIME_TIMELINE, wall_time_ns() as i64);
     rec.log(
         name,
         &rerun::EncodedImage::from_file_contents(msg.data.clone()),
@@ -0,0 +1,76 @@
+use crate::{
+    cu29::msgs::{DetectionsMsg, TracksMsg},
+    tracking::{Tracker, TrackerConfig},
+};
+use cu29::prelude::*;
+use std::collections::HashMap;
+
+/// Task that tracks the detected objects across frames with stable identifiers
+pub struct ObjectTracker {
+    config: TrackerConfig,
+    // one tracker per channel, created with the first detections of the channel
+    trackers: HashMap<u8, Tracker>,
+}
+
+impl Freezable for ObjectTracker {}
+
+impl<'cl> CuTask<'cl> for ObjectTracker {
+    type Input = input_msg!('cl, DetectionsMsg);
+    type Output = output_msg!('cl, TracksMsg);
+
+    fn new(config: Option<&ComponentConfig>) -> Result<Self, CuError>
+    where
+        Self: Sized,
+    {
+        let mut tracker_config = TrackerConfig::default();
+        if let Some(config) = config {
+            if let Some(iou_threshold) = config.get::<f64>("iou_threshold") {
+                tracker_config.iou_threshold = iou_threshold as f32;
+            }
+            if let Some(high_score) = config.get::<f64>("high_score") {
+                tracker_config.high_score = high_score as f32;
+            }
+            if let Some(min_hits) = config.get::<u32>("min_hits") {
+                tracker_config.min_hits = min_hits;
+            }
+            if let Some(max_lost_ms) = config.get::<u32>("max_lost_ms") {
+                tracker_config.max_lost_ms = max_lost_ms as u64;
+            }
+        }
+
+        log::debug!("Created object tracker: {:?}", tracker_config);
+
+        Ok(Self {
+            config: tracker_config,
+            trackers: HashMap::new(),
+        })
+    }
+
+    fn process(
+        &mut self,
+        _clock: &RobotClock,
+        input: Self::Input,
+        output: Self::Output,
+    ) -> Result<(), CuError> {
+        // clear the output payload to avoid any previous payload to be forwarded
+        output.clear_payload();
+
+        let Some(msg) = input.payload() else {
+            return Ok(());
+        };
+
+        let tracks = self
+            .trackers
+            .entry(msg.channel_id)
+            .or_insert_with(|| Tracker::new(self.config.clone()))
+            .update(msg.stamp_ns, &msg.detections);
+
+        output.set_payload(TracksMsg {
+            stamp_ns: msg.stamp_ns,
+            channel_id: msg.channel_id,
+            tracks,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use crate::cu29::msgs::ImageRgb8;
+
+mod font;
+pub use font::*;
+
+/// An rgb color
+pub type Rgb = [u8; 3];
+
+/// The horizontal space between two glyphs in pixels, before scaling
+const GLYPH_SPACING: usize = 1;
+
+/// Set the color of a pixel, the pixels outside the image are ignored
+pub fn put_pixel(image: &mut ImageRgb8, x: i64, y: i64, color: Rgb) {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    if x < 0 || y < 0 || x >= width || y >= height {
+        return;
+    }
+    let idx = ((y * width + x) * 3) as usize;
+    image.as_slice_mut()[idx..idx + 3].copy_from_slice(&color);
+}
+
+/// Fill the rectangle between the top-left `(x0, y0)` and the bottom-right `(x1, y1)` corners
+pub fn fill_rect(image: &mut ImageRgb8, x0: i64, y0: i64, x1: i64, y1: i64, color: Rgb) {
+    // clip the rectangle to the image to not iterate over the pixels outside
+    let (x0, x1) = (x0.max(0), x1.min(image.width() as i64));
+    let (y0, y1) = (y0.max(0), y1.min(image.height() as i64));
+    for y in y0..y1 {
+        for x in x0..x1 {
+            put_pixel(image, x, y, color);
+        }
+    }
+}
+
+/// Draw the outline of a rectangle with the given thickness, drawn inwards
+pub fn draw_rect(
+    image: &mut ImageRgb8,
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
+    thickness: i64,
+    color: Rgb,
+) {
+    fill_rect(image, x0, y0, x1, y0 + thickness, color);
+    fill_rect(image, x0, y1 - thickness, x1, y1, color);
+    fill_rect(image, x0, y0, x0 + thickness, y1, color);
+    fill_rect(image, x1 - thickness, y0, x1, y1, color);
+}
+
+/// The size in pixels of the text drawn with the given scale
+pub fn text_size(text: &str, scale: usize) -> (usize, usize) {
+    let num_chars = text.chars().count();
+    (
+        num_chars * (GLYPH_WIDTH + GLYPH_SPACING) * scale,
+        GLYPH_HEIGHT * scale,
+    )
+}
+
+/// Draw a single line of text with its top-left corner at `(x, y)`
+///
+/// # Arguments
+///
+/// * `image` - The image to draw on
+/// * `x` - The left position of the text
+/// * `y` - The top position of the text
+/// * `text` - The text to draw, only ascii characters are supported
+/// * `scale` - The size of a glyph pixel in image pixels
+/// * `color` - The color of the text
+pub fn draw_text(image: &mut ImageRgb8, x: i64, y: i64, text: &str, scale: usize, color: Rgb) {
+    let scale = scale.max(1) as i64;
+    let advance = (GLYPH_WIDTH + GLYPH_SPACING) as i64 * scale;
+    for (i, c) in text.chars().enumerate() {
+        let origin_x = x + i as i64 * advance;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                let px = origin_x + col as i64 * scale;
+                let py = y + row as i64 * scale;
+                fill_rect(image, px, py, px + scale, py + scale, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{
+    cu29::msgs::ImageRgb8,
+    inference::{MockBackend, MockConfig, ModelConfig, PaligemmaBackend},
+};
+use cu29::prelude::ComponentConfig;
+use kornia::imgproc::{interpolation::InterpolationMode, resize::resize_fast};
+use serde::{Deserialize, Serialize};
+
+/// The default backend to use if no backend is provided
+pub const DEFAULT_BACKEND: &str = "paligemma";
+
+/// The error returned by the inference backends
+#[derive(Debug)]
+pub enum InferenceError {
+    /// The backend configuration is invalid
+    Config(String),
+    /// The input image could not be decoded
+    Image(String),
+    /// The files of the model are missing or corrupted
+    ModelFiles(String),
+    /// The backend failed to load or to run the model
+    Backend(String),
+}
+
+impl std::fmt::Display for InferenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InferenceError::Config(e) => write!(f, "Invalid backend config: {}", e),
+            InferenceError::Image(e) => write!(f, "Invalid image: {}", e),
+            InferenceError::ModelFiles(e) => write!(f, "Invalid model files: {}", e),
+            InferenceError::Backend(e) => write!(f, "Backend error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for InferenceError {}
+
+/// The structured response of an inference backend
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct InferenceOutput {
+    /// the text generated by the model
+    pub response: String,
+}
+
+/// The parameters controlling the generation of the response
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, bincode::Encode, bincode::Decode)]
+#[serde(default)]
+pub struct GenerationParams {
+    /// the maximum number of tokens to generate
+    pub max_new_tokens: usize,
+    /// sample the tokens instead of greedy decoding
+    pub sample: bool,
+    /// the sampling temperature
+    pub temperature: Option<f64>,
+    /// the nucleus sampling probability
+    pub top_p: Option<f64>,
+    /// the seed of the sampler
+    pub seed: u64,
+    /// resize the input image to `[cols, rows]` before running the model
+    pub resize: Option<[usize; 2]>,
+}
+
+impl Default for GenerationParams {
+    fn default() -> Self {
+        Self {
+            max_new_tokens: 50,
+            sample: false,
+            temperature: None,
+            top_p: None,
+            seed: 299792458,
+            resize: None,
+        }
+    }
+}
+
+impl GenerationParams {
+    /// Parse the generation parameters from the task config
+    pub fn from_component_config(config: Option<&ComponentConfig>) -> Self {
+        let mut params = Self::default();
+        let Some(config) = config else {
+            return params;
+        };
+        if let Some(max_new_tokens) = config.get::<u32>("max_new_tokens") {
+            params.max_new_tokens = max_new_tokens as usize;
+        }
+        if let Some(sample) = config.get::<bool>("sample") {
+            params.sample = sample;
+        }
+        if let Some(temperature) = config.get::<f64>("temperature") {
+            params.temperature = Some(temperature);
+        }
+        if let Some(top_p) = config.get::<f64>("top_p") {
+            params.top_p = Some(top_p);
+        }
+        if let Some(seed) = config.get::<u64>("seed") {
+            params.seed = seed;
+        }
+        if let (Some(cols), Some(rows)) = (
+            config.get::<u32>("resize_cols"),
+            config.get::<u32>("resize_rows"),
+        ) {
+            params.resize = Some([cols as usize, rows as usize]);
+        }
+        params
+    }
+}
+
+/// A model that takes an image and a prompt and returns a structured response
+///
+/// The backends are owned by the inference thread, so they only need to be `Send`.
+pub trait InferenceBackend: Send {
+    /// The name of the backend
+    fn name(&self) -> &str;
+
+    /// Run the model on an image given a prompt
+    fn infer(
+        &mut self,
+        image: &ImageRgb8,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<InferenceOutput, InferenceError>;
+}
+
+/// The configuration to create an inference backend
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BackendConfig {
+    /// the name of the backend, e.g. `paligemma` or `mock`
+    pub backend: String,
+    /// the default generation parameters
+    #[serde(default)]
+    pub params: GenerationParams,
+    /// the weights of the model
+    #[serde(default)]
+    pub model: ModelConfig,
+    /// the configuration of the mock backend
+    #[serde(default)]
+    pub mock: MockConfig,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            backend: DEFAULT_BACKEND.to_string(),
+            params: GenerationParams::default(),
+            model: ModelConfig::default(),
+            mock: MockConfig::default(),
+        }
+    }
+}
+
+impl BackendConfig {
+    /// Parse the backend configuration from the task config
+    pub fn from_component_config(config: Option<&ComponentConfig>) -> Result<Self, InferenceError> {
+        let mut backend_config = Self {
+            params: GenerationParams::from_component_config(config),
+            model: ModelConfig::from_component_config(config)?,
+            ..Default::default()
+        };
+        let Some(config) = config else {
+            return Ok(backend_config);
+        };
+        if let Some(backend) = config.get::<String>("backend") {
+            backend_config.backend = backend;
+        }
+        if let Some(mode) = config.get::<String>("mock_mode") {
+            backend_config.mock.mode = mode.parse()?;
+        }
+        if let Some(responses_path) = config.get::<String>("mock_responses_path") {
+            backend_config.mock.responses_path = Some(responses_path);
+        }
+        if let Some(latency_ms) = config.get::<u32>("mock_latency_ms") {
+            backend_config.mock.latency_ms = latency_ms as u64;
+        }
+        Ok(backend_config)
+    }
+
+    /// The name of the model loaded by the backend
+    pub fn model_name(&self) -> String {
+        match self.backend.as_str() {
+            "paligemma" => self.model.name().to_string(),
+            backend => backend.to_string(),
+        }
+    }
+}
+
+/// Create the inference backend selected in the configuration
+pub fn create_backend(config: &BackendConfig) -> Result<Box<dyn InferenceBackend>, InferenceError> {
+    match config.backend.as_str() {
+        "paligemma" => Ok(Box::new(PaligemmaBackend::new(
+            &config.params,
+            &config.model,
+        )?)),
+        "mock" => Ok(Box::new(MockBackend::new(&config.mock)?)),
+        backend => Err(InferenceError::Config(format!(
+            "Backend {} not supported. Try 'paligemma' or 'mock' instead",
+            backend
+        ))),
+    }
+}
+
+/// Resize the image to the size requested in the generation parameters, if any
+pub fn resize_input(
+    image: &ImageRgb8,
+    params: &GenerationParams,
+) -> Result<ImageRgb8, InferenceError> {
+    let Some([cols, rows]) = params.resize else {
+        return Ok(image.clone());
+    };
+
+    let mut resized = ImageRgb8::from_size_val([cols, rows].into(), 0u8)
+        .map_err(|e| InferenceError::Config(e.to_string()))?;
+
+    resize_fast(image, &mut resized, InterpolationMode::Bilinear)
+        .map_err(|e| InferenceError::Backend(e.to_string()))?;
+
+    Ok(resized)
+}
@@ -0,0 +1,142 @@
+use crate::{
+    cu29::msgs::{EncodedImage, ImageRgb8},
+    inference::{resize_input, GenerationParams, InferenceBackend, InferenceError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+/// The extensions of the image files read from a folder
+const IMAGE_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// An image to run the batch inference on
+pub enum BatchImage {
+    /// an image file, read when its turn comes
+    File(PathBuf),
+    /// a frame of a recording
+    Frame(EncodedImage),
+}
+
+/// The result of a prompt on an image of the batch, written as a JSON line
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BatchRecord {
+    /// the image file, if the image was read from a folder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// the timestamp of the frame, if the image was read from a recording
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stamp_ns: Option<u64>,
+    pub prompt: String,
+    pub response: String,
+    /// the time spent running the model
+    pub latency_ms: u64,
+    /// the model that produced the response
+    pub model: String,
+    /// the reason why the image could not be processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// List the image files of a folder, sorted by name
+pub fn list_image_files(dir: &Path) -> std::io::Result<Vec<BatchImage>> {
+    let mut files = std::fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    Ok(files.into_iter().map(BatchImage::File).collect())
+}
+
+/// Decode a jpeg or png image into an rgb image
+pub fn decode_image(data: &[u8]) -> Result<ImageRgb8, InferenceError> {
+    let image = image::load_from_memory(data)
+        .map_err(|e| InferenceError::Image(e.to_string()))?
+        .to_rgb8();
+    let (width, height) = image.dimensions();
+    ImageRgb8::new([width as usize, height as usize].into(), image.into_raw())
+        .map_err(|e| InferenceError::Image(e.to_string()))
+}
+
+/// Run a prompt on an encoded image like the inference tasks do
+///
+/// # Returns
+///
+/// The response of the model and the time spent running it in milliseconds
+pub fn infer_encoded(
+    backend: &mut dyn InferenceBackend,
+    data: &[u8],
+    prompt: &str,
+    params: &GenerationParams,
+) -> Result<(String, u64), InferenceError> {
+    let image = decode_image(data)?;
+
+    let start = Instant::now();
+    let output =
+        resize_input(&image, params).and_then(|image| backend.infer(&image, prompt, params))?;
+    let latency_ms = start.elapsed().as_millis() as u64;
+
+    Ok((output.response, latency_ms))
+}
+
+/// Run every prompt on every image with the same backend as the inference tasks
+///
+/// The images that fail are reported in the `error` field of their records instead of
+/// stopping the batch.
+///
+/// # Arguments
+///
+/// * `backend` - The loaded backend to run the prompts with
+/// * `model` - The name of the model, recorded in the results
+/// * `images` - The image files or recording frames
+/// * `prompts` - The prompts to run on each image, to compare them
+/// * `params` - The generation parameters
+/// * `on_record` - Called with each result, e.g. to write it as a JSON line
+pub fn run_batch<E>(
+    backend: &mut dyn InferenceBackend,
+    model: &str,
+    images: Vec<BatchImage>,
+    prompts: &[String],
+    params: &GenerationParams,
+    mut on_record: impl FnMut(BatchRecord) -> Result<(), E>,
+) -> Result<(), E> {
+    for image in images {
+        let (file, stamp_ns, data) = match image {
+            BatchImage::File(path) => {
+                let data = std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e));
+                (Some(path.display().to_string()), None, data)
+            }
+            BatchImage::Frame(frame) => (None, Some(frame.stamp_ns), Ok(frame.data)),
+        };
+
+        for prompt in prompts {
+            let result = data.as_ref().map_err(|e| e.clone()).and_then(|data| {
+                infer_encoded(backend, data, prompt, params).map_err(|e| e.to_string())
+            });
+
+            let (response, latency_ms, error) = match result {
+                Ok((response, latency_ms)) => (response, latency_ms, None),
+                Err(e) => (String::new(), 0, Some(e)),
+            };
+
+            on_record(BatchRecord {
+                file: file.clone(),
+                stamp_ns,
+                prompt: prompt.clone(),
+                response,
+                latency_ms,
+                model: model.to_string(),
+                error,
+            })?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,506 @@
+use crate::inference::InferenceError;
+use cu29::prelude::ComponentConfig;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// The repository of the PaliGemma weights on the Hugging Face hub
+pub const DEFAULT_PALIGEMMA_REPO: &str = "google/paligemma-3b-mix-224";
+
+/// The file listing the expected checksums of a local model directory
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Where the weights of a model are read from
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelSource {
+    /// the Hugging Face cache of the user, downloading the missing files
+    #[default]
+    Hub,
+    /// a Hugging Face cache root, e.g. `~/.cache/huggingface`, without downloading
+    Cache(PathBuf),
+    /// a directory with the model files, e.g. copied to an air-gapped robot
+    Dir(PathBuf),
+}
+
+/// The weights to load a model from
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ModelConfig {
+    /// the repository of the model on the Hugging Face hub
+    pub repo: String,
+    /// where the files of the model are read from
+    #[serde(default)]
+    pub source: ModelSource,
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        Self {
+            repo: DEFAULT_PALIGEMMA_REPO.to_string(),
+            source: ModelSource::Hub,
+        }
+    }
+}
+
+impl ModelConfig {
+    /// Parse the model configuration from the task config
+    ///
+    /// The keys are `model_repo` and either `model_dir` or `model_cache`.
+    pub fn from_component_config(config: Option<&ComponentConfig>) -> Result<Self, InferenceError> {
+        let mut model = Self::default();
+        let Some(config) = config else {
+            return Ok(model);
+        };
+        if let Some(repo) = config.get::<String>("model_repo") {
+            model.repo = repo;
+        }
+        model.source = match (
+            config.get::<String>("model_dir"),
+            config.get::<String>("model_cache"),
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(InferenceError::Config(
+                    "Either model_dir or model_cache can be set, not both".to_string(),
+                ))
+            }
+            (Some(dir), None) => ModelSource::Dir(dir.into()),
+            (None, Some(cache)) => ModelSource::Cache(cache.into()),
+            (None, None) => ModelSource::Hub,
+        };
+        Ok(model)
+    }
+
+    /// The name of the model, e.g. `paligemma-3b-mix-224`
+    pub fn name(&self) -> &str {
+        self.repo.rsplit('/').next().unwrap_or(&self.repo)
+    }
+}
+
+/// A file of a model
+#[derive(Clone, Debug, Serialize)]
+pub struct ModelFile {
+    /// the path relative to the model directory
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+/// A model found in a Hugging Face cache
+#[derive(Clone, Debug, Serialize)]
+pub struct CachedModel {
+    /// the repository of the model, e.g. `google/paligemma-3b-mix-224`
+    pub repo: String,
+    /// the revision of the snapshot
+    pub revision: String,
+    /// the directory of the snapshot
+    pub path: PathBuf,
+    pub files: Vec<ModelFile>,
+    pub size_bytes: u64,
+}
+
+/// The default Hugging Face cache root, `$HF_HOME` or `~/.cache/huggingface`
+pub fn default_cache_root() -> PathBuf {
+    if let Some(root) = std::env::var_os("HF_HOME") {
+        return root.into();
+    }
+    let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
+    Path::new(&home).join(".cache").join("huggingface")
+}
+
+/// List the models of a Hugging Face cache root and their sizes
+pub fn list_cached_models(root: &Path) -> Result<Vec<CachedModel>, InferenceError> {
+    let hub = root.join("hub");
+    let entries = std::fs::read_dir(&hub)
+        .map_err(|e| InferenceError::ModelFiles(format!("{}: {}", hub.display(), e)))?;
+
+    let mut models = Vec::new();
+    for entry in entries.flatten() {
+        let dir_name = entry.file_name().to_string_lossy().to_string();
+        let Some(repo) = dir_name.strip_prefix("models--") else {
+            continue;
+        };
+        let repo = repo.replace("--", "/");
+        match cached_snapshot(root, &repo) {
+            Ok((revision, path)) => {
+                let files = list_files(&path)?;
+                models.push(CachedModel {
+                    repo,
+                    revision,
+                    size_bytes: files.iter().map(|file| file.size_bytes).sum(),
+                    files,
+                    path,
+                });
+            }
+            Err(e) => log::warn!("Skipping cached model {}: {}", repo, e),
+        }
+    }
+    models.sort_by(|a, b| a.repo.cmp(&b.repo));
+
+    Ok(models)
+}
+
+/// Validate the files of a model and return the directory to load them from
+///
+/// The hub source validates the snapshot of the default cache root if the model is
+/// already cached there, and returns `None` if the files still need to be downloaded.
+pub fn resolve_model_dir(model: &ModelConfig) -> Result<Option<PathBuf>, InferenceError> {
+    match &model.source {
+        ModelSource::Hub => {
+            let Ok((_, snapshot)) = cached_snapshot(&default_cache_root(), &model.repo) else {
+                return Ok(None);
+            };
+            // NOTE: an interrupted download is completed, but corrupted files are not loaded
+            match required_files(&snapshot) {
+                Ok(weights) => {
+                    verify_checksums(&snapshot, &weights)?;
+                    Ok(Some(snapshot))
+                }
+                Err(e) => {
+                    log::info!("Downloading the missing files of {}: {}", model.repo, e);
+                    Ok(None)
+                }
+            }
+        }
+        ModelSource::Cache(root) => {
+            let (_, snapshot) = cached_snapshot(root, &model.repo)?;
+            validate_model_files(&snapshot)?;
+            Ok(Some(snapshot))
+        }
+        ModelSource::Dir(dir) => {
+            validate_model_files(dir)?;
+            Ok(Some(dir.clone()))
+        }
+    }
+}
+
+/// Check that the weights and the tokenizer are present and match their checksums
+///
+/// The checksums are read from the `SHA256SUMS` file of the directory if any, otherwise
+/// from the names of the blobs of a Hugging Face cache.
+pub fn validate_model_files(dir: &Path) -> Result<(), InferenceError> {
+    let weights = required_files(dir)?;
+    verify_checksums(dir, &weights)
+}
+
+/// Check that the tokenizer and the weights are present and return the weight files
+fn required_files(dir: &Path) -> Result<Vec<String>, InferenceError> {
+    if !dir.is_dir() {
+        return Err(InferenceError::ModelFiles(format!(
+            "Model directory {} not found",
+            dir.display()
+        )));
+    }
+
+    let files = list_files(dir)?;
+    let has_file = |name: &str| files.iter().any(|file| file.name == name);
+
+    if !has_file("tokenizer.json") {
+        return Err(InferenceError::ModelFiles(format!(
+            "Missing tokenizer.json in {}",
+            dir.display()
+        )));
+    }
+
+    // the sharded weights list their shards in the index
+    let index_path = dir.join("model.safetensors.index.json");
+    let weights = if index_path.exists() {
+        let index = std::fs::read_to_string(&index_path)
+            .map_err(|e| InferenceError::ModelFiles(format!("{}: {}", index_path.display(), e)))?;
+        let index: serde_json::Value = serde_json::from_str(&index)
+            .map_err(|e| InferenceError::ModelFiles(format!("{}: {}", index_path.display(), e)))?;
+        let mut shards = index["weight_map"]
+            .as_object()
+            .map(|map| {
+                map.values()
+                    .filter_map(|shard| shard.as_str().map(|s| s.to_string()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        shards.sort();
+        shards.dedup();
+        shards
+    } else {
+        files
+            .iter()
+            .filter(|file| file.name.ends_with(".safetensors"))
+            .map(|file| file.name.clone())
+            .collect()
+    };
+
+    if weights.is_empty() {
+        return Err(InferenceError::ModelFiles(format!(
+            "No safetensors weights in {}",
+            dir.display()
+        )));
+    }
+    if let Some(missing) = weights.iter().find(|name| !has_file(name)) {
+        return Err(InferenceError::ModelFiles(format!(
+            "Missing weights {} in {}",
+            missing,
+            dir.display()
+        )));
+    }
+
+    Ok(weights)
+}
+
+/// Check the files against the `SHA256SUMS` file or the blob names of the weights
+fn verify_checksums(dir: &Path, weights: &[String]) -> Result<(), InferenceError> {
+    let checksums_path = dir.join(CHECKSUMS_FILE);
+    if checksums_path.exists() {
+        let checksums = std::fs::read_to_string(&checksums_path).map_err(|e| {
+            InferenceError::ModelFiles(format!("{}: {}", checksums_path.display(), e))
+        })?;
+        // the format of `sha256sum`: `<hex digest>  <file name>`
+        for line in checksums.lines().filter(|line| !line.trim().is_empty()) {
+            let Some((expected, name)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let name = name.trim().trim_start_matches('*');
+            verify_checksum(&dir.join(name), expected)?;
+        }
+    } else {
+        // the weights of a Hugging Face cache are stored in blobs named by their sha256
+        for name in weights {
+            let path = dir.join(name);
+            let Ok(blob) = std::fs::canonicalize(&path) else {
+                continue;
+            };
+            let Some(expected) = blob.file_name().map(|n| n.to_string_lossy().to_string()) else {
+                continue;
+            };
+            if expected.len() == 64 && expected.chars().all(|c| c.is_ascii_hexdigit()) {
+                verify_checksum(&path, &expected)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The revision and the snapshot directory of a repository in a Hugging Face cache root
+pub(crate) fn cached_snapshot(
+    root: &Path,
+    repo: &str,
+) -> Result<(String, PathBuf), InferenceError> {
+    let repo_dir = root
+        .join("hub")
+        .join(format!("models--{}", repo.replace('/', "--")));
+    if !repo_dir.is_dir() {
+        return Err(InferenceError::ModelFiles(format!(
+            "Model {} not found in the cache {}",
+            repo,
+            root.display()
+        )));
+    }
+
+    let refs_path = repo_dir.join("refs").join("main");
+    let revision = std::fs::read_to_string(&refs_path)
+        .map_err(|e| InferenceError::ModelFiles(format!("{}: {}", refs_path.display(), e)))?
+        .trim()
+        .to_string();
+
+    let snapshot = repo_dir.join("snapshots").join(&revision);
+    if !snapshot.is_dir() {
+        return Err(InferenceError::ModelFiles(format!(
+            "Snapshot {} of model {} not found in the cache {}",
+            revision,
+            repo,
+            root.display()
+        )));
+    }
+
+    Ok((revision, snapshot))
+}
+
+/// The files of a directory and of its subdirectories with their sizes
+fn list_files(dir: &Path) -> Result<Vec<ModelFile>, InferenceError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        let entries = std::fs::read_dir(&current)
+            .map_err(|e| InferenceError::ModelFiles(format!("{}: {}", current.display(), e)))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // follow the symlinks of the snapshots to their blobs
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else if let Ok(name) = path.strip_prefix(dir) {
+                files.push(ModelFile {
+                    name: name.to_string_lossy().to_string(),
+                    size_bytes: metadata.len(),
+                });
+            }
+        }
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+fn verify_checksum(path: &Path, expected: &str) -> Result<(), InferenceError> {
+    log::debug!("Verifying the checksum of {}", path.display());
+
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| InferenceError::ModelFiles(format!("{}: {}", path.display(), e)))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let n = file
+            .read(&mut buffer)
+            .map_err(|e| InferenceError::ModelFiles(format!("{}: {}", path.display(), e)))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+
+    let digest = hex::encode(hasher.finalize());
+    if !digest.eq_ignore_ascii_case(expected.trim()) {
+        return Err(InferenceError::ModelFiles(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            path.display(),
+            expected.trim(),
+            digest
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("bubbaloop_cache_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, content: &str) {
+        std::fs::write(dir.join(name), content).unwrap();
+    }
+
+    fn sha256(content: &str) -> String {
+        hex::encode(Sha256::digest(content.as_bytes()))
+    }
+
+    /// A model directory with a tokenizer and the weights sharded in two files
+    fn model_dir(name: &str) -> PathBuf {
+        let dir = test_dir(name);
+        write(&dir, "tokenizer.json", "{}");
+        write(
+            &dir,
+            "model.safetensors.index.json",
+            r#"{"weight_map": {
+                "a.weight": "model-00001-of-00002.safetensors",
+                "b.weight": "model-00002-of-00002.safetensors",
+                "c.weight": "model-00002-of-00002.safetensors"
+            }}"#,
+        );
+        write(&dir, "model-00001-of-00002.safetensors", "shard 1");
+        write(&dir, "model-00002-of-00002.safetensors", "shard 2");
+        dir
+    }
+
+    fn assert_invalid(dir: &Path, message: &str) {
+        match validate_model_files(dir) {
+            Err(InferenceError::ModelFiles(e)) => assert!(e.contains(message), "{}", e),
+            result => panic!("expected invalid model files, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn validate_model_files_accepts_a_complete_model() {
+        let dir = model_dir("complete");
+        validate_model_files(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_model_files_requires_the_tokenizer() {
+        let dir = model_dir("no_tokenizer");
+        std::fs::remove_file(dir.join("tokenizer.json")).unwrap();
+        assert_invalid(&dir, "Missing tokenizer.json");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_model_files_requires_all_the_shards() {
+        let dir = model_dir("no_shard");
+        std::fs::remove_file(dir.join("model-00002-of-00002.safetensors")).unwrap();
+        assert_invalid(&dir, "Missing weights model-00002-of-00002.safetensors");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_model_files_requires_weights() {
+        let dir = test_dir("no_weights");
+        write(&dir, "tokenizer.json", "{}");
+        assert_invalid(&dir, "No safetensors weights");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_model_files_checks_the_checksums() {
+        let dir = model_dir("checksums");
+        write(
+            &dir,
+            CHECKSUMS_FILE,
+            &format!(
+                "{}  tokenizer.json\n{} *model-00001-of-00002.safetensors\n\n",
+                sha256("{}"),
+                sha256("shard 1"),
+            ),
+        );
+        validate_model_files(&dir).unwrap();
+
+        // a corrupted shard
+        write(&dir, "model-00001-of-00002.safetensors", "shard 1 truncat");
+        assert_invalid(&dir, "Checksum mismatch for");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_model_dir_loads_the_configured_files_in_place() {
+        let dir = model_dir("resolve_dir");
+        let model = ModelConfig {
+            repo: "google/test".to_string(),
+            source: ModelSource::Dir(dir.clone()),
+        };
+        assert_eq!(resolve_model_dir(&model).unwrap(), Some(dir.clone()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_model_dir_finds_the_snapshot_of_a_cache() {
+        let root = test_dir("resolve_cache");
+        let repo_dir = root.join("hub").join("models--google--test");
+        std::fs::create_dir_all(repo_dir.join("refs")).unwrap();
+        write(&repo_dir.join("refs"), "main", "abc123\n");
+        let snapshot = repo_dir.join("snapshots").join("abc123");
+        std::fs::create_dir_all(&snapshot).unwrap();
+        write(&snapshot, "tokenizer.json", "{}");
+        write(&snapshot, "model.safetensors", "weights");
+
+        let model = ModelConfig {
+            repo: "google/test".to_string(),
+            source: ModelSource::Cache(root.clone()),
+        };
+        assert_eq!(resolve_model_dir(&model).unwrap(), Some(snapshot));
+
+        let missing = ModelConfig {
+            repo: "google/missing".to_string(),
+            ..model
+        };
+        assert!(resolve_model_dir(&missing).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
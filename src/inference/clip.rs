@@ -0,0 +1,122 @@
+use crate::{
+    cu29::msgs::ImageRgb8,
+    inference::{normalize, resolve_model_dir, EmbeddingBackend, InferenceError, ModelConfig},
+};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::clip::{ClipConfig, ClipModel};
+use kornia::imgproc::{interpolation::InterpolationMode, resize::resize_fast};
+use std::path::{Path, PathBuf};
+use tokenizers::Tokenizer;
+
+/// The maximum number of tokens of the CLIP text encoder
+const MAX_TEXT_TOKENS: usize = 77;
+
+/// Embedding backend running OpenAI CLIP on CPU via candle
+pub struct ClipBackend {
+    model: ClipModel,
+    tokenizer: Tokenizer,
+    image_size: usize,
+    device: Device,
+}
+
+impl ClipBackend {
+    pub fn new(model: &ModelConfig) -> Result<Self, InferenceError> {
+        let model_dir = resolve_model_dir(model)?;
+        let weights = model_file(model, model_dir.as_deref(), "model.safetensors")?;
+        let tokenizer = model_file(model, model_dir.as_deref(), "tokenizer.json")?;
+
+        let config = ClipConfig::vit_base_patch32();
+        let device = Device::Cpu;
+
+        // SAFETY: the weights are not modified while they are mapped
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], DType::F32, &device) }
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+        let clip =
+            ClipModel::new(vb, &config).map_err(|e| InferenceError::Backend(e.to_string()))?;
+        let tokenizer =
+            Tokenizer::from_file(tokenizer).map_err(|e| InferenceError::Backend(e.to_string()))?;
+
+        Ok(Self {
+            model: clip,
+            tokenizer,
+            image_size: config.image_size,
+            device,
+        })
+    }
+
+    fn to_vector(features: Tensor) -> Result<Vec<f32>, InferenceError> {
+        let vector = features
+            .squeeze(0)
+            .and_then(|features| features.to_vec1::<f32>())
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+        Ok(normalize(vector))
+    }
+}
+
+impl EmbeddingBackend for ClipBackend {
+    fn name(&self) -> &str {
+        "clip"
+    }
+
+    fn embed_image(&mut self, image: &ImageRgb8) -> Result<Vec<f32>, InferenceError> {
+        let size = self.image_size;
+
+        // NOTE: the image is stretched to the square input of the model
+        let mut resized = ImageRgb8::from_size_val([size, size].into(), 0u8)
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+        resize_fast(image, &mut resized, InterpolationMode::Bilinear)
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+
+        let features = Tensor::from_vec(resized.as_slice().to_vec(), (size, size, 3), &self.device)
+            .and_then(|pixels| pixels.permute((2, 0, 1)))
+            .and_then(|pixels| pixels.to_dtype(DType::F32))
+            .and_then(|pixels| pixels.affine(2.0 / 255.0, -1.0))
+            .and_then(|pixels| pixels.unsqueeze(0))
+            .and_then(|pixels| self.model.get_image_features(&pixels))
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+
+        Self::to_vector(features)
+    }
+
+    fn embed_text(&mut self, text: &str) -> Result<Vec<f32>, InferenceError> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+        let mut tokens = encoding.get_ids().to_vec();
+        tokens.truncate(MAX_TEXT_TOKENS);
+
+        let features = Tensor::new(tokens.as_slice(), &self.device)
+            .and_then(|input_ids| input_ids.unsqueeze(0))
+            .and_then(|input_ids| self.model.get_text_features(&input_ids))
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+
+        Self::to_vector(features)
+    }
+}
+
+/// The path to a file of the model, downloaded from the hub if there is no model directory
+fn model_file(
+    model: &ModelConfig,
+    model_dir: Option<&Path>,
+    file: &str,
+) -> Result<PathBuf, InferenceError> {
+    match model_dir {
+        Some(model_dir) => {
+            let path = model_dir.join(file);
+            if !path.is_file() {
+                return Err(InferenceError::ModelFiles(format!(
+                    "{} of {} not found in {}",
+                    file,
+                    model.repo,
+                    model_dir.display()
+                )));
+            }
+            Ok(path)
+        }
+        None => hf_hub::api::sync::Api::new()
+            .and_then(|api| api.model(model.repo.clone()).get(file))
+            .map_err(|e| InferenceError::ModelFiles(format!("{}: {}", model.repo, e))),
+    }
+}
@@ -0,0 +1,256 @@
+use crate::cu29::msgs::Detection;
+
+/// The number of bins the box coordinates are quantized into
+const LOC_BINS: f32 = 1024.0;
+
+/// The number of segmentation codes describing an object
+const SEG_TOKENS: usize = 16;
+
+/// Check if the prompt asks the model for detections or segmentations
+///
+/// The `segment` prompts are parsed into boxes with their raw codes, not into masks.
+pub fn is_detection_prompt(prompt: &str) -> bool {
+    let prompt = prompt.trim_start();
+    prompt.starts_with("detect") || prompt.starts_with("segment")
+}
+
+/// Parse the location and segmentation tokens of a PaliGemma response into detections
+///
+/// Each object is given by four `<locXXXX>` tokens in the order `y_min, x_min, y_max, x_max`,
+/// optionally followed by sixteen `<segXXX>` tokens for `segment` prompts, and its label.
+/// The objects are separated by `;`, e.g. `<loc0100><loc0200><loc0300><loc0400> person`.
+///
+/// The segmentation codes are returned as they are, they are not decoded into a mask.
+///
+/// # Arguments
+///
+/// * `response` - The raw response of the model
+///
+/// # Returns
+///
+/// The detections with their boxes normalized to the image size, the objects
+/// without a complete box are skipped
+pub fn parse_detections(response: &str) -> Vec<Detection> {
+    response
+        .split(';')
+        .filter_map(|object| {
+            let mut locs = Vec::new();
+            let mut segs = Vec::new();
+
+            let mut rest = object.trim_start();
+            while let Some((token, value, tail)) = next_token(rest) {
+                match token {
+                    "loc" => locs.push(value),
+                    "seg" => segs.push(value),
+                    _ => break,
+                }
+                rest = tail.trim_start();
+            }
+
+            let [y_min, x_min, y_max, x_max] = locs.get(..4)? else {
+                return None;
+            };
+
+            let normalize = |v: &u16| (*v as f32 / LOC_BINS).clamp(0.0, 1.0);
+
+            Some(Detection {
+                label: rest.trim().to_string(),
+                bbox: [
+                    normalize(x_min),
+                    normalize(y_min),
+                    normalize(x_max),
+                    normalize(y_max),
+                ],
+                seg_codes: (segs.len() == SEG_TOKENS).then_some(segs),
+                score: None,
+            })
+        })
+        .collect()
+}
+
+/// Split the leading `<nameXXXX>` token of the text into its name, value and the remaining text
+fn next_token(text: &str) -> Option<(&str, u16, &str)> {
+    let inner = text.strip_prefix('<')?;
+    let end = inner.find('>')?;
+    let token = &inner[..end];
+    let digits = token.find(|c: char| c.is_ascii_digit())?;
+    let value = token[digits..].parse().ok()?;
+    Some((&token[..digits], value, &inner[end + 1..]))
+}
+
+/// The intersection over union of two boxes given as `[x_min, y_min, x_max, y_max]`
+pub fn box_iou(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    let width = (a[2].min(b[2]) - a[0].max(b[0])).max(0.0);
+    let height = (a[3].min(b[3]) - a[1].max(b[1])).max(0.0);
+    let intersection = width * height;
+
+    let area = |bbox: &[f32; 4]| (bbox[2] - bbox[0]).max(0.0) * (bbox[3] - bbox[1]).max(0.0);
+    let union = area(a) + area(b) - intersection;
+
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+/// Keep the most confident detections of each label among the overlapping ones
+///
+/// # Arguments
+///
+/// * `detections` - The detections with their scores
+/// * `iou_threshold` - The IoU above which the less confident detection is discarded
+///
+/// # Returns
+///
+/// The kept detections, the most confident first
+pub fn non_max_suppression(mut detections: Vec<Detection>, iou_threshold: f32) -> Vec<Detection> {
+    detections.sort_by(|a, b| {
+        b.score
+            .unwrap_or_default()
+            .total_cmp(&a.score.unwrap_or_default())
+    });
+
+    let mut kept: Vec<Detection> = Vec::new();
+    for detection in detections {
+        let overlaps = kept.iter().any(|other| {
+            other.label == detection.label && box_iou(&other.bbox, &detection.bbox) > iou_threshold
+        });
+        if !overlaps {
+            kept.push(detection);
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_locations_as_y_x_y_x() {
+        let detections = parse_detections("<loc0256><loc0512><loc0768><loc1024> person");
+
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].label, "person");
+        assert_eq!(detections[0].bbox, [0.5, 0.25, 1.0, 0.75]);
+        assert!(detections[0].seg_codes.is_none());
+    }
+
+    #[test]
+    fn splits_the_objects_on_semicolons() {
+        let detections = parse_detections(
+            "<loc0000><loc0000><loc0512><loc0512> person ; <loc0512><loc0512><loc1024><loc1024> red car",
+        );
+
+        let labels = detections
+            .iter()
+            .map(|detection| detection.label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["person", "red car"]);
+        assert_eq!(detections[1].bbox, [0.5, 0.5, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn skips_the_incomplete_boxes() {
+        let detections = parse_detections(
+            "<loc0100><loc0200><loc0300> person ; car ; <loc0000><loc0000><loc1024><loc1024> dog",
+        );
+
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].label, "dog");
+        assert!(parse_detections("a person walking on the street").is_empty());
+    }
+
+    #[test]
+    fn keeps_the_segmentation_codes() {
+        let segs = (0..SEG_TOKENS)
+            .map(|i| format!("<seg{:03}>", i * 8))
+            .collect::<String>();
+        let detections =
+            parse_detections(&format!("<loc0000><loc0000><loc1024><loc1024>{} cat", segs));
+
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].label, "cat");
+        assert_eq!(
+            detections[0].seg_codes,
+            Some((0..SEG_TOKENS as u16).map(|i| i * 8).collect())
+        );
+
+        // the partial codes are dropped but the box is kept
+        let detections =
+            parse_detections("<loc0000><loc0000><loc1024><loc1024><seg001><seg002> cat");
+        assert_eq!(detections.len(), 1);
+        assert!(detections[0].seg_codes.is_none());
+    }
+
+    #[test]
+    fn clamps_the_locations_to_the_image() {
+        let detections = parse_detections("<loc0000><loc0000><loc2048><loc2048> sky");
+        assert_eq!(detections[0].bbox, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    fn detection(label: &str, bbox: [f32; 4], score: f32) -> Detection {
+        Detection {
+            label: label.to_string(),
+            bbox,
+            seg_codes: None,
+            score: Some(score),
+        }
+    }
+
+    #[test]
+    fn box_iou_of_overlapping_boxes() {
+        assert_eq!(box_iou(&[0.0, 0.0, 0.5, 0.5], &[0.0, 0.0, 0.5, 0.5]), 1.0);
+        assert_eq!(
+            box_iou(&[0.0, 0.0, 0.5, 0.5], &[0.25, 0.0, 0.75, 0.5]),
+            1.0 / 3.0
+        );
+        assert_eq!(box_iou(&[0.0, 0.0, 0.2, 0.2], &[0.5, 0.5, 1.0, 1.0]), 0.0);
+        assert_eq!(box_iou(&[0.5, 0.5, 0.5, 0.5], &[0.5, 0.5, 0.5, 0.5]), 0.0);
+    }
+
+    #[test]
+    fn suppresses_the_less_confident_overlapping_boxes() {
+        let detections = non_max_suppression(
+            vec![
+                detection("person", [0.0, 0.0, 0.5, 0.5], 0.6),
+                detection("person", [0.05, 0.0, 0.55, 0.5], 0.9),
+                detection("person", [0.5, 0.5, 1.0, 1.0], 0.4),
+            ],
+            0.45,
+        );
+
+        let scores = detections.iter().map(|d| d.score).collect::<Vec<_>>();
+        assert_eq!(scores, [Some(0.9), Some(0.4)]);
+    }
+
+    #[test]
+    fn keeps_the_overlapping_boxes_of_other_labels() {
+        let detections = non_max_suppression(
+            vec![
+                detection("person", [0.0, 0.0, 0.5, 0.5], 0.6),
+                detection("dog", [0.0, 0.0, 0.5, 0.5], 0.8),
+            ],
+            0.45,
+        );
+
+        let labels = detections
+            .iter()
+            .map(|d| d.label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["dog", "person"]);
+    }
+
+    #[test]
+    fn keeps_the_boxes_overlapping_below_the_threshold() {
+        // the boxes overlap with an IoU of 1/3
+        let boxes = vec![
+            detection("car", [0.0, 0.0, 0.5, 0.5], 0.9),
+            detection("car", [0.25, 0.0, 0.75, 0.5], 0.8),
+        ];
+
+        assert_eq!(non_max_suppression(boxes.clone(), 0.45).len(), 2);
+        assert_eq!(non_max_suppression(boxes, 0.3).len(), 1);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{
+    cu29::msgs::{Detection, ImageRgb8},
+    inference::{non_max_suppression, InferenceError},
+};
+use kornia::imgproc::{interpolation::InterpolationMode, resize::resize_fast};
+use std::path::{Path, PathBuf};
+use tract_onnx::prelude::*;
+
+/// The labels of the COCO dataset the YOLO models are trained on
+pub const COCO_LABELS: [&str; 80] = [
+    "person",
+    "bicycle",
+    "car",
+    "motorcycle",
+    "airplane",
+    "bus",
+    "train",
+    "truck",
+    "boat",
+    "traffic light",
+    "fire hydrant",
+    "stop sign",
+    "parking meter",
+    "bench",
+    "bird",
+    "cat",
+    "dog",
+    "horse",
+    "sheep",
+    "cow",
+    "elephant",
+    "bear",
+    "zebra",
+    "giraffe",
+    "backpack",
+    "umbrella",
+    "handbag",
+    "tie",
+    "suitcase",
+    "frisbee",
+    "skis",
+    "snowboard",
+    "sports ball",
+    "kite",
+    "baseball bat",
+    "baseball glove",
+    "skateboard",
+    "surfboard",
+    "tennis racket",
+    "bottle",
+    "wine glass",
+    "cup",
+    "fork",
+    "knife",
+    "spoon",
+    "bowl",
+    "banana",
+    "apple",
+    "sandwich",
+    "orange",
+    "broccoli",
+    "carrot",
+    "hot dog",
+    "pizza",
+    "donut",
+    "cake",
+    "chair",
+    "couch",
+    "potted plant",
+    "bed",
+    "dining table",
+    "toilet",
+    "tv",
+    "laptop",
+    "mouse",
+    "remote",
+    "keyboard",
+    "cell phone",
+    "microwave",
+    "oven",
+    "toaster",
+    "sink",
+    "refrigerator",
+    "book",
+    "clock",
+    "vase",
+    "scissors",
+    "teddy bear",
+    "hair drier",
+    "toothbrush",
+];
+
+/// The configuration of the ONNX object detector
+#[derive(Clone, Debug)]
+pub struct DetectorConfig {
+    /// the path to the `.onnx` model
+    pub model_path: PathBuf,
+    /// the size of the square input of the model
+    pub input_size: usize,
+    /// the minimum confidence of the kept detections
+    pub score_threshold: f32,
+    /// the IoU above which the overlapping detections are suppressed
+    pub iou_threshold: f32,
+    /// the labels of the classes, the COCO labels by default
+    pub labels: Vec<String>,
+    /// only keep these labels, all of them if empty
+    pub classes: Vec<String>,
+}
+
+impl DetectorConfig {
+    pub fn new(model_path: impl Into<PathBuf>) -> Self {
+        Self {
+            model_path: model_path.into(),
+            input_size: 640,
+            score_threshold: 0.25,
+            iou_threshold: 0.45,
+            labels: COCO_LABELS.iter().map(|label| label.to_string()).collect(),
+            classes: Vec::new(),
+        }
+    }
+}
+
+type DetectorModel = SimplePlan<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
+
+/// A YOLOv8-style object detector running on CPU with the pure-Rust tract runtime
+///
+/// The model takes a `1x3xSxS` image normalized to `[0, 1]` and returns the boxes as
+/// `1x(4+C)xN` rows of `center_x, center_y, width, height` in input pixels followed by
+/// the scores of the `C` classes.
+pub struct OnnxDetector {
+    config: DetectorConfig,
+    model: DetectorModel,
+}
+
+impl OnnxDetector {
+    pub fn new(config: DetectorConfig) -> Result<Self, InferenceError> {
+        let model = load_model(&config.model_path, config.input_size).map_err(|e| {
+            InferenceError::Backend(format!("{}: {}", config.model_path.display(), e))
+        })?;
+        Ok(Self { config, model })
+    }
+
+    /// Detect the objects of an image
+    ///
+    /// # Returns
+    ///
+    /// The detections with their boxes normalized to the image size and their scores
+    pub fn detect(&self, image: &ImageRgb8) -> Result<Vec<Detection>, InferenceError> {
+        let size = self.config.input_size;
+
+        // NOTE: the image is stretched, so the normalized boxes map back to the original image
+        let mut resized = ImageRgb8::from_size_val([size, size].into(), 0u8)
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+        resize_fast(image, &mut resized, InterpolationMode::Bilinear)
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+
+        let pixels = resized.as_slice();
+        let input: Tensor =
+            tract_ndarray::Array4::from_shape_fn((1, 3, size, size), |(_, c, y, x)| {
+                pixels[(y * size + x) * 3 + c] as f32 / 255.0
+            })
+            .into();
+
+        let outputs = self
+            .model
+            .run(tvec!(input.into()))
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+        let output = outputs[0]
+            .to_array_view::<f32>()
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+
+        let detections = decode_yolo_output(output, &self.config)?;
+
+        Ok(non_max_suppression(detections, self.config.iou_threshold))
+    }
+}
+
+/// Decode the output of a YOLOv8-style model into the detections above the score threshold
+///
+/// # Arguments
+///
+/// * `output` - The `1x(4+C)xN` or `1xNx(4+C)` boxes in input pixels and class scores
+/// * `config` - The input size, the score threshold, the labels and the classes to keep
+///
+/// # Returns
+///
+/// The detections with their boxes normalized to the input size, before the suppression
+pub fn decode_yolo_output(
+    output: tract_ndarray::ArrayViewD<f32>,
+    config: &DetectorConfig,
+) -> Result<Vec<Detection>, InferenceError> {
+    let shape = output.shape().to_vec();
+    let [1, rows, cols] = shape[..] else {
+        return Err(InferenceError::Backend(format!(
+            "Unexpected output shape {:?}, expected 1x(4+C)xN",
+            shape
+        )));
+    };
+    let output = output
+        .index_axis_move(tract_ndarray::Axis(0), 0)
+        .into_dimensionality::<tract_ndarray::Ix2>()
+        .map_err(|e| InferenceError::Backend(e.to_string()))?;
+
+    // the exported models differ on whether the boxes are the rows or the columns
+    let boxes = if rows > cols {
+        output
+    } else {
+        output.reversed_axes()
+    };
+
+    let size = config.input_size as f32;
+    let normalize = |v: f32| (v / size).clamp(0.0, 1.0);
+
+    let mut detections = Vec::new();
+    for values in boxes.outer_iter() {
+        let Some((class, score)) = values
+            .iter()
+            .skip(4)
+            .copied()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+        else {
+            continue;
+        };
+        if score < config.score_threshold {
+            continue;
+        }
+
+        let label = config
+            .labels
+            .get(class)
+            .cloned()
+            .unwrap_or_else(|| format!("class_{}", class));
+        if !config.classes.is_empty() && !config.classes.contains(&label) {
+            continue;
+        }
+
+        let (cx, cy, w, h) = (values[0], values[1], values[2], values[3]);
+        detections.push(Detection {
+            label,
+            bbox: [
+                normalize(cx - w / 2.0),
+                normalize(cy - h / 2.0),
+                normalize(cx + w / 2.0),
+                normalize(cy + h / 2.0),
+            ],
+            seg_codes: None,
+            score: Some(score),
+        });
+    }
+
+    Ok(detections)
+}
+
+fn load_model(path: &Path, input_size: usize) -> TractResult<DetectorModel> {
+    tract_onnx::onnx()
+        .model_for_path(path)?
+        .with_input_fact(0, f32::fact([1, 3, input_size, input_size]).into())?
+        .into_optimized()?
+        .into_runnable()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The number of boxes of the test outputs, more than the values of a box
+    const NUM_BOXES: usize = 8;
+
+    fn config() -> DetectorConfig {
+        DetectorConfig {
+            input_size: 100,
+            score_threshold: 0.5,
+            labels: vec!["person".to_string(), "car".to_string()],
+            ..DetectorConfig::new("yolov8n.onnx")
+        }
+    }
+
+    /// A `1x(4+3)xN` output, or `1xNx(4+3)` if transposed, padded with empty boxes
+    fn output(boxes: &[[f32; 7]], transposed: bool) -> tract_ndarray::ArrayD<f32> {
+        let value = |b: usize, i: usize| boxes.get(b).map_or(0.0, |values| values[i]);
+        if transposed {
+            tract_ndarray::Array3::from_shape_fn((1, NUM_BOXES, 7), |(_, b, i)| value(b, i))
+        } else {
+            tract_ndarray::Array3::from_shape_fn((1, 7, NUM_BOXES), |(_, i, b)| value(b, i))
+        }
+        .into_dyn()
+    }
+
+    fn assert_bbox(actual: [f32; 4], expected: [f32; 4]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn decodes_both_output_orientations() {
+        let boxes = [
+            [50.0, 50.0, 20.0, 40.0, 0.1, 0.9, 0.0],
+            [10.0, 20.0, 10.0, 10.0, 0.7, 0.2, 0.0],
+        ];
+
+        for transposed in [false, true] {
+            let detections =
+                decode_yolo_output(output(&boxes, transposed).view(), &config()).unwrap();
+
+            assert_eq!(detections.len(), 2, "transposed: {}", transposed);
+            assert_eq!(detections[0].label, "car");
+            assert_eq!(detections[0].score, Some(0.9));
+            assert_bbox(detections[0].bbox, [0.4, 0.3, 0.6, 0.7]);
+            assert_eq!(detections[1].label, "person");
+            assert_eq!(detections[1].score, Some(0.7));
+            assert_bbox(detections[1].bbox, [0.05, 0.15, 0.15, 0.25]);
+        }
+    }
+
+    #[test]
+    fn drops_the_boxes_below_the_score_threshold() {
+        let boxes = [
+            [50.0, 50.0, 20.0, 20.0, 0.49, 0.3, 0.0],
+            [50.0, 50.0, 20.0, 20.0, 0.2, 0.5, 0.0],
+        ];
+        let detections = decode_yolo_output(output(&boxes, false).view(), &config()).unwrap();
+
+        let scores = detections.iter().map(|d| d.score).collect::<Vec<_>>();
+        assert_eq!(scores, [Some(0.5)]);
+    }
+
+    #[test]
+    fn keeps_the_selected_classes() {
+        let boxes = [
+            [50.0, 50.0, 20.0, 20.0, 0.9, 0.0, 0.0],
+            [50.0, 50.0, 20.0, 20.0, 0.0, 0.9, 0.0],
+            [50.0, 50.0, 20.0, 20.0, 0.0, 0.0, 0.9],
+        ];
+
+        let labels = |config: &DetectorConfig| {
+            decode_yolo_output(output(&boxes, false).view(), config)
+                .unwrap()
+                .into_iter()
+                .map(|d| d.label)
+                .collect::<Vec<_>>()
+        };
+        // the classes without a label are named after their index
+        assert_eq!(labels(&config()), ["person", "car", "class_2"]);
+
+        let config = DetectorConfig {
+            classes: vec!["car".to_string()],
+            ..config()
+        };
+        assert_eq!(labels(&config), ["car"]);
+    }
+
+    #[test]
+    fn clamps_the_boxes_to_the_image() {
+        let boxes = [[0.0, 95.0, 20.0, 20.0, 0.9, 0.0, 0.0]];
+        let detections = decode_yolo_output(output(&boxes, true).view(), &config()).unwrap();
+
+        assert_bbox(detections[0].bbox, [0.0, 0.85, 0.1, 1.0]);
+    }
+
+    #[test]
+    fn rejects_unexpected_output_shapes() {
+        let output = tract_ndarray::ArrayD::<f32>::zeros(vec![7, NUM_BOXES]);
+        assert!(decode_yolo_output(output.view(), &config()).is_err());
+
+        let output = tract_ndarray::ArrayD::<f32>::zeros(vec![2, 7, NUM_BOXES]);
+        assert!(decode_yolo_output(output.view(), &config()).is_err());
+    }
+}
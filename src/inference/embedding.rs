@@ -0,0 +1,170 @@
+use crate::{
+    cu29::msgs::ImageRgb8,
+    inference::{InferenceError, ModelConfig},
+};
+use cu29::prelude::ComponentConfig;
+
+/// The embedding backend to use if no backend is provided
+pub const DEFAULT_EMBEDDING_BACKEND: &str = "clip";
+
+/// The repository of the CLIP weights on the Hugging Face hub
+pub const DEFAULT_CLIP_REPO: &str = "openai/clip-vit-base-patch32";
+
+/// A model that maps the images and the texts to the same vector space
+///
+/// The vectors are normalized, so their dot product is their cosine similarity.
+pub trait EmbeddingBackend: Send {
+    /// The name of the backend, e.g. `clip`
+    fn name(&self) -> &str;
+
+    /// Embed an image
+    fn embed_image(&mut self, image: &ImageRgb8) -> Result<Vec<f32>, InferenceError>;
+
+    /// Embed a text describing an image
+    fn embed_text(&mut self, text: &str) -> Result<Vec<f32>, InferenceError>;
+}
+
+/// The configuration to create an embedding backend
+#[derive(Clone, Debug)]
+pub struct EmbeddingConfig {
+    /// the name of the backend, `clip` or `mock`
+    pub backend: String,
+    /// the weights of the clip backend
+    pub model: ModelConfig,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            backend: DEFAULT_EMBEDDING_BACKEND.to_string(),
+            model: ModelConfig {
+                repo: DEFAULT_CLIP_REPO.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    /// Parse the embedding configuration from the task config
+    ///
+    /// The keys are `backend` and the model keys of [`ModelConfig`].
+    pub fn from_component_config(config: Option<&ComponentConfig>) -> Result<Self, InferenceError> {
+        let mut embedding_config = Self::default();
+        let Some(config) = config else {
+            return Ok(embedding_config);
+        };
+        if let Some(backend) = config.get::<String>("backend") {
+            embedding_config.backend = backend;
+        }
+        let model = ModelConfig::from_component_config(Some(config))?;
+        embedding_config.model.source = model.source;
+        if config.get::<String>("model_repo").is_some() {
+            embedding_config.model.repo = model.repo;
+        }
+        Ok(embedding_config)
+    }
+
+    /// The name of the model loaded by the backend, stored with the embeddings
+    pub fn model_name(&self) -> String {
+        match self.backend.as_str() {
+            "clip" => self.model.name().to_string(),
+            backend => backend.to_string(),
+        }
+    }
+}
+
+/// Create the embedding backend selected in the configuration
+pub fn create_embedding_backend(
+    config: &EmbeddingConfig,
+) -> Result<Box<dyn EmbeddingBackend>, InferenceError> {
+    match config.backend.as_str() {
+        #[cfg(feature = "clip")]
+        "clip" => Ok(Box::new(crate::inference::ClipBackend::new(&config.model)?)),
+        #[cfg(not(feature = "clip"))]
+        "clip" => Err(InferenceError::Config(
+            "The clip backend requires the `clip` feature".to_string(),
+        )),
+        "mock" => Ok(Box::new(MockEmbeddingBackend)),
+        backend => Err(InferenceError::Config(format!(
+            "Embedding backend {} not supported. Try 'clip' or 'mock' instead",
+            backend
+        ))),
+    }
+}
+
+/// The colors understood by the texts of the mock backend
+const MOCK_COLORS: [(&str, [f32; 3]); 10] = [
+    ("black", [0.0, 0.0, 0.0]),
+    ("white", [255.0, 255.0, 255.0]),
+    ("gray", [128.0, 128.0, 128.0]),
+    ("red", [255.0, 0.0, 0.0]),
+    ("green", [0.0, 255.0, 0.0]),
+    ("blue", [0.0, 0.0, 255.0]),
+    ("yellow", [255.0, 255.0, 0.0]),
+    ("cyan", [0.0, 255.0, 255.0]),
+    ("magenta", [255.0, 0.0, 255.0]),
+    ("orange", [255.0, 165.0, 0.0]),
+];
+
+/// Embedding backend that does not load any model, meant for offline testing
+///
+/// The images are embedded by their mean color and the texts by the color names they
+/// contain, so that e.g. `a red car` finds the reddest frames.
+pub struct MockEmbeddingBackend;
+
+impl EmbeddingBackend for MockEmbeddingBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn embed_image(&mut self, image: &ImageRgb8) -> Result<Vec<f32>, InferenceError> {
+        let mut sum = [0f64; 3];
+        for pixel in image.as_slice().chunks_exact(3) {
+            for (s, p) in sum.iter_mut().zip(pixel) {
+                *s += *p as f64;
+            }
+        }
+        let num_pixels = (image.width() * image.height()).max(1) as f64;
+        Ok(centered_color(sum.map(|s| (s / num_pixels) as f32)))
+    }
+
+    fn embed_text(&mut self, text: &str) -> Result<Vec<f32>, InferenceError> {
+        let text = text.to_lowercase();
+        let colors = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter_map(|word| {
+                let word = if word == "grey" { "gray" } else { word };
+                MOCK_COLORS.iter().find(|(name, _)| *name == word)
+            })
+            .collect::<Vec<_>>();
+        if colors.is_empty() {
+            return Err(InferenceError::Backend(format!(
+                "The mock backend only understands color names, got '{}'",
+                text
+            )));
+        }
+
+        let mut mean = [0f32; 3];
+        for (_, color) in &colors {
+            for (m, c) in mean.iter_mut().zip(color) {
+                *m += c / colors.len() as f32;
+            }
+        }
+        Ok(centered_color(mean))
+    }
+}
+
+/// The normalized offset of a color from the middle gray, so opposite colors do not match
+fn centered_color(color: [f32; 3]) -> Vec<f32> {
+    normalize(color.iter().map(|c| c - 127.5).collect())
+}
+
+/// Scale a vector to a unit length, the null vectors are left unchanged
+pub fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > f32::EPSILON {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
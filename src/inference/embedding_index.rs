@@ -0,0 +1,328 @@
+use crate::api::models::search::SearchRequest;
+use rusqlite::{params, Connection};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// The number of frames returned by a search if no limit is given
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+/// The embedding of a sampled frame
+#[derive(Clone, Debug)]
+pub struct IndexedFrame {
+    pub id: i64,
+    /// the wall time in nanoseconds when the frame was sampled
+    pub wall_time_ns: u64,
+    /// the timestamp of the frame in the pipeline
+    pub stamp_ns: u64,
+    pub channel_id: u8,
+    /// the model the frame was embedded with
+    pub model: String,
+    /// the normalized embedding of the frame
+    pub vector: Vec<f32>,
+}
+
+#[derive(Default)]
+struct IndexState {
+    conn: Option<Connection>,
+    frames: Vec<IndexedFrame>,
+    next_id: i64,
+}
+
+/// A local vector index of the frame embeddings
+///
+/// The embeddings are searched exhaustively in memory, and persisted in a SQLite database
+/// once one is opened.
+#[derive(Clone, Default)]
+pub struct EmbeddingIndex {
+    state: Arc<Mutex<IndexState>>,
+}
+
+impl EmbeddingIndex {
+    /// Open or create the database at the given path and load its embeddings
+    pub fn open(&self, path: &Path) -> rusqlite::Result<()> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS frame_embeddings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                wall_time_ns INTEGER NOT NULL,
+                stamp_ns INTEGER NOT NULL,
+                channel_id INTEGER NOT NULL,
+                model TEXT NOT NULL,
+                vector BLOB NOT NULL
+            );",
+        )?;
+
+        let frames = conn
+            .prepare(
+                "SELECT id, wall_time_ns, stamp_ns, channel_id, model, vector
+                    FROM frame_embeddings ORDER BY id",
+            )?
+            .query_map([], |row| {
+                let vector: Vec<u8> = row.get(5)?;
+                Ok(IndexedFrame {
+                    id: row.get(0)?,
+                    wall_time_ns: row.get::<_, i64>(1)? as u64,
+                    stamp_ns: row.get::<_, i64>(2)? as u64,
+                    channel_id: row.get(3)?,
+                    model: row.get(4)?,
+                    vector: vector
+                        .chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        log::debug!(
+            "Loaded {} frame embeddings from {}",
+            frames.len(),
+            path.display()
+        );
+
+        let mut state = self.state.lock().unwrap();
+        state.next_id = frames.last().map_or(1, |frame| frame.id + 1);
+        state.frames = frames;
+        state.conn = Some(conn);
+        Ok(())
+    }
+
+    /// Add the embedding of a frame
+    ///
+    /// # Arguments
+    ///
+    /// * `wall_time_ns` - The wall time when the frame was sampled, searched by the time range
+    /// * `stamp_ns` - The timestamp of the frame in the pipeline
+    /// * `channel_id` - The channel of the frame
+    /// * `model` - The model the frame was embedded with
+    /// * `vector` - The normalized embedding of the frame
+    ///
+    /// # Returns
+    ///
+    /// The id of the frame in the index
+    pub fn insert(
+        &self,
+        wall_time_ns: u64,
+        stamp_ns: u64,
+        channel_id: u8,
+        model: &str,
+        vector: Vec<f32>,
+    ) -> rusqlite::Result<i64> {
+        let mut state = self.state.lock().unwrap();
+        let id = match state.conn.as_ref() {
+            Some(conn) => {
+                let bytes = vector
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect::<Vec<_>>();
+                conn.execute(
+                    "INSERT INTO frame_embeddings
+                        (wall_time_ns, stamp_ns, channel_id, model, vector)
+                        VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        wall_time_ns as i64,
+                        stamp_ns as i64,
+                        channel_id,
+                        model,
+                        bytes
+                    ],
+                )?;
+                conn.last_insert_rowid()
+            }
+            None => state.next_id.max(1),
+        };
+
+        state.next_id = id + 1;
+        state.frames.push(IndexedFrame {
+            id,
+            wall_time_ns,
+            stamp_ns,
+            channel_id,
+            model: model.to_string(),
+            vector,
+        });
+
+        Ok(id)
+    }
+
+    /// Find the frames the most similar to a query
+    ///
+    /// # Arguments
+    ///
+    /// * `vector` - The normalized embedding of the query
+    /// * `model` - The model the query was embedded with, only its frames are compared
+    /// * `request` - The channel, the wall time range and the number of frames
+    ///
+    /// # Returns
+    ///
+    /// The matching frames with their cosine similarity, the most similar first
+    pub fn search(
+        &self,
+        vector: &[f32],
+        model: &str,
+        request: &SearchRequest,
+    ) -> Vec<(f32, IndexedFrame)> {
+        let state = self.state.lock().unwrap();
+
+        let mut scored = state
+            .frames
+            .iter()
+            .filter(|frame| {
+                frame.model == model
+                    && frame.vector.len() == vector.len()
+                    && request.channel_id.is_none_or(|id| frame.channel_id == id)
+                    && request.from.is_none_or(|from| frame.wall_time_ns >= from)
+                    && request.to.is_none_or(|to| frame.wall_time_ns <= to)
+            })
+            .map(|frame| {
+                let score = frame.vector.iter().zip(vector).map(|(a, b)| a * b).sum();
+                (score, frame)
+            })
+            .collect::<Vec<(f32, _)>>();
+
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(request.limit.unwrap_or(DEFAULT_SEARCH_LIMIT));
+
+        scored
+            .into_iter()
+            .map(|(score, frame)| (score, frame.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cu29::msgs::ImageRgb8,
+        inference::{EmbeddingBackend, MockEmbeddingBackend},
+    };
+
+    const SECOND_NS: u64 = 1_000_000_000;
+
+    fn image(color: [u8; 3]) -> ImageRgb8 {
+        ImageRgb8::new([4, 4].into(), color.repeat(4 * 4)).unwrap()
+    }
+
+    /// Index a red, a green and a blue frame of the channels 0, 1 and 0, a second apart
+    fn insert_colors(index: &EmbeddingIndex, backend: &mut MockEmbeddingBackend) {
+        let frames = [([255, 0, 0], 0), ([0, 255, 0], 1), ([0, 0, 255], 0)];
+        for (i, (color, channel_id)) in frames.into_iter().enumerate() {
+            let vector = backend.embed_image(&image(color)).unwrap();
+            let wall_time_ns = i as u64 * SECOND_NS;
+            index
+                .insert(wall_time_ns, i as u64, channel_id, backend.name(), vector)
+                .unwrap();
+        }
+    }
+
+    fn search(
+        index: &EmbeddingIndex,
+        backend: &mut MockEmbeddingBackend,
+        text: &str,
+        request: SearchRequest,
+    ) -> Vec<(f32, IndexedFrame)> {
+        let vector = backend.embed_text(text).unwrap();
+        index.search(&vector, backend.name(), &request)
+    }
+
+    #[test]
+    fn search_finds_the_described_color_first() {
+        let mut backend = MockEmbeddingBackend;
+        let index = EmbeddingIndex::default();
+        insert_colors(&index, &mut backend);
+
+        let results = search(&index, &mut backend, "a red car", SearchRequest::default());
+        let ids = results
+            .iter()
+            .map(|(_, frame)| frame.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2, 3]);
+        assert!((results[0].0 - 1.0).abs() < 1e-5);
+        assert!(results[1].0 < 0.0);
+
+        let results = search(&index, &mut backend, "blue", SearchRequest::default());
+        assert_eq!(results[0].1.id, 3);
+        assert_eq!(results[0].1.wall_time_ns, 2 * SECOND_NS);
+        assert_eq!(results[0].1.stamp_ns, 2);
+    }
+
+    #[test]
+    fn search_filters_the_frames() {
+        let mut backend = MockEmbeddingBackend;
+        let index = EmbeddingIndex::default();
+        insert_colors(&index, &mut backend);
+
+        let ids = |results: Vec<(f32, IndexedFrame)>| {
+            results
+                .into_iter()
+                .map(|(_, frame)| frame.id)
+                .collect::<Vec<_>>()
+        };
+
+        let request = SearchRequest {
+            channel_id: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(ids(search(&index, &mut backend, "green", request)), [1, 3]);
+
+        let request = SearchRequest {
+            from: Some(SECOND_NS),
+            to: Some(2 * SECOND_NS),
+            ..Default::default()
+        };
+        assert_eq!(ids(search(&index, &mut backend, "red", request)), [2, 3]);
+
+        let request = SearchRequest {
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(ids(search(&index, &mut backend, "green", request)), [2]);
+
+        // the frames embedded by another model are not comparable
+        let vector = backend.embed_text("red").unwrap();
+        assert!(index
+            .search(&vector, "clip-vit-base-patch32", &SearchRequest::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn open_reloads_the_persisted_frames() {
+        let path = std::env::temp_dir().join(format!(
+            "bubbaloop_embeddings_test_{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let mut backend = MockEmbeddingBackend;
+        let index = EmbeddingIndex::default();
+        index.open(&path).unwrap();
+        insert_colors(&index, &mut backend);
+        drop(index);
+
+        let index = EmbeddingIndex::default();
+        index.open(&path).unwrap();
+        let results = search(&index, &mut backend, "green", SearchRequest::default());
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].1.id, 2);
+        assert_eq!(results[0].1.channel_id, 1);
+        assert_eq!(results[0].1.wall_time_ns, SECOND_NS);
+
+        // the new frames continue the ids of the database
+        let vector = backend.embed_image(&image([255, 255, 255])).unwrap();
+        assert_eq!(index.insert(0, 0, 0, "mock", vector).unwrap(), 4);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn mock_backend_only_understands_colors() {
+        let mut backend = MockEmbeddingBackend;
+        assert!(backend.embed_text("a car").is_err());
+        assert_eq!(
+            backend.embed_text("Grey").unwrap(),
+            backend.embed_text("gray").unwrap()
+        );
+    }
+}
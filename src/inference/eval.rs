@@ -0,0 +1,577 @@
+use crate::inference::{box_iou, infer_encoded, parse_detections, BackendConfig, InferenceBackend};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+pub type EvalResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The IoU from which a predicted box matches an expected one
+const MATCH_IOU: f32 = 0.5;
+
+/// The maximum n-gram order of the text overlap
+const MAX_NGRAM: usize = 4;
+
+/// A dataset of images with their expected responses
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EvalManifest {
+    /// the prompt of the samples without their own prompt
+    #[serde(default)]
+    pub prompt: Option<String>,
+    pub samples: Vec<EvalSample>,
+}
+
+/// An image of the dataset with its ground truth
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EvalSample {
+    /// the path to the image, relative to the manifest
+    pub image: PathBuf,
+    /// the prompt to run, the one of the manifest if not provided
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// the expected caption
+    #[serde(default)]
+    pub caption: Option<String>,
+    /// the keywords expected in the response
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// the expected boxes of a `detect` prompt, an empty list scores the false positives
+    #[serde(default)]
+    pub boxes: Option<Vec<ExpectedBox>>,
+}
+
+/// An expected object of a `detect` prompt
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExpectedBox {
+    pub label: String,
+    /// the box as `[x_min, y_min, x_max, y_max]` normalized to the image size
+    pub bbox: [f32; 4],
+}
+
+impl EvalManifest {
+    /// Read a manifest and resolve the paths of its images
+    pub fn load(path: &Path) -> EvalResult<Self> {
+        let mut manifest: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for sample in &mut manifest.samples {
+            if sample.image.is_relative() {
+                sample.image = dir.join(&sample.image);
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// The matching of the predicted boxes of a sample with the expected ones
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BoxMetrics {
+    /// the mean IoU of the expected boxes with their matched prediction, 0 if unmatched,
+    /// none if no box is expected
+    pub iou: Option<f64>,
+    /// the number of expected boxes matched with an IoU of at least 0.5
+    pub matched: usize,
+    pub predicted: usize,
+    pub expected: usize,
+}
+
+/// The response and the metrics of a sample
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EvalSampleResult {
+    pub image: String,
+    pub prompt: String,
+    pub response: String,
+    pub latency_ms: u64,
+    /// the reason why the sample could not be processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// the normalized response equals the expected caption
+    pub exact_match: Option<bool>,
+    /// the ratio of the expected keywords found in the response
+    pub keyword_match: Option<f64>,
+    /// the BLEU-like overlap of the response with the expected caption
+    pub text_overlap: Option<f64>,
+    pub boxes: Option<BoxMetrics>,
+}
+
+/// The latency percentiles in milliseconds
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LatencyStats {
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+/// The metrics aggregated over the samples providing their ground truth
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct EvalMetrics {
+    pub exact_match: Option<f64>,
+    pub keyword_match: Option<f64>,
+    pub text_overlap: Option<f64>,
+    pub box_iou: Option<f64>,
+    pub box_precision: Option<f64>,
+    pub box_recall: Option<f64>,
+    pub latency_ms: LatencyStats,
+}
+
+/// The report of an evaluation run
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EvalReport {
+    /// the path to the manifest
+    pub manifest: String,
+    /// the model that produced the responses
+    pub model: String,
+    /// the backend configuration, to reproduce the run
+    pub config: BackendConfig,
+    pub num_samples: usize,
+    pub num_errors: usize,
+    pub metrics: EvalMetrics,
+    pub samples: Vec<EvalSampleResult>,
+}
+
+/// Run the backend on every sample of the manifest and score the responses
+///
+/// # Arguments
+///
+/// * `backend` - The loaded backend to evaluate
+/// * `config` - The configuration the backend was created with
+/// * `manifest` - The samples with their ground truth
+/// * `manifest_path` - The path to the manifest, recorded in the report
+/// * `default_prompt` - The prompt of the samples without prompt in the manifest
+/// * `on_sample` - Called with the result of each sample, e.g. to report the progress
+pub fn run_eval(
+    backend: &mut dyn InferenceBackend,
+    config: &BackendConfig,
+    manifest: &EvalManifest,
+    manifest_path: &Path,
+    default_prompt: &str,
+    mut on_sample: impl FnMut(&EvalSampleResult),
+) -> EvalReport {
+    let mut samples = Vec::with_capacity(manifest.samples.len());
+
+    for sample in &manifest.samples {
+        let prompt = sample
+            .prompt
+            .as_deref()
+            .or(manifest.prompt.as_deref())
+            .unwrap_or(default_prompt)
+            .to_string();
+
+        let result = std::fs::read(&sample.image)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                infer_encoded(backend, &data, &prompt, &config.params).map_err(|e| e.to_string())
+            });
+
+        // the failed samples are scored with an empty response
+        let (response, latency_ms, error) = match result {
+            Ok((response, latency_ms)) => (response, latency_ms, None),
+            Err(e) => (String::new(), 0, Some(e)),
+        };
+
+        let result = EvalSampleResult {
+            image: sample.image.display().to_string(),
+            exact_match: sample
+                .caption
+                .as_ref()
+                .map(|caption| normalize_text(caption) == normalize_text(&response)),
+            keyword_match: (!sample.keywords.is_empty())
+                .then(|| keyword_match(&response, &sample.keywords)),
+            text_overlap: sample
+                .caption
+                .as_ref()
+                .map(|caption| text_overlap(&response, caption)),
+            boxes: sample
+                .boxes
+                .as_ref()
+                .map(|boxes| match_boxes(&response, boxes)),
+            prompt,
+            response,
+            latency_ms,
+            error,
+        };
+
+        on_sample(&result);
+        samples.push(result);
+    }
+
+    EvalReport {
+        manifest: manifest_path.display().to_string(),
+        model: config.model_name(),
+        config: config.clone(),
+        num_samples: samples.len(),
+        num_errors: samples.iter().filter(|s| s.error.is_some()).count(),
+        metrics: aggregate(&samples),
+        samples,
+    }
+}
+
+impl EvalReport {
+    /// Render the report as a Markdown document
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        let metric = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.3}", v));
+
+        let _ = writeln!(md, "# Evaluation report\n");
+        let _ = writeln!(md, "* manifest: `{}`", self.manifest);
+        let _ = writeln!(
+            md,
+            "* model: `{}` (backend `{}`)",
+            self.model, self.config.backend
+        );
+        let _ = writeln!(
+            md,
+            "* samples: {} ({} errors)\n",
+            self.num_samples, self.num_errors
+        );
+
+        let metrics = &self.metrics;
+        let _ = writeln!(md, "| Metric | Value |");
+        let _ = writeln!(md, "|---|---|");
+        let _ = writeln!(md, "| Exact match | {} |", metric(metrics.exact_match));
+        let _ = writeln!(md, "| Keyword match | {} |", metric(metrics.keyword_match));
+        let _ = writeln!(md, "| Text overlap | {} |", metric(metrics.text_overlap));
+        let _ = writeln!(md, "| Box IoU | {} |", metric(metrics.box_iou));
+        let _ = writeln!(md, "| Box precision | {} |", metric(metrics.box_precision));
+        let _ = writeln!(md, "| Box recall | {} |", metric(metrics.box_recall));
+        let latency = &metrics.latency_ms;
+        let _ = writeln!(md, "| Latency mean (ms) | {:.1} |", latency.mean);
+        let _ = writeln!(md, "| Latency p50 (ms) | {} |", latency.p50);
+        let _ = writeln!(md, "| Latency p90 (ms) | {} |", latency.p90);
+        let _ = writeln!(md, "| Latency p99 (ms) | {} |", latency.p99);
+        let _ = writeln!(md, "| Latency max (ms) | {} |", latency.max);
+
+        let _ = writeln!(md, "\n## Samples\n");
+        let _ = writeln!(
+            md,
+            "| Image | Prompt | Response | Exact | Keywords | Overlap | Box IoU | Latency (ms) |"
+        );
+        let _ = writeln!(md, "|---|---|---|---|---|---|---|---|");
+        for sample in &self.samples {
+            let response = match &sample.error {
+                Some(error) => format!("**error**: {}", error),
+                None => sample.response.clone(),
+            };
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {} | {} | {} | {} |",
+                escape_cell(&sample.image),
+                escape_cell(&sample.prompt),
+                escape_cell(&response),
+                sample
+                    .exact_match
+                    .map_or("-".to_string(), |exact| exact.to_string()),
+                metric(sample.keyword_match),
+                metric(sample.text_overlap),
+                metric(sample.boxes.as_ref().and_then(|boxes| boxes.iou)),
+                sample.latency_ms
+            );
+        }
+
+        md
+    }
+}
+
+/// Lowercase the text and keep only its words
+fn normalize_text(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn keyword_match(response: &str, keywords: &[String]) -> f64 {
+    let words = normalize_text(response);
+    let words = words.split_whitespace().collect::<Vec<_>>();
+    let found = keywords
+        .iter()
+        .filter(|keyword| {
+            // the keywords can span several words, e.g. `red car`
+            let keyword = normalize_text(keyword);
+            let keyword = keyword.split_whitespace().collect::<Vec<_>>();
+            !keyword.is_empty() && words.windows(keyword.len()).any(|window| window == keyword)
+        })
+        .count();
+    found as f64 / keywords.len() as f64
+}
+
+/// A sentence-level BLEU score with smoothed precisions for the higher n-gram orders
+fn text_overlap(response: &str, reference: &str) -> f64 {
+    let candidate = normalize_text(response);
+    let reference = normalize_text(reference);
+    let candidate = candidate.split_whitespace().collect::<Vec<_>>();
+    let reference = reference.split_whitespace().collect::<Vec<_>>();
+    if candidate.is_empty() || reference.is_empty() {
+        return 0.0;
+    }
+
+    let max_n = MAX_NGRAM.min(candidate.len()).min(reference.len());
+    let mut log_precision = 0.0;
+    for n in 1..=max_n {
+        let mut reference_counts = HashMap::new();
+        for ngram in reference.windows(n) {
+            *reference_counts.entry(ngram).or_insert(0usize) += 1;
+        }
+
+        // the candidate n-grams are counted at most as many times as in the reference
+        let mut matches = 0;
+        for ngram in candidate.windows(n) {
+            if let Some(count) = reference_counts.get_mut(ngram) {
+                if *count > 0 {
+                    *count -= 1;
+                    matches += 1;
+                }
+            }
+        }
+        let total = candidate.len() - n + 1;
+
+        let precision = if n == 1 {
+            matches as f64 / total as f64
+        } else {
+            (matches as f64 + 1.0) / (total as f64 + 1.0)
+        };
+        if precision == 0.0 {
+            return 0.0;
+        }
+        log_precision += precision.ln() / max_n as f64;
+    }
+
+    // penalize the responses shorter than the reference
+    let brevity_penalty = if candidate.len() < reference.len() {
+        (1.0 - reference.len() as f64 / candidate.len() as f64).exp()
+    } else {
+        1.0
+    };
+
+    brevity_penalty * log_precision.exp()
+}
+
+/// Greedily match the predicted boxes with the expected boxes of the same label
+fn match_boxes(response: &str, expected: &[ExpectedBox]) -> BoxMetrics {
+    let predicted = parse_detections(response);
+
+    let mut pairs = Vec::new();
+    for (i, expected) in expected.iter().enumerate() {
+        for (j, predicted) in predicted.iter().enumerate() {
+            if expected
+                .label
+                .trim()
+                .eq_ignore_ascii_case(predicted.label.trim())
+            {
+                pairs.push((box_iou(&expected.bbox, &predicted.bbox), i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut expected_used = vec![false; expected.len()];
+    let mut predicted_used = vec![false; predicted.len()];
+    let mut iou_sum = 0.0;
+    let mut matched = 0;
+    for (iou, i, j) in pairs {
+        if expected_used[i] || predicted_used[j] || iou <= 0.0 {
+            continue;
+        }
+        expected_used[i] = true;
+        predicted_used[j] = true;
+        iou_sum += iou as f64;
+        if iou >= MATCH_IOU {
+            matched += 1;
+        }
+    }
+
+    BoxMetrics {
+        iou: (!expected.is_empty()).then(|| iou_sum / expected.len() as f64),
+        matched,
+        predicted: predicted.len(),
+        expected: expected.len(),
+    }
+}
+
+fn aggregate(samples: &[EvalSampleResult]) -> EvalMetrics {
+    let mean = |values: Vec<f64>| {
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    };
+
+    let boxes = samples
+        .iter()
+        .filter_map(|sample| sample.boxes.as_ref())
+        .collect::<Vec<_>>();
+    let matched = boxes.iter().map(|b| b.matched).sum::<usize>() as f64;
+    let predicted = boxes.iter().map(|b| b.predicted).sum::<usize>();
+    let expected = boxes.iter().map(|b| b.expected).sum::<usize>();
+
+    let mut latencies = samples
+        .iter()
+        .filter(|sample| sample.error.is_none())
+        .map(|sample| sample.latency_ms)
+        .collect::<Vec<_>>();
+    latencies.sort_unstable();
+
+    EvalMetrics {
+        exact_match: mean(
+            samples
+                .iter()
+                .filter_map(|s| s.exact_match.map(|exact| if exact { 1.0 } else { 0.0 }))
+                .collect(),
+        ),
+        keyword_match: mean(samples.iter().filter_map(|s| s.keyword_match).collect()),
+        text_overlap: mean(samples.iter().filter_map(|s| s.text_overlap).collect()),
+        box_iou: mean(boxes.iter().filter_map(|b| b.iou).collect()),
+        box_precision: (!boxes.is_empty()).then(|| matched / predicted.max(1) as f64),
+        box_recall: (!boxes.is_empty()).then(|| matched / expected.max(1) as f64),
+        latency_ms: LatencyStats {
+            mean: mean(latencies.iter().map(|&l| l as f64).collect()).unwrap_or_default(),
+            p50: percentile(&latencies, 0.5),
+            p90: percentile(&latencies, 0.9),
+            p99: percentile(&latencies, 0.99),
+            max: latencies.last().copied().unwrap_or_default(),
+        },
+    }
+}
+
+/// The nearest-rank percentile of sorted values, 0 if there are none
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    let rank = ((p * sorted.len() as f64).ceil() as usize).max(1);
+    sorted.get(rank - 1).copied().unwrap_or_default()
+}
+
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected(label: &str, bbox: [f32; 4]) -> ExpectedBox {
+        ExpectedBox {
+            label: label.to_string(),
+            bbox,
+        }
+    }
+
+    fn sample(boxes: BoxMetrics) -> EvalSampleResult {
+        EvalSampleResult {
+            image: "image.jpg".to_string(),
+            prompt: "detect person".to_string(),
+            response: String::new(),
+            latency_ms: 0,
+            error: None,
+            exact_match: None,
+            keyword_match: None,
+            text_overlap: None,
+            boxes: Some(boxes),
+        }
+    }
+
+    #[test]
+    fn text_overlap_of_identical_texts_is_one() {
+        let score = text_overlap("A person walking a dog.", "a person walking a dog");
+        assert!((score - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn text_overlap_penalizes_short_responses() {
+        // all the n-grams match, only the brevity penalty exp(1 - 6 / 2) applies
+        let score = text_overlap("the cat", "the cat sat on the mat");
+        assert!((score - (-2.0f64).exp()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn text_overlap_smooths_the_higher_orders() {
+        // unigrams 2 / 3, bigrams (1 + 1) / (2 + 1), the trigram (0 + 1) / (1 + 1)
+        let score = text_overlap("a red dog", "a red car");
+        let expected = (2.0 * (2.0f64 / 3.0).ln() + (0.5f64).ln()) / 3.0;
+        assert!((score - expected.exp()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn text_overlap_without_common_words_is_zero() {
+        assert_eq!(text_overlap("a dog", "the cat"), 0.0);
+        assert_eq!(text_overlap("", "the cat"), 0.0);
+    }
+
+    #[test]
+    fn keyword_match_counts_the_whole_words() {
+        let keywords = ["red car".to_string(), "person".to_string()];
+        assert_eq!(keyword_match("A Red-Car parked.", &keywords), 0.5);
+        assert_eq!(keyword_match("a persons group", &keywords), 0.0);
+        assert_eq!(keyword_match("a person in a red car", &keywords), 1.0);
+    }
+
+    #[test]
+    fn match_boxes_pairs_the_same_labels() {
+        let response = "<loc0000><loc0000><loc0512><loc0512> person ; \
+                        <loc0000><loc0000><loc1024><loc1024> car";
+        let metrics = match_boxes(
+            response,
+            &[
+                expected("person", [0.0, 0.0, 0.5, 0.5]),
+                expected("dog", [0.0, 0.0, 1.0, 1.0]),
+            ],
+        );
+
+        assert_eq!(metrics.predicted, 2);
+        assert_eq!(metrics.expected, 2);
+        assert_eq!(metrics.matched, 1);
+        // the dog is not matched with the car
+        assert_eq!(metrics.iou, Some(0.5));
+    }
+
+    #[test]
+    fn match_boxes_below_the_iou_threshold_is_not_matched() {
+        let metrics = match_boxes(
+            "<loc0000><loc0000><loc0512><loc0512> person",
+            &[expected("person", [0.0, 0.0, 1.0, 1.0])],
+        );
+        assert_eq!(metrics.matched, 0);
+        assert_eq!(metrics.iou, Some(0.25));
+    }
+
+    #[test]
+    fn match_boxes_without_expected_boxes_counts_the_false_positives() {
+        let metrics = match_boxes("<loc0000><loc0000><loc0512><loc0512> person", &[]);
+        assert_eq!(metrics.predicted, 1);
+        assert_eq!(metrics.matched, 0);
+        assert_eq!(metrics.iou, None);
+    }
+
+    #[test]
+    fn aggregate_scores_the_false_positives_of_empty_samples() {
+        let metrics = aggregate(&[
+            sample(BoxMetrics {
+                iou: Some(1.0),
+                matched: 1,
+                predicted: 1,
+                expected: 1,
+            }),
+            sample(BoxMetrics {
+                iou: None,
+                matched: 0,
+                predicted: 1,
+                expected: 0,
+            }),
+        ]);
+
+        assert_eq!(metrics.box_iou, Some(1.0));
+        assert_eq!(metrics.box_precision, Some(0.5));
+        assert_eq!(metrics.box_recall, Some(1.0));
+    }
+
+    #[test]
+    fn percentile_uses_the_nearest_rank() {
+        let latencies = (1..=10).map(|i| i * 10).collect::<Vec<u64>>();
+        assert_eq!(percentile(&latencies, 0.5), 50);
+        assert_eq!(percentile(&latencies, 0.9), 90);
+        assert_eq!(percentile(&latencies, 0.99), 100);
+        assert_eq!(percentile(&[7], 0.5), 7);
+        assert_eq!(percentile(&[], 0.5), 0);
+    }
+}
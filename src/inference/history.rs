@@ -0,0 +1,331 @@
+use crate::api::models::inference::{
+    InferenceHistoryEntry, InferenceHistoryQuery, InferenceResult,
+};
+use rusqlite::{params, Connection};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// The number of results returned by a history query if no limit is given
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// A persistent store of the inference results backed by a SQLite database
+#[derive(Clone, Default)]
+pub struct InferenceHistory {
+    // NOTE: the store is disabled until a database is opened
+    conn: Arc<Mutex<Option<Connection>>>,
+}
+
+impl InferenceHistory {
+    /// Open or create the database at the given path and use it to store the results
+    pub fn open(&self, path: &Path) -> rusqlite::Result<()> {
+        self.set_connection(Connection::open(path)?)
+    }
+
+    /// Create or migrate the results table of the database and use it to store the results
+    fn set_connection(&self, conn: Connection) -> rusqlite::Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS inference_results (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                wall_time_ns INTEGER NOT NULL,
+                stamp_ns INTEGER NOT NULL,
+                channel_id INTEGER NOT NULL,
+                prompt TEXT NOT NULL,
+                response TEXT NOT NULL,
+                params TEXT NOT NULL,
+                latency_ms INTEGER NOT NULL,
+                model TEXT NOT NULL DEFAULT ''
+            );
+            CREATE INDEX IF NOT EXISTS inference_results_time
+                ON inference_results (channel_id, wall_time_ns);",
+        )?;
+
+        // the databases created before the results recorded their model
+        let has_model = conn
+            .prepare("SELECT 1 FROM pragma_table_info('inference_results') WHERE name = 'model'")?
+            .exists([])?;
+        if !has_model {
+            conn.execute(
+                "ALTER TABLE inference_results ADD COLUMN model TEXT NOT NULL DEFAULT ''",
+                [],
+            )?;
+        }
+
+        *self.conn.lock().unwrap() = Some(conn);
+        Ok(())
+    }
+
+    /// Store a new result with the current wall time, does nothing if no database is open
+    pub fn insert(&self, result: &InferenceResult) -> rusqlite::Result<()> {
+        let wall_time_ns = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        self.insert_at(result, wall_time_ns)
+    }
+
+    fn insert_at(&self, result: &InferenceResult, wall_time_ns: u64) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_ref() else {
+            return Ok(());
+        };
+
+        conn.execute(
+            "INSERT INTO inference_results
+                (wall_time_ns, stamp_ns, channel_id, prompt, response, params, latency_ms, model)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                wall_time_ns as i64,
+                result.stamp_ns as i64,
+                result.channel_id,
+                result.prompt,
+                result.response,
+                serde_json::to_string(&result.params).unwrap_or_default(),
+                result.latency_ms as i64,
+                result.model,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Search the stored results, the most recent first
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The channel, the wall time range and the text to search for
+    ///
+    /// # Returns
+    ///
+    /// The matching results, an empty list if no database is open
+    pub fn query(
+        &self,
+        query: &InferenceHistoryQuery,
+    ) -> rusqlite::Result<Vec<InferenceHistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_ref() else {
+            return Ok(Vec::new());
+        };
+
+        // NOTE: the filters not provided are bound as NULL and ignored
+        let mut stmt = conn.prepare(
+            "SELECT id, wall_time_ns, stamp_ns, channel_id, prompt, response, params, latency_ms,
+                    model
+                FROM inference_results
+                WHERE (?1 IS NULL OR channel_id = ?1)
+                    AND (?2 IS NULL OR wall_time_ns >= ?2)
+                    AND (?3 IS NULL OR wall_time_ns <= ?3)
+                    AND (?4 IS NULL
+                        OR instr(lower(response), lower(?4)) > 0
+                        OR instr(lower(prompt), lower(?4)) > 0)
+                ORDER BY wall_time_ns DESC
+                LIMIT ?5",
+        )?;
+
+        let rows = stmt.query_map(
+            params![
+                query.channel,
+                query.from.map(|from| from as i64),
+                query.to.map(|to| to as i64),
+                query.q,
+                query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT) as i64,
+            ],
+            |row| {
+                let params: String = row.get(6)?;
+                Ok(InferenceHistoryEntry {
+                    id: row.get(0)?,
+                    wall_time_ns: row.get::<_, i64>(1)? as u64,
+                    result: InferenceResult {
+                        stamp_ns: row.get::<_, i64>(2)? as u64,
+                        channel_id: row.get(3)?,
+                        prompt: row.get(4)?,
+                        response: row.get(5)?,
+                        params: serde_json::from_str(&params).unwrap_or_default(),
+                        latency_ms: row.get::<_, i64>(7)? as u64,
+                        model: row.get(8)?,
+                    },
+                })
+            },
+        )?;
+
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND_NS: u64 = 1_000_000_000;
+
+    fn result(channel_id: u8, prompt: &str, response: &str) -> InferenceResult {
+        InferenceResult {
+            stamp_ns: 0,
+            channel_id,
+            prompt: prompt.to_string(),
+            response: response.to_string(),
+            params: Default::default(),
+            latency_ms: 10,
+            model: "mock".to_string(),
+        }
+    }
+
+    /// A history of four results, one per second
+    fn history() -> InferenceHistory {
+        let history = InferenceHistory::default();
+        history
+            .set_connection(Connection::open_in_memory().unwrap())
+            .unwrap();
+        for (second, result) in [
+            result(
+                0,
+                "caption en",
+                "A delivery truck parked in front of the house.",
+            ),
+            result(1, "caption en", "An empty street."),
+            result(
+                0,
+                "detect person",
+                "<loc0000><loc0000><loc0512><loc0512> person",
+            ),
+            result(1, "caption en", "A DELIVERY van on the street."),
+        ]
+        .iter()
+        .enumerate()
+        {
+            history
+                .insert_at(result, (second as u64 + 1) * SECOND_NS)
+                .unwrap();
+        }
+        history
+    }
+
+    fn responses(history: &InferenceHistory, query: InferenceHistoryQuery) -> Vec<String> {
+        history
+            .query(&query)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.result.response)
+            .collect()
+    }
+
+    #[test]
+    fn query_returns_the_most_recent_first() {
+        let entries = history().query(&Default::default()).unwrap();
+        let times = entries
+            .iter()
+            .map(|entry| entry.wall_time_ns / SECOND_NS)
+            .collect::<Vec<_>>();
+        assert_eq!(times, [4, 3, 2, 1]);
+        assert_eq!(entries[0].result.channel_id, 1);
+        assert_eq!(entries[0].result.model, "mock");
+        assert_eq!(entries[0].result.latency_ms, 10);
+    }
+
+    #[test]
+    fn query_filters_by_channel() {
+        let query = InferenceHistoryQuery {
+            channel: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            responses(&history(), query),
+            ["A DELIVERY van on the street.", "An empty street."]
+        );
+    }
+
+    #[test]
+    fn query_filters_by_time_range() {
+        let query = InferenceHistoryQuery {
+            from: Some(2 * SECOND_NS),
+            to: Some(3 * SECOND_NS),
+            ..Default::default()
+        };
+        assert_eq!(
+            responses(&history(), query),
+            [
+                "<loc0000><loc0000><loc0512><loc0512> person",
+                "An empty street."
+            ]
+        );
+    }
+
+    #[test]
+    fn query_searches_the_prompt_and_the_response() {
+        let query = InferenceHistoryQuery {
+            q: Some("delivery".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            responses(&history(), query),
+            [
+                "A DELIVERY van on the street.",
+                "A delivery truck parked in front of the house."
+            ]
+        );
+
+        let query = InferenceHistoryQuery {
+            q: Some("detect".to_string()),
+            channel: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(responses(&history(), query).len(), 1);
+    }
+
+    #[test]
+    fn query_limits_the_results() {
+        let query = InferenceHistoryQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            responses(&history(), query),
+            [
+                "A DELIVERY van on the street.",
+                "<loc0000><loc0000><loc0512><loc0512> person"
+            ]
+        );
+    }
+
+    #[test]
+    fn query_without_database_is_empty() {
+        let history = InferenceHistory::default();
+        history.insert(&result(0, "caption en", "A cat.")).unwrap();
+        assert!(history.query(&Default::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn open_migrates_the_tables_without_model() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE inference_results (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                wall_time_ns INTEGER NOT NULL,
+                stamp_ns INTEGER NOT NULL,
+                channel_id INTEGER NOT NULL,
+                prompt TEXT NOT NULL,
+                response TEXT NOT NULL,
+                params TEXT NOT NULL,
+                latency_ms INTEGER NOT NULL
+            );
+            INSERT INTO inference_results
+                (wall_time_ns, stamp_ns, channel_id, prompt, response, params, latency_ms)
+                VALUES (1, 2, 0, 'caption en', 'An old result.', '{}', 5);",
+        )
+        .unwrap();
+
+        let history = InferenceHistory::default();
+        history.set_connection(conn).unwrap();
+        history
+            .insert_at(&result(0, "caption en", "A new result."), 2)
+            .unwrap();
+
+        let entries = history.query(&Default::default()).unwrap();
+        let models = entries
+            .iter()
+            .map(|entry| (entry.result.response.as_str(), entry.result.model.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(models, [("A new result.", "mock"), ("An old result.", "")]);
+    }
+}
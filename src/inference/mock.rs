@@ -0,0 +1,217 @@
+use crate::{
+    cu29::msgs::ImageRgb8,
+    inference::{GenerationParams, InferenceBackend, InferenceError, InferenceOutput},
+};
+use serde::{Deserialize, Serialize};
+
+/// The behaviour of the mock backend
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MockMode {
+    /// return the prompt as response
+    #[default]
+    Echo,
+    /// cycle through the responses of a file, one per line
+    Canned,
+    /// return cheap statistics of the image
+    Stats,
+}
+
+impl std::str::FromStr for MockMode {
+    type Err = InferenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "echo" => Ok(Self::Echo),
+            "canned" => Ok(Self::Canned),
+            "stats" => Ok(Self::Stats),
+            _ => Err(InferenceError::Config(format!(
+                "Mock mode {} not supported. Try 'echo', 'canned' or 'stats' instead",
+                s
+            ))),
+        }
+    }
+}
+
+/// The configuration of the mock backend
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MockConfig {
+    /// the behaviour of the backend
+    pub mode: MockMode,
+    /// the file with the canned responses, required by the `canned` mode
+    pub responses_path: Option<String>,
+    /// an artificial latency added to every inference
+    pub latency_ms: u64,
+}
+
+/// Inference backend that does not load any model, meant for offline testing
+pub struct MockBackend {
+    mode: MockMode,
+    responses: Vec<String>,
+    latency: std::time::Duration,
+    counter: usize,
+}
+
+impl MockBackend {
+    pub fn new(config: &MockConfig) -> Result<Self, InferenceError> {
+        let responses = match config.mode {
+            MockMode::Canned => {
+                let path = config.responses_path.as_ref().ok_or_else(|| {
+                    InferenceError::Config("The canned mode requires a responses file".to_string())
+                })?;
+                let responses = std::fs::read_to_string(path)
+                    .map_err(|e| InferenceError::Config(format!("{}: {}", path, e)))?
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| line.to_string())
+                    .collect::<Vec<_>>();
+                if responses.is_empty() {
+                    return Err(InferenceError::Config(format!(
+                        "The responses file {} is empty",
+                        path
+                    )));
+                }
+                responses
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            mode: config.mode,
+            responses,
+            latency: std::time::Duration::from_millis(config.latency_ms),
+            counter: 0,
+        })
+    }
+}
+
+impl InferenceBackend for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn infer(
+        &mut self,
+        image: &ImageRgb8,
+        prompt: &str,
+        _params: &GenerationParams,
+    ) -> Result<InferenceOutput, InferenceError> {
+        // simulate the time spent by a real model
+        std::thread::sleep(self.latency);
+
+        let response = match self.mode {
+            MockMode::Echo => prompt.to_string(),
+            MockMode::Canned => {
+                let response = self.responses[self.counter % self.responses.len()].clone();
+                self.counter += 1;
+                response
+            }
+            MockMode::Stats => image_stats(image),
+        };
+
+        Ok(InferenceOutput { response })
+    }
+}
+
+/// Compute the size, the mean color and the brightness of the image
+fn image_stats(image: &ImageRgb8) -> String {
+    let mut sum = [0u64; 3];
+    for pixel in image.as_slice().chunks_exact(3) {
+        sum[0] += pixel[0] as u64;
+        sum[1] += pixel[1] as u64;
+        sum[2] += pixel[2] as u64;
+    }
+
+    let num_pixels = (image.width() * image.height()).max(1) as f64;
+    let mean = sum.map(|s| s as f64 / num_pixels);
+    let brightness = (0.299 * mean[0] + 0.587 * mean[1] + 0.114 * mean[2]) / 255.0;
+
+    format!(
+        "image {}x{} mean rgb ({:.1}, {:.1}, {:.1}) brightness {:.2}",
+        image.width(),
+        image.height(),
+        mean[0],
+        mean[1],
+        mean[2],
+        brightness
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(color: [u8; 3]) -> ImageRgb8 {
+        ImageRgb8::new([4, 2].into(), color.repeat(4 * 2)).unwrap()
+    }
+
+    fn infer(backend: &mut MockBackend, prompt: &str) -> String {
+        backend
+            .infer(&image([0, 0, 0]), prompt, &GenerationParams::default())
+            .unwrap()
+            .response
+    }
+
+    #[test]
+    fn echo_returns_the_prompt() {
+        let mut backend = MockBackend::new(&MockConfig::default()).unwrap();
+        assert_eq!(infer(&mut backend, "caption en"), "caption en");
+    }
+
+    #[test]
+    fn canned_cycles_through_the_responses() {
+        let path = std::env::temp_dir().join(format!(
+            "bubbaloop_mock_responses_{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&path, "a cat\n\na dog\n").unwrap();
+
+        let mut backend = MockBackend::new(&MockConfig {
+            mode: MockMode::Canned,
+            responses_path: Some(path.display().to_string()),
+            latency_ms: 0,
+        })
+        .unwrap();
+        let responses = (0..3)
+            .map(|_| infer(&mut backend, "caption en"))
+            .collect::<Vec<_>>();
+        assert_eq!(responses, ["a cat", "a dog", "a cat"]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn canned_requires_a_responses_file() {
+        let config = MockConfig {
+            mode: MockMode::Canned,
+            ..Default::default()
+        };
+        assert!(matches!(
+            MockBackend::new(&config),
+            Err(InferenceError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn stats_describes_the_image() {
+        let mut backend = MockBackend::new(&MockConfig {
+            mode: MockMode::Stats,
+            ..Default::default()
+        })
+        .unwrap();
+        let response = backend
+            .infer(&image([255, 0, 0]), "", &GenerationParams::default())
+            .unwrap()
+            .response;
+        assert_eq!(
+            response,
+            "image 4x2 mean rgb (255.0, 0.0, 0.0) brightness 0.30"
+        );
+    }
+
+    #[test]
+    fn mode_from_str() {
+        assert!(matches!("stats".parse::<MockMode>(), Ok(MockMode::Stats)));
+        assert!("random".parse::<MockMode>().is_err());
+    }
+}
@@ -0,0 +1,42 @@
+mod backend;
+pub use backend::*;
+
+mod batch;
+pub use batch::*;
+
+mod cache;
+pub use cache::*;
+
+#[cfg(feature = "clip")]
+mod clip;
+#[cfg(feature = "clip")]
+pub use clip::*;
+
+mod detection;
+pub use detection::*;
+
+#[cfg(feature = "onnx")]
+mod detector;
+#[cfg(feature = "onnx")]
+pub use detector::*;
+
+mod embedding;
+pub use embedding::*;
+
+mod embedding_index;
+pub use embedding_index::*;
+
+mod eval;
+pub use eval::*;
+
+mod history;
+pub use history::*;
+
+mod mock;
+pub use mock::*;
+
+mod paligemma;
+pub use paligemma::*;
+
+mod scheduling;
+pub use scheduling::*;
@@ -0,0 +1,117 @@
+use crate::{
+    cu29::msgs::ImageRgb8,
+    inference::{
+        cached_snapshot, default_cache_root, resolve_model_dir, GenerationParams, InferenceBackend,
+        InferenceError, InferenceOutput, ModelConfig, DEFAULT_PALIGEMMA_REPO,
+    },
+};
+use kornia_paligemma::{Paligemma, PaligemmaConfig};
+
+/// The sampling parameters the sampler of the model is created with
+type Sampling = (u64, Option<f64>, Option<f64>);
+
+/// Inference backend running Google PaliGemma via kornia-paligemma
+// NOTE: check the original prompt instructions
+// https://ai.google.dev/gemma/docs/paligemma/prompt-system-instructions
+pub struct PaligemmaBackend {
+    // none while the model reloads or after a failed reload
+    model: Option<Paligemma>,
+    // the sampling parameters the model was loaded with
+    sampling: Sampling,
+}
+
+impl PaligemmaBackend {
+    pub fn new(params: &GenerationParams, model: &ModelConfig) -> Result<Self, InferenceError> {
+        check_model(model)?;
+
+        let sampling = (params.seed, params.temperature, params.top_p);
+        Ok(Self {
+            model: Some(load_model(sampling)?),
+            sampling,
+        })
+    }
+}
+
+impl InferenceBackend for PaligemmaBackend {
+    fn name(&self) -> &str {
+        "paligemma"
+    }
+
+    fn infer(
+        &mut self,
+        image: &ImageRgb8,
+        prompt: &str,
+        params: &GenerationParams,
+    ) -> Result<InferenceOutput, InferenceError> {
+        // NOTE: the sampler is created with the model, so we need to reload it to apply changes.
+        // The sampling parameters are ignored by the greedy decoding, so only reload to sample.
+        // The inference waits for the reload so that it runs with the parameters it echoes,
+        // and the current model is released first to not keep two models in memory.
+        let sampling = (params.seed, params.temperature, params.top_p);
+        if (params.sample && sampling != self.sampling) || self.model.is_none() {
+            log::debug!(
+                "Reloading Paligemma with sampling parameters: {:?}",
+                sampling
+            );
+            self.model = None;
+            self.model = Some(load_model(sampling)?);
+            self.sampling = sampling;
+        }
+
+        let model = self
+            .model
+            .as_mut()
+            .ok_or_else(|| InferenceError::Backend("Paligemma is not loaded".to_string()))?;
+        let response = model
+            .inference(image, prompt, params.max_new_tokens, params.sample)
+            .map_err(|e| InferenceError::Backend(e.to_string()))?;
+        Ok(InferenceOutput { response })
+    }
+}
+
+/// Validate the files of the model and check that kornia-paligemma loads them
+///
+/// kornia-paligemma always loads the default repository from the Hugging Face cache of
+/// the process, `HF_HOME` as set when the server started. The other repositories and
+/// the files outside of that cache are rejected instead of silently loading different
+/// weights.
+fn check_model(model: &ModelConfig) -> Result<(), InferenceError> {
+    if model.repo != DEFAULT_PALIGEMMA_REPO {
+        return Err(InferenceError::Config(format!(
+            "kornia-paligemma only loads {}, got {}",
+            DEFAULT_PALIGEMMA_REPO, model.repo
+        )));
+    }
+
+    // NOTE: the hub source resolves to the cache of the process or is downloaded there
+    let Some(model_dir) = resolve_model_dir(model)? else {
+        return Ok(());
+    };
+
+    let process_root = default_cache_root();
+    let loaded_dir = cached_snapshot(&process_root, &model.repo)
+        .ok()
+        .and_then(|(_, snapshot)| std::fs::canonicalize(snapshot).ok());
+    if std::fs::canonicalize(&model_dir).ok() != loaded_dir || loaded_dir.is_none() {
+        return Err(InferenceError::Config(format!(
+            "kornia-paligemma reads the weights from the Hugging Face cache {}, \
+             not from {}: start the server with HF_HOME set to the cache root \
+             holding the model, e.g. a copy of ~/.cache/huggingface, and use model_cache",
+            process_root.display(),
+            model_dir.display()
+        )));
+    }
+
+    log::debug!("Loading {} from {}", model.repo, model_dir.display());
+    Ok(())
+}
+
+fn load_model((seed, temp, top_p): Sampling) -> Result<Paligemma, InferenceError> {
+    Paligemma::new(PaligemmaConfig {
+        seed,
+        temp,
+        top_p,
+        ..Default::default()
+    })
+    .map_err(|e| InferenceError::Backend(e.to_string()))
+}
@@ -0,0 +1,393 @@
+use crate::{api::models::inference::ChannelStats, inference::InferenceError};
+use cu29::prelude::ComponentConfig;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// How the next channel to run inference on is selected
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedulingPolicy {
+    /// every channel gets a turn in order
+    #[default]
+    RoundRobin,
+    /// the channels get turns proportionally to their weight
+    Weighted,
+}
+
+/// Which frames are kept while the model is busy
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueMode {
+    /// keep only the latest frame of each channel
+    #[default]
+    Latest,
+    /// keep the frames in a bounded first-in first-out queue per channel
+    Fifo { capacity: usize },
+}
+
+/// The configuration of the multi-channel scheduling
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SchedulingConfig {
+    /// how the next channel is selected
+    pub policy: SchedulingPolicy,
+    /// which frames are kept while the model is busy
+    pub queue: QueueMode,
+    /// the minimum interval between two inferences of the same channel
+    pub min_interval_ms: u64,
+    /// the minimum interval per channel, overriding the default one
+    pub channel_min_interval_ms: BTreeMap<u8, u64>,
+    /// the weight per channel used by the weighted policy, 1 by default
+    pub channel_weights: BTreeMap<u8, u32>,
+}
+
+impl SchedulingConfig {
+    /// Parse the scheduling configuration from the task config
+    ///
+    /// The per channel settings are given as `min_interval_ms_{channel_id}` and
+    /// `weight_{channel_id}`.
+    pub fn from_component_config(config: Option<&ComponentConfig>) -> Result<Self, InferenceError> {
+        let mut scheduling = Self::default();
+        let Some(config) = config else {
+            return Ok(scheduling);
+        };
+
+        if let Some(policy) = config.get::<String>("scheduling") {
+            scheduling.policy = match policy.as_str() {
+                "round_robin" => SchedulingPolicy::RoundRobin,
+                "weighted" => SchedulingPolicy::Weighted,
+                _ => {
+                    return Err(InferenceError::Config(format!(
+                        "Scheduling {} not supported. Try 'round_robin' or 'weighted' instead",
+                        policy
+                    )))
+                }
+            };
+        }
+
+        if let Some(queue) = config.get::<String>("queue") {
+            scheduling.queue = match queue.as_str() {
+                "latest" => QueueMode::Latest,
+                "fifo" => QueueMode::Fifo {
+                    capacity: config.get::<u32>("queue_capacity").unwrap_or(4) as usize,
+                },
+                _ => {
+                    return Err(InferenceError::Config(format!(
+                        "Queue {} not supported. Try 'latest' or 'fifo' instead",
+                        queue
+                    )))
+                }
+            };
+        }
+
+        if let Some(min_interval_ms) = config.get::<u32>("min_interval_ms") {
+            scheduling.min_interval_ms = min_interval_ms as u64;
+        }
+
+        for channel_id in 0..=u8::MAX {
+            if let Some(interval) = config.get::<u32>(&format!("min_interval_ms_{}", channel_id)) {
+                scheduling
+                    .channel_min_interval_ms
+                    .insert(channel_id, interval as u64);
+            }
+            if let Some(weight) = config.get::<u32>(&format!("weight_{}", channel_id)) {
+                scheduling.channel_weights.insert(channel_id, weight);
+            }
+        }
+
+        Ok(scheduling)
+    }
+}
+
+/// The queue and the bookkeeping of a single channel
+struct ChannelState<T> {
+    queue: VecDeque<T>,
+    last_scheduled: Option<Instant>,
+    // the running credit of the smooth weighted round robin
+    credit: i64,
+    stats: ChannelStats,
+}
+
+/// Selects which queued frame of which channel runs next on a single inference worker
+pub struct ChannelScheduler<T> {
+    config: SchedulingConfig,
+    channels: BTreeMap<u8, ChannelState<T>>,
+    // the last channel selected by the round robin policy
+    cursor: Option<u8>,
+}
+
+impl<T> ChannelScheduler<T> {
+    pub fn new(config: SchedulingConfig) -> Self {
+        Self {
+            config,
+            channels: BTreeMap::new(),
+            cursor: None,
+        }
+    }
+
+    /// Queue a new item of a channel, dropping the older ones if needed
+    pub fn push(&mut self, channel_id: u8, item: T) {
+        let channel = self.channel(channel_id);
+
+        let capacity = match self.config.queue {
+            QueueMode::Latest => 1,
+            QueueMode::Fifo { capacity } => capacity.max(1),
+        };
+
+        channel.queue.push_back(item);
+        while channel.queue.len() > capacity {
+            channel.queue.pop_front();
+            channel.stats.dropped += 1;
+        }
+        channel.stats.queued = channel.queue.len();
+    }
+
+    /// Queue the item of a channel only if it can be scheduled, creating it lazily
+    ///
+    /// The item is dropped without being created if the channel is not due yet, or if the
+    /// worker is busy and only the latest frame is kept, since a newer frame would replace
+    /// it before it is picked.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the item was queued
+    pub fn offer(
+        &mut self,
+        channel_id: u8,
+        now: Instant,
+        is_busy: bool,
+        item: impl FnOnce() -> T,
+    ) -> bool {
+        let keep = self.is_due(channel_id, now)
+            && !(is_busy && matches!(self.config.queue, QueueMode::Latest));
+        if !keep {
+            self.channel(channel_id).stats.dropped += 1;
+            return false;
+        }
+        self.push(channel_id, item());
+        true
+    }
+
+    /// Pop the next item to process according to the scheduling policy
+    pub fn next(&mut self, now: Instant) -> Option<(u8, T)> {
+        let ready = self
+            .channels
+            .iter()
+            .filter(|(channel_id, channel)| {
+                !channel.queue.is_empty() && self.is_due(**channel_id, now)
+            })
+            .map(|(channel_id, _)| *channel_id)
+            .collect::<Vec<_>>();
+
+        let channel_id = match self.config.policy {
+            SchedulingPolicy::RoundRobin => {
+                // the first ready channel after the last selected one
+                let cursor = self.cursor;
+                ready
+                    .iter()
+                    .find(|&&id| cursor.is_none_or(|cursor| id > cursor))
+                    .or_else(|| ready.first())
+                    .copied()?
+            }
+            SchedulingPolicy::Weighted => {
+                // smooth weighted round robin over the ready channels
+                let mut total = 0;
+                for &id in &ready {
+                    let weight = self.weight(id);
+                    total += weight;
+                    if let Some(channel) = self.channels.get_mut(&id) {
+                        channel.credit += weight;
+                    }
+                }
+                let id = ready
+                    .iter()
+                    .copied()
+                    .max_by_key(|id| self.channels[id].credit)?;
+                if let Some(channel) = self.channels.get_mut(&id) {
+                    channel.credit -= total;
+                }
+                id
+            }
+        };
+
+        self.cursor = Some(channel_id);
+
+        let channel = self.channels.get_mut(&channel_id)?;
+        let item = channel.queue.pop_front()?;
+        channel.last_scheduled = Some(now);
+        channel.stats.processed += 1;
+        channel.stats.queued = channel.queue.len();
+
+        Some((channel_id, item))
+    }
+
+    /// The processed, dropped and queued counts per channel
+    pub fn stats(&self) -> BTreeMap<u8, ChannelStats> {
+        self.channels
+            .iter()
+            .map(|(channel_id, channel)| (*channel_id, channel.stats.clone()))
+            .collect()
+    }
+
+    fn channel(&mut self, channel_id: u8) -> &mut ChannelState<T> {
+        self.channels
+            .entry(channel_id)
+            .or_insert_with(|| ChannelState {
+                queue: VecDeque::new(),
+                last_scheduled: None,
+                credit: 0,
+                stats: ChannelStats::default(),
+            })
+    }
+
+    /// Check if the minimum interval of a channel elapsed since its last inference
+    fn is_due(&self, channel_id: u8, now: Instant) -> bool {
+        self.channels
+            .get(&channel_id)
+            .and_then(|channel| channel.last_scheduled)
+            .is_none_or(|last| now.duration_since(last) >= self.min_interval(channel_id))
+    }
+
+    fn min_interval(&self, channel_id: u8) -> Duration {
+        Duration::from_millis(
+            self.config
+                .channel_min_interval_ms
+                .get(&channel_id)
+                .copied()
+                .unwrap_or(self.config.min_interval_ms),
+        )
+    }
+
+    fn weight(&self, channel_id: u8) -> i64 {
+        self.config
+            .channel_weights
+            .get(&channel_id)
+            .copied()
+            .unwrap_or(1) as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(config: SchedulingConfig) -> ChannelScheduler<u32> {
+        ChannelScheduler::new(config)
+    }
+
+    #[test]
+    fn latest_keeps_only_the_newest_frame() {
+        let mut queue = scheduler(SchedulingConfig::default());
+        queue.push(0, 1);
+        queue.push(0, 2);
+        queue.push(0, 3);
+
+        assert_eq!(queue.next(Instant::now()), Some((0, 3)));
+        assert_eq!(queue.next(Instant::now()), None);
+        assert_eq!(queue.stats()[&0].dropped, 2);
+        assert_eq!(queue.stats()[&0].processed, 1);
+    }
+
+    #[test]
+    fn fifo_drops_the_oldest_frames_over_capacity() {
+        let mut queue = scheduler(SchedulingConfig {
+            queue: QueueMode::Fifo { capacity: 2 },
+            ..Default::default()
+        });
+        for frame in 1..=4 {
+            queue.push(0, frame);
+        }
+
+        let now = Instant::now();
+        assert_eq!(queue.next(now), Some((0, 3)));
+        assert_eq!(queue.next(now), Some((0, 4)));
+        assert_eq!(queue.stats()[&0].dropped, 2);
+    }
+
+    #[test]
+    fn offer_drops_without_creating_the_frame_when_busy() {
+        let mut queue = scheduler(SchedulingConfig::default());
+
+        let queued = queue.offer(0, Instant::now(), true, || panic!("the frame was created"));
+        assert!(!queued);
+        assert_eq!(queue.stats()[&0].dropped, 1);
+        assert_eq!(queue.next(Instant::now()), None);
+
+        assert!(queue.offer(0, Instant::now(), false, || 1));
+        assert_eq!(queue.next(Instant::now()), Some((0, 1)));
+    }
+
+    #[test]
+    fn offer_keeps_the_fifo_frames_while_busy() {
+        let mut queue = scheduler(SchedulingConfig {
+            queue: QueueMode::Fifo { capacity: 4 },
+            ..Default::default()
+        });
+
+        let now = Instant::now();
+        assert!(queue.offer(0, now, true, || 1));
+        assert!(queue.offer(0, now, true, || 2));
+        assert_eq!(queue.stats()[&0].queued, 2);
+    }
+
+    #[test]
+    fn round_robin_alternates_the_channels() {
+        let mut queue = scheduler(SchedulingConfig::default());
+        let now = Instant::now();
+
+        let mut order = Vec::new();
+        for _ in 0..4 {
+            queue.push(0, 0);
+            queue.push(1, 1);
+            queue.push(2, 2);
+            order.push(queue.next(now).map(|(id, _)| id));
+        }
+        assert_eq!(order, vec![Some(0), Some(1), Some(2), Some(0)]);
+    }
+
+    #[test]
+    fn weighted_follows_the_channel_weights() {
+        let mut queue = scheduler(SchedulingConfig {
+            policy: SchedulingPolicy::Weighted,
+            channel_weights: BTreeMap::from([(0, 3), (1, 1)]),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        let mut counts = [0; 2];
+        for _ in 0..8 {
+            queue.push(0, 0);
+            queue.push(1, 1);
+            if let Some((id, _)) = queue.next(now) {
+                counts[id as usize] += 1;
+            }
+        }
+        assert_eq!(counts, [6, 2]);
+    }
+
+    #[test]
+    fn min_interval_throttles_the_channel() {
+        let mut queue = scheduler(SchedulingConfig {
+            min_interval_ms: 100,
+            channel_min_interval_ms: BTreeMap::from([(1, 0)]),
+            ..Default::default()
+        });
+        let start = Instant::now();
+
+        queue.push(0, 1);
+        assert_eq!(queue.next(start), Some((0, 1)));
+
+        // the channel is not due yet, the frame waits and new ones are not created
+        queue.push(0, 2);
+        assert_eq!(queue.next(start + Duration::from_millis(50)), None);
+        assert!(!queue.offer(0, start + Duration::from_millis(50), false, || 3));
+
+        // the channel overriding the interval is not throttled
+        queue.push(1, 10);
+        assert_eq!(queue.next(start + Duration::from_millis(50)), Some((1, 10)));
+
+        assert_eq!(queue.next(start + Duration::from_millis(100)), Some((0, 2)));
+    }
+}
@@ -1,3 +1,10 @@
+pub mod alerts;
+pub mod analytics;
 pub mod api;
 pub mod cu29;
+pub mod draw;
+pub mod inference;
 pub mod pipeline;
+pub mod recording;
+pub mod tracking;
+pub mod webhooks;
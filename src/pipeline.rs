@@ -1,12 +1,28 @@
 use crate::{
-    api::models::{inference::InferenceResult, recording::RecordingCommand},
-    cu29::msgs::EncodedImage,
+    alerts::AlertStore,
+    analytics::AnalyticsStore,
+    api::models::{
+        inference::{
+            ChannelStats, InferenceModelRequest, InferenceQuery, InferenceResult,
+            InferenceSettings, ModelInfo, ModelSlot,
+        },
+        recording::{
+            RecordingCommand, RecordingSessionConfig, RecordingSessionInfo, RecordingSessionStatus,
+        },
+        search::EmbeddingQuery,
+        webhooks::WebhookEvent,
+    },
+    cu29::msgs::{DetectionsMsg, EncodedImage, TracksMsg},
+    inference::{EmbeddingIndex, InferenceHistory},
+    recording::RecordingResult,
+    webhooks::WebhookStore,
 };
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    sync::atomic::AtomicBool,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, AtomicUsize},
     sync::{Arc, Mutex},
 };
 
@@ -37,12 +53,21 @@ impl<T: Clone> Default for BroadcastSender<T> {
 }
 
 /// A sender and receiver for a single message
-#[derive(Clone)]
 pub struct SenderReceiver<T> {
     pub rx: Arc<Mutex<std::sync::mpsc::Receiver<T>>>,
     pub tx: std::sync::mpsc::Sender<T>,
 }
 
+// NOTE: implemented manually as the messages do not need to be cloned
+impl<T> Clone for SenderReceiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            rx: self.rx.clone(),
+            tx: self.tx.clone(),
+        }
+    }
+}
+
 impl<T> Default for SenderReceiver<T> {
     fn default() -> Self {
         Self::new()
@@ -74,24 +99,132 @@ impl<T> Default for InferenceSenderReceiver<T> {
     }
 }
 
+/// Global store of the recording sessions managed by the server
+#[derive(Clone, Default)]
+pub struct RecordingStore {
+    /// the commands broadcasted to all the recorder tasks
+    pub commands: BroadcastSender<RecordingCommand>,
+    /// the recording sessions indexed by their id
+    pub sessions: Arc<Mutex<HashMap<String, RecordingSessionInfo>>>,
+    /// the directories where the recorder tasks write their recordings
+    pub directories: Arc<Mutex<HashSet<PathBuf>>>,
+    /// the extracted clips indexed by their id
+    pub clips: Arc<Mutex<HashMap<String, PathBuf>>>,
+}
+
+impl RecordingStore {
+    /// Send the start command to the recorder tasks and track the new session
+    pub fn start_session(&self, config: RecordingSessionConfig) -> RecordingResult<()> {
+        const NO_RECORDER: &str = "No recorder running, try `just start-pipeline cameras`";
+
+        // NOTE: tracked before the command is sent so that the recorders find the session
+        let session_id = config.session_id.clone();
+        let pending_recorders = self.commands.tx.receiver_count();
+        if pending_recorders == 0 {
+            return Err(NO_RECORDER.into());
+        }
+        self.sessions.lock().unwrap().insert(
+            session_id.clone(),
+            RecordingSessionInfo {
+                config: config.clone(),
+                status: RecordingSessionStatus::Recording,
+                manifests: Vec::new(),
+                pending_recorders,
+                annotations: Vec::new(),
+            },
+        );
+
+        if self
+            .commands
+            .tx
+            .send(RecordingCommand::Start(config))
+            .is_err()
+        {
+            self.sessions.lock().unwrap().remove(&session_id);
+            return Err(NO_RECORDER.into());
+        }
+
+        Ok(())
+    }
+
+    /// Mark a session as finalized by one of its recorders
+    ///
+    /// The session is stopped once all the recorders that received it are done.
+    pub fn finalize_session(&self, session_id: &str, manifest: Option<String>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(session_id) else {
+            return;
+        };
+        session.manifests.extend(manifest);
+        session.pending_recorders = session.pending_recorders.saturating_sub(1);
+        if !matches!(session.status, RecordingSessionStatus::Failed(_)) {
+            session.status = if session.pending_recorders == 0 {
+                RecordingSessionStatus::Stopped
+            } else {
+                RecordingSessionStatus::Finalizing
+            };
+        }
+    }
+}
+
 /// Global store of all results managed by the server
 #[derive(Clone)]
 pub struct ResultStore {
     // NOTE: support a fixed number of streams
     pub inference: [BroadcastSender<InferenceResult>; 8],
-    pub inference_settings: SenderReceiver<String>,
+    // NOTE: lives in the server so that the settings survive pipeline restarts
+    pub inference_settings: Arc<Mutex<InferenceSettings>>,
+    // the scheduling counters of the inference tasks per channel
+    pub inference_stats: Arc<Mutex<BTreeMap<u8, ChannelStats>>>,
+    // the loading status of the models indexed by their task id and slot
+    pub inference_models: Arc<Mutex<BTreeMap<(String, ModelSlot), ModelInfo>>>,
+    // the requests to swap the model, received by all the inference tasks
+    pub inference_model_swaps: BroadcastSender<InferenceModelRequest>,
+    // the persistent history of the inference results
+    pub inference_history: InferenceHistory,
+    // the on-demand queries, picked by the first inference task that is not busy
+    pub inference_queries: SenderReceiver<InferenceQuery>,
+    // the embeddings of the sampled frames searched by the api
+    pub embedding_index: EmbeddingIndex,
+    // the search queries, embedded by the first embedding task that is not busy
+    pub embedding_queries: SenderReceiver<EmbeddingQuery>,
+    // the number of embedding tasks with a loaded model, to answer the search queries
+    pub embedding_tasks: Arc<AtomicUsize>,
+    // NOTE: support a fixed number of streams
+    pub detections: [BroadcastSender<DetectionsMsg>; 8],
+    // NOTE: support a fixed number of streams
+    pub tracks: [BroadcastSender<TracksMsg>; 8],
     // NOTE: support a fixed number of streams
     pub images: [BroadcastSender<EncodedImage>; 8],
-    pub recording: SenderReceiver<RecordingCommand>,
+    // the latest image of each channel, e.g. to attach to the alerts
+    pub latest_images: Arc<Mutex<HashMap<u8, EncodedImage>>>,
+    pub recording: RecordingStore,
+    pub alerts: AlertStore,
+    pub analytics: AnalyticsStore,
+    pub webhooks: WebhookStore,
 }
 
 impl Default for ResultStore {
     fn default() -> Self {
         Self {
             inference: std::array::from_fn(|_| BroadcastSender::new()),
-            inference_settings: SenderReceiver::new(),
+            inference_settings: Arc::new(Mutex::new(InferenceSettings::default())),
+            inference_stats: Arc::new(Mutex::new(BTreeMap::new())),
+            inference_models: Arc::new(Mutex::new(BTreeMap::new())),
+            inference_model_swaps: BroadcastSender::new(),
+            inference_history: InferenceHistory::default(),
+            inference_queries: SenderReceiver::new(),
+            embedding_index: EmbeddingIndex::default(),
+            embedding_queries: SenderReceiver::new(),
+            embedding_tasks: Arc::new(AtomicUsize::new(0)),
+            detections: std::array::from_fn(|_| BroadcastSender::new()),
+            tracks: std::array::from_fn(|_| BroadcastSender::new()),
             images: std::array::from_fn(|_| BroadcastSender::new()),
-            recording: SenderReceiver::new(),
+            latest_images: Arc::new(Mutex::new(HashMap::new())),
+            recording: RecordingStore::default(),
+            alerts: AlertStore::default(),
+            analytics: AnalyticsStore::default(),
+            webhooks: WebhookStore::default(),
         }
     }
 }
@@ -124,8 +257,9 @@ impl PipelineStore {
 
     /// Unregister a pipeline from the store and stop it
     pub fn unregister_pipeline(&self, name: &str) -> bool {
-        let mut map = self.0.lock().unwrap();
-        map.remove(name)
+        // NOTE: release the store before joining, the exiting thread reports its status to it
+        let pipeline = self.0.lock().unwrap().remove(name);
+        pipeline
             .map(|pipeline| {
                 pipeline
                     .stop_signal
@@ -141,6 +275,38 @@ impl PipelineStore {
     }
 }
 
+/// Report the pipeline threads exiting with an error to the store and the webhooks
+///
+/// The pipelines stopped from the api are reported by the stop handler instead.
+pub fn report_pipeline_exit(pipeline_id: &str, result: PipelineResult) -> PipelineResult {
+    let Err(e) = &result else {
+        return result;
+    };
+
+    log::error!("Pipeline {} exited with an error: {}", pipeline_id, e);
+    let status = PipelineStatus::Error(e.to_string());
+
+    if let Some(pipeline) = SERVER_GLOBAL_STATE
+        .pipeline_store
+        .0
+        .lock()
+        .unwrap()
+        .get_mut(pipeline_id)
+    {
+        pipeline.status = status.clone();
+    }
+
+    SERVER_GLOBAL_STATE
+        .result_store
+        .webhooks
+        .notify(WebhookEvent::PipelineStatus {
+            pipeline_id: pipeline_id.to_string(),
+            status,
+        });
+
+    result
+}
+
 /// The current status of a pipeline
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum PipelineStatus {
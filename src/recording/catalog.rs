@@ -0,0 +1,77 @@
+use crate::{
+    api::models::recording::RecordingManifest,
+    recording::{RecordingResult, TimeRange},
+};
+use std::path::{Path, PathBuf};
+
+/// A finalized recording found on disk together with its manifest
+#[derive(Clone, Debug)]
+pub struct RecordingEntry {
+    /// the path to the `.rrd` file
+    pub path: PathBuf,
+    /// the manifest written next to the recording
+    pub manifest: RecordingManifest,
+}
+
+/// List the finalized recordings in a directory by reading their manifests
+pub fn list_recordings(dir: &Path) -> RecordingResult<Vec<RecordingEntry>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+
+        // skip json files that are not recording manifests
+        let Ok(manifest) = std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                serde_json::from_slice::<RecordingManifest>(&data).map_err(|e| e.to_string())
+            })
+        else {
+            continue;
+        };
+
+        let rrd_path = dir.join(&manifest.recording);
+        if rrd_path.exists() {
+            entries.push(RecordingEntry {
+                path: rrd_path,
+                manifest,
+            });
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.manifest.start_time_ns);
+
+    Ok(entries)
+}
+
+/// Find the recordings of a channel overlapping a wall-clock time range
+pub fn find_recordings(
+    dirs: &[PathBuf],
+    channel_id: u8,
+    range: TimeRange,
+) -> RecordingResult<Vec<RecordingEntry>> {
+    let mut found = Vec::new();
+    for dir in dirs {
+        for entry in list_recordings(dir)? {
+            let manifest = &entry.manifest;
+            if !manifest.channels.contains(&channel_id) {
+                continue;
+            }
+
+            let start_ns = manifest.start_time_ns as i64;
+            let stop_ns = manifest.stop_time_ns.map_or(i64::MAX, |stop| stop as i64);
+            let overlaps = range.end_ns.is_none_or(|end| start_ns <= end)
+                && range.start_ns.is_none_or(|start| stop_ns >= start);
+
+            if overlaps {
+                found.push(entry);
+            }
+        }
+    }
+
+    found.sort_by_key(|entry| entry.manifest.start_time_ns);
+
+    Ok(found)
+}
@@ -0,0 +1,269 @@
+use crate::{
+    cu29::msgs::EncodedImage,
+    recording::{RecordingResult, WALL_TIME_TIMELINE},
+};
+use kornia::{
+    image::{Image, ImageSize},
+    io::{
+        jpeg::{ImageDecoder, ImageEncoder},
+        stream::video::{ImageFormat, VideoCodec, VideoWriter},
+    },
+};
+use std::path::Path;
+
+/// Write the encoded images as individual jpeg files into a folder
+///
+/// The files are named after the image timestamp in nanoseconds.
+pub fn export_jpeg_folder(images: &[EncodedImage], dir: &Path) -> RecordingResult<()> {
+    std::fs::create_dir_all(dir)?;
+    for image in images {
+        // NOTE: the recorder stores the images already jpeg encoded
+        std::fs::write(dir.join(format!("{}.jpg", image.stamp_ns)), &image.data)?;
+    }
+    Ok(())
+}
+
+/// Write the encoded images into a new rerun recording
+///
+/// The images are logged under `/cam/{channel_id}` on the [`WALL_TIME_TIMELINE`], so the
+/// clips can be read back like the recordings.
+pub fn export_rrd(images: &[EncodedImage], path: &Path) -> RecordingResult<()> {
+    let rec = rerun::RecordingStreamBuilder::new("bubbaloop_clip").save(path)?;
+
+    for image in images {
+        rec.set_time_nanos(WALL_TIME_TIMELINE, image.stamp_ns as i64);
+        rec.log(
+            format!("/cam/{}", image.channel_id),
+            &rerun::EncodedImage::from_file_contents(image.data.clone()),
+        )?;
+    }
+
+    rec.flush_blocking();
+
+    Ok(())
+}
+
+/// Write the encoded images into an mp4 video
+///
+/// The video resolution is taken from the first image.
+pub fn export_mp4(images: &[EncodedImage], path: &Path, fps: u32) -> RecordingResult<()> {
+    let mut decoder = ImageDecoder::new()?;
+
+    let mut frames = images.iter().map(|image| decoder.decode(&image.data));
+
+    let Some(first) = frames.next() else {
+        return Err("No images to export".into());
+    };
+    let first = first?;
+
+    let mut writer = VideoWriter::new(
+        path,
+        VideoCodec::H264,
+        ImageFormat::Rgb8,
+        fps as i32,
+        first.size(),
+    )?;
+
+    writer.start()?;
+    writer.write(&first)?;
+    for frame in frames {
+        let frame = frame?;
+        if frame.size() != first.size() {
+            log::warn!(
+                "Skipping frame with a different resolution: {:?}",
+                frame.size()
+            );
+            continue;
+        }
+        writer.write(&frame)?;
+    }
+    writer.close()?;
+
+    Ok(())
+}
+
+/// Write a contact sheet with thumbnails evenly sampled from the encoded images
+///
+/// # Arguments
+///
+/// * `images` - The encoded images to sample from
+/// * `path` - The path to the output jpeg file
+/// * `num_cols` - The number of thumbnails per row
+/// * `num_thumbnails` - The maximum number of thumbnails in the sheet
+/// * `thumbnail_width` - The width of each thumbnail, the height keeps the aspect ratio
+pub fn export_contact_sheet(
+    images: &[EncodedImage],
+    path: &Path,
+    num_cols: usize,
+    num_thumbnails: usize,
+    thumbnail_width: usize,
+) -> RecordingResult<()> {
+    if images.is_empty() || num_cols == 0 || num_thumbnails == 0 || thumbnail_width == 0 {
+        return Err("No images to export".into());
+    }
+
+    let mut decoder = ImageDecoder::new()?;
+
+    // evenly sample the images across the whole range
+    let num_thumbnails = num_thumbnails.min(images.len());
+    let step = images.len() as f64 / num_thumbnails as f64;
+    let thumbnails = (0..num_thumbnails)
+        .map(|i| decoder.decode(&images[(i as f64 * step) as usize].data))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let first = &thumbnails[0];
+    let thumbnail_height = thumbnail_width * first.height() / first.width().max(1);
+
+    let num_rows = num_thumbnails.div_ceil(num_cols);
+    let sheet_width = num_cols * thumbnail_width;
+    let sheet_height = num_rows * thumbnail_height;
+
+    let mut sheet = vec![0u8; sheet_width * sheet_height * 3];
+
+    for (i, thumbnail) in thumbnails.iter().enumerate() {
+        let (offset_x, offset_y) = (
+            (i % num_cols) * thumbnail_width,
+            (i / num_cols) * thumbnail_height,
+        );
+        let src = thumbnail.as_slice();
+        // nearest neighbour resize into the sheet
+        for y in 0..thumbnail_height {
+            let src_y = y * thumbnail.height() / thumbnail_height;
+            for x in 0..thumbnail_width {
+                let src_x = x * thumbnail.width() / thumbnail_width;
+                let src_idx = (src_y * thumbnail.width() + src_x) * 3;
+                let dst_idx = ((offset_y + y) * sheet_width + offset_x + x) * 3;
+                sheet[dst_idx..dst_idx + 3].copy_from_slice(&src[src_idx..src_idx + 3]);
+            }
+        }
+    }
+
+    let sheet = Image::<u8, 3>::new(
+        ImageSize {
+            width: sheet_width,
+            height: sheet_height,
+        },
+        sheet,
+    )?;
+
+    let data = ImageEncoder::new()?.encode(&sheet)?;
+    std::fs::write(path, data)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::{read_encoded_images, TimeRange};
+    use std::path::PathBuf;
+
+    const SECOND_NS: u64 = 1_000_000_000;
+
+    /// A fresh directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "bubbaloop_export_test_{}_{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A jpeg image of the given size filled with a gray level
+    fn jpeg(width: usize, height: usize, level: u8) -> Vec<u8> {
+        let image =
+            Image::<u8, 3>::new(ImageSize { width, height }, vec![level; width * height * 3])
+                .unwrap();
+        ImageEncoder::new().unwrap().encode(&image).unwrap()
+    }
+
+    /// The images of a channel, one per second from the given second
+    fn images(channel_id: u8, seconds: std::ops::Range<u64>) -> Vec<EncodedImage> {
+        seconds
+            .map(|second| EncodedImage {
+                stamp_ns: second * SECOND_NS,
+                channel_id,
+                data: jpeg(64, 48, (second * 20) as u8),
+                encoding: "jpeg".to_string(),
+            })
+            .collect()
+    }
+
+    fn sheet_size(path: &Path) -> (usize, usize) {
+        let sheet = ImageDecoder::new()
+            .unwrap()
+            .decode(&std::fs::read(path).unwrap())
+            .unwrap();
+        (sheet.width(), sheet.height())
+    }
+
+    #[test]
+    fn export_rrd_is_read_back_by_time_range() {
+        let dir = test_dir("rrd");
+        let path = dir.join("clip.rrd");
+
+        let channel_2 = images(2, 1..6);
+        let channel_3 = images(3, 1..3);
+        let all = channel_2
+            .iter()
+            .chain(&channel_3)
+            .cloned()
+            .collect::<Vec<_>>();
+        export_rrd(&all, &path).unwrap();
+
+        let range = TimeRange {
+            start_ns: Some(2 * SECOND_NS as i64),
+            end_ns: Some(4 * SECOND_NS as i64),
+        };
+        let read = read_encoded_images(&path, 2, range).unwrap();
+        let stamps = read.iter().map(|image| image.stamp_ns).collect::<Vec<_>>();
+        assert_eq!(stamps, [2 * SECOND_NS, 3 * SECOND_NS, 4 * SECOND_NS]);
+        for (image, expected) in read.iter().zip(&channel_2[1..4]) {
+            assert_eq!(image.channel_id, 2);
+            assert_eq!(image.data, expected.data);
+        }
+
+        let read = read_encoded_images(&path, 2, TimeRange::default()).unwrap();
+        assert_eq!(read.len(), 5);
+        let read = read_encoded_images(&path, 3, TimeRange::default()).unwrap();
+        assert_eq!(read.len(), 2);
+        let read = read_encoded_images(&path, 4, TimeRange::default()).unwrap();
+        assert!(read.is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn export_contact_sheet_size() {
+        let dir = test_dir("sheet");
+        let path = dir.join("sheet.jpg");
+
+        // the thumbnails are 32 pixels wide, so 24 high for the 64x48 images
+        for (num_images, num_cols, num_thumbnails, size) in [
+            (10, 3, 5, (96, 48)),
+            (10, 1, 3, (32, 72)),
+            (5, 5, 5, (160, 24)),
+            // the thumbnails are limited to the number of images
+            (3, 4, 8, (128, 24)),
+        ] {
+            let images = images(0, 0..num_images);
+            export_contact_sheet(&images, &path, num_cols, num_thumbnails, 32).unwrap();
+            assert_eq!(
+                sheet_size(&path),
+                size,
+                "{} images, {} columns, {} thumbnails",
+                num_images,
+                num_cols,
+                num_thumbnails
+            );
+        }
+
+        assert!(export_contact_sheet(&images(0, 0..3), &path, 0, 4, 32).is_err());
+        assert!(export_contact_sheet(&[], &path, 3, 4, 32).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,13 @@
+pub type RecordingResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The timeline the recordings are indexed by, in nanoseconds since the unix epoch
+pub const WALL_TIME_TIMELINE: &str = "wall_time";
+
+mod catalog;
+pub use catalog::*;
+
+mod export;
+pub use export::*;
+
+mod reader;
+pub use reader::*;
@@ -0,0 +1,158 @@
+use crate::{
+    cu29::msgs::EncodedImage,
+    recording::{RecordingResult, WALL_TIME_TIMELINE},
+};
+use rerun::{
+    dataframe::{EntityPathFilter, QueryEngine, QueryExpression, Timeline},
+    external::arrow::{
+        array::{Array, AsArray, Int64Array},
+        compute::cast,
+        datatypes::{DataType, UInt8Type},
+    },
+    ChunkStoreConfig, StoreKind, VersionPolicy,
+};
+use std::path::Path;
+
+/// A wall-clock time range in nanoseconds since the unix epoch
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeRange {
+    /// the start of the range, unbounded if `None`
+    pub start_ns: Option<i64>,
+    /// the end of the range, unbounded if `None`
+    pub end_ns: Option<i64>,
+}
+
+impl TimeRange {
+    /// Check if the given time is inside the range
+    pub fn contains(&self, time_ns: i64) -> bool {
+        self.start_ns.is_none_or(|start| time_ns >= start)
+            && self.end_ns.is_none_or(|end| time_ns <= end)
+    }
+}
+
+/// Read the encoded images of a channel from a recording produced by the recorder tasks
+///
+/// The images are sorted by their [`WALL_TIME_TIMELINE`] time, which is stored in the
+/// `stamp_ns` field. The recordings made before that timeline existed are read on the
+/// rerun log time instead.
+///
+/// # Arguments
+///
+/// * `path` - The path to the `.rrd` file
+/// * `channel_id` - The channel to read the images from
+/// * `range` - The wall-clock time range of the images to read
+///
+/// # Returns
+///
+/// The encoded images of the channel inside the time range
+pub fn read_encoded_images(
+    path: &Path,
+    channel_id: u8,
+    range: TimeRange,
+) -> RecordingResult<Vec<EncodedImage>> {
+    let engines =
+        QueryEngine::from_rrd_filepath(&ChunkStoreConfig::DEFAULT, path, VersionPolicy::Warn)?;
+
+    let entity_path = format!("/cam/{}", channel_id);
+    let entity_path_filter = EntityPathFilter::try_from(entity_path.as_str())?;
+    let mut images = Vec::new();
+
+    for timeline in [
+        Timeline::new_temporal(WALL_TIME_TIMELINE),
+        Timeline::log_time(),
+    ] {
+        let mut has_timeline = false;
+        for (store_id, engine) in &engines {
+            if store_id.kind != StoreKind::Recording {
+                continue;
+            }
+
+            let query = QueryExpression {
+                filtered_index: Some(timeline),
+                view_contents: Some(
+                    engine
+                        .iter_entity_paths_sorted(&entity_path_filter)
+                        .map(|entity_path| (entity_path, None))
+                        .collect(),
+                ),
+                ..Default::default()
+            };
+
+            let query_handle = engine.query(query);
+
+            for batch in query_handle.batch_iter() {
+                let schema = batch.schema();
+
+                // find the index and the image blob columns
+                let mut time_column = None;
+                let mut blob_column = None;
+                for (i, field) in schema.fields().iter().enumerate() {
+                    if field.name() == timeline.name().as_str() {
+                        time_column = Some(i);
+                    } else if field.name().contains("Blob") {
+                        blob_column = Some(i);
+                    }
+                }
+
+                let (Some(time_column), Some(blob_column)) = (time_column, blob_column) else {
+                    continue;
+                };
+                has_timeline = true;
+
+                let times = cast(batch.column(time_column), &DataType::Int64)?;
+                let Some(times) = times.as_any().downcast_ref::<Int64Array>() else {
+                    continue;
+                };
+                let blobs = batch.column(blob_column).as_list::<i32>();
+
+                for row in 0..batch.num_rows() {
+                    if times.is_null(row) || blobs.is_null(row) {
+                        continue;
+                    }
+
+                    let time_ns = times.value(row);
+                    if !range.contains(time_ns) {
+                        continue;
+                    }
+
+                    let Some(data) = blob_bytes(blobs.value(row).as_ref()) else {
+                        continue;
+                    };
+
+                    images.push(EncodedImage {
+                        stamp_ns: time_ns as u64,
+                        channel_id,
+                        data,
+                        encoding: "jpeg".to_string(),
+                    });
+                }
+            }
+        }
+
+        // only fall back to the log time if the recording has no wall time
+        if has_timeline {
+            break;
+        }
+    }
+
+    images.sort_by_key(|image| image.stamp_ns);
+
+    Ok(images)
+}
+
+/// Extract the bytes of the first blob instance of a cell
+fn blob_bytes(cell: &dyn Array) -> Option<Vec<u8>> {
+    if cell.is_empty() {
+        return None;
+    }
+    match cell.data_type() {
+        // NOTE: a blob is a list of bytes, one per instance
+        DataType::List(_) => {
+            let blob = cell.as_list::<i32>().value(0);
+            Some(blob.as_primitive::<UInt8Type>().values().to_vec())
+        }
+        DataType::Binary => Some(cell.as_binary::<i32>().value(0).to_vec()),
+        DataType::LargeBinary => Some(cell.as_binary::<i64>().value(0).to_vec()),
+        _ => None,
+    }
+}
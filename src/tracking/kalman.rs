@@ -0,0 +1,180 @@
+/// A constant velocity Kalman filter of a single coordinate
+///
+/// The state is the position and the velocity, the measurement is the position.
+#[derive(Clone, Debug)]
+struct AxisFilter {
+    // the position and the velocity
+    x: [f32; 2],
+    // the covariance of the state
+    p: [[f32; 2]; 2],
+}
+
+impl AxisFilter {
+    fn new(position: f32, position_var: f32, velocity_var: f32) -> Self {
+        Self {
+            x: [position, 0.0],
+            p: [[position_var, 0.0], [0.0, velocity_var]],
+        }
+    }
+
+    fn predict(&mut self, dt: f32, q: f32) {
+        let [pos, vel] = self.x;
+        self.x = [pos + vel * dt, vel];
+
+        // P = F P F^T + Q with F = [[1, dt], [0, 1]] and a white noise acceleration Q
+        let [[p00, p01], [p10, p11]] = self.p;
+        let (dt2, dt3, dt4) = (dt * dt, dt * dt * dt, dt * dt * dt * dt);
+        self.p = [
+            [
+                p00 + dt * (p10 + p01) + dt2 * p11 + q * dt4 / 4.0,
+                p01 + dt * p11 + q * dt3 / 2.0,
+            ],
+            [p10 + dt * p11 + q * dt3 / 2.0, p11 + q * dt2],
+        ];
+    }
+
+    fn update(&mut self, z: f32, r: f32) {
+        let [[p00, p01], [p10, p11]] = self.p;
+        let s = p00 + r;
+        let k = [p00 / s, p10 / s];
+        let y = z - self.x[0];
+
+        self.x = [self.x[0] + k[0] * y, self.x[1] + k[1] * y];
+        self.p = [
+            [(1.0 - k[0]) * p00, (1.0 - k[0]) * p01],
+            [p10 - k[1] * p00, p11 - k[1] * p01],
+        ];
+    }
+}
+
+/// A Kalman filter of a box with a constant velocity of its center and size
+///
+/// The coordinates are filtered independently, which is exact for the constant velocity
+/// model with a diagonal noise.
+#[derive(Clone, Debug)]
+pub struct KalmanBox {
+    // the center x, center y, width and height
+    axes: [AxisFilter; 4],
+}
+
+impl KalmanBox {
+    // the noises in normalized image units, tuned for boxes of a few percents of the image
+    const MEASUREMENT_VAR: f32 = 1e-4;
+    const VELOCITY_VAR: f32 = 1e-2;
+    const ACCELERATION_VAR: f32 = 1e-2;
+
+    /// Start a filter at the given box `[x_min, y_min, x_max, y_max]`
+    pub fn new(bbox: &[f32; 4]) -> Self {
+        let measurement = to_center_size(bbox);
+        Self {
+            axes: measurement
+                .map(|v| AxisFilter::new(v, Self::MEASUREMENT_VAR, Self::VELOCITY_VAR)),
+        }
+    }
+
+    /// Move the box forward by `dt` seconds
+    pub fn predict(&mut self, dt: f32) {
+        for axis in &mut self.axes {
+            axis.predict(dt, Self::ACCELERATION_VAR);
+        }
+        // the size can not become negative
+        for axis in &mut self.axes[2..] {
+            axis.x[0] = axis.x[0].max(0.0);
+        }
+    }
+
+    /// Correct the box with a new measurement `[x_min, y_min, x_max, y_max]`
+    pub fn update(&mut self, bbox: &[f32; 4]) {
+        let measurement = to_center_size(bbox);
+        for (axis, z) in self.axes.iter_mut().zip(measurement) {
+            axis.update(z, Self::MEASUREMENT_VAR);
+        }
+    }
+
+    /// The estimated box `[x_min, y_min, x_max, y_max]`
+    pub fn bbox(&self) -> [f32; 4] {
+        let [cx, cy, w, h] = self.axes.each_ref().map(|axis| axis.x[0]);
+        [cx - w / 2.0, cy - h / 2.0, cx + w / 2.0, cy + h / 2.0]
+    }
+
+    /// The estimated velocity of the center in normalized units per second
+    pub fn velocity(&self) -> [f32; 2] {
+        [self.axes[0].x[1], self.axes[1].x[1]]
+    }
+}
+
+fn to_center_size(bbox: &[f32; 4]) -> [f32; 4] {
+    [
+        (bbox[0] + bbox[2]) / 2.0,
+        (bbox[1] + bbox[3]) / 2.0,
+        bbox[2] - bbox[0],
+        bbox[3] - bbox[1],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_box_eq(actual: [f32; 4], expected: [f32; 4]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn new_filter_starts_at_the_box() {
+        let filter = KalmanBox::new(&[0.1, 0.2, 0.3, 0.5]);
+        assert_box_eq(filter.bbox(), [0.1, 0.2, 0.3, 0.5]);
+        assert_eq!(filter.velocity(), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn predict_without_velocity_keeps_the_box() {
+        let mut filter = KalmanBox::new(&[0.1, 0.2, 0.3, 0.5]);
+        filter.predict(0.5);
+        assert_box_eq(filter.bbox(), [0.1, 0.2, 0.3, 0.5]);
+    }
+
+    #[test]
+    fn update_weights_the_prediction_and_the_measurement() {
+        // the initial and the measurement variances are equal, so the gain is one half
+        let mut filter = KalmanBox::new(&[0.0, 0.0, 0.2, 0.2]);
+        filter.update(&[0.1, 0.1, 0.3, 0.3]);
+        assert_box_eq(filter.bbox(), [0.05, 0.05, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn update_estimates_the_velocity() {
+        // the box moves right by 0.01 every 0.1 seconds
+        let mut filter = KalmanBox::new(&[0.0, 0.4, 0.2, 0.6]);
+        for i in 1..=30 {
+            let x = i as f32 * 0.01;
+            filter.predict(0.1);
+            filter.update(&[x, 0.4, x + 0.2, 0.6]);
+        }
+
+        let [vx, vy] = filter.velocity();
+        assert!((vx - 0.1).abs() < 0.01, "vx = {}", vx);
+        assert!(vy.abs() < 0.01, "vy = {}", vy);
+
+        // the prediction follows the motion without measurement
+        filter.predict(0.1);
+        assert_box_eq(filter.bbox(), [0.31, 0.4, 0.51, 0.6]);
+    }
+
+    #[test]
+    fn predict_does_not_make_the_size_negative() {
+        // the box shrinks by 0.01 every 0.1 seconds
+        let mut filter = KalmanBox::new(&[0.3, 0.3, 0.5, 0.5]);
+        for i in 1..=10 {
+            let margin = i as f32 * 0.005;
+            filter.predict(0.1);
+            filter.update(&[0.3 + margin, 0.3 + margin, 0.5 - margin, 0.5 - margin]);
+        }
+
+        filter.predict(60.0);
+        let bbox = filter.bbox();
+        assert!(bbox[2] >= bbox[0] && bbox[3] >= bbox[1], "{:?}", bbox);
+    }
+}
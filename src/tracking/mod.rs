@@ -0,0 +1,5 @@
+mod kalman;
+pub use kalman::*;
+
+mod tracker;
+pub use tracker::*;
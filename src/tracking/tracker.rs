@@ -0,0 +1,416 @@
+use crate::{
+    cu29::msgs::{Detection, Track, TrackStatus},
+    inference::box_iou,
+    tracking::KalmanBox,
+};
+use serde::{Deserialize, Serialize};
+
+/// The configuration of the multi-object tracker
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TrackerConfig {
+    /// the minimum IoU between a predicted track and a detection to associate them
+    pub iou_threshold: f32,
+    /// the score from which a detection starts a new track, the lower ones only extend tracks
+    pub high_score: f32,
+    /// the number of detections before a track is confirmed
+    pub min_hits: u32,
+    /// the time after which a lost track is removed
+    pub max_lost_ms: u64,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            iou_threshold: 0.3,
+            high_score: 0.5,
+            min_hits: 3,
+            max_lost_ms: 1000,
+        }
+    }
+}
+
+/// The matched `(track, detection)` pairs, the unmatched tracks and detections
+type Association = (Vec<(usize, usize)>, Vec<usize>, Vec<usize>);
+
+/// The state of a tracked object
+struct TrackState {
+    track_id: u64,
+    label: String,
+    filter: KalmanBox,
+    status: TrackStatus,
+    hits: u32,
+    first_seen_ns: u64,
+    last_seen_ns: u64,
+}
+
+impl TrackState {
+    fn to_track(&self, stamp_ns: u64) -> Track {
+        Track {
+            track_id: self.track_id,
+            label: self.label.clone(),
+            bbox: self.filter.bbox(),
+            velocity: self.filter.velocity(),
+            status: self.status,
+            hits: self.hits,
+            age_ms: stamp_ns.saturating_sub(self.first_seen_ns) / 1_000_000,
+            lost_ms: stamp_ns.saturating_sub(self.last_seen_ns) / 1_000_000,
+        }
+    }
+}
+
+/// A SORT-style tracker of the detections of a single channel
+///
+/// The tracks are predicted with a constant velocity Kalman filter and greedily associated
+/// with the detections of the same label by IoU. Like ByteTrack, the detections with a low
+/// score are associated in a second pass with the confirmed tracks only, to keep the objects
+/// through occlusions without starting spurious tracks.
+pub struct Tracker {
+    config: TrackerConfig,
+    tracks: Vec<TrackState>,
+    next_id: u64,
+    last_stamp_ns: Option<u64>,
+}
+
+impl Tracker {
+    pub fn new(config: TrackerConfig) -> Self {
+        Self {
+            config,
+            tracks: Vec::new(),
+            next_id: 1,
+            last_stamp_ns: None,
+        }
+    }
+
+    /// Update the tracks with the detections of a new frame
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp_ns` - The timestamp of the frame
+    /// * `detections` - The detections of the frame, without score they count as confident
+    ///
+    /// # Returns
+    ///
+    /// The confirmed and lost tracks
+    pub fn update(&mut self, stamp_ns: u64, detections: &[Detection]) -> Vec<Track> {
+        let dt = self
+            .last_stamp_ns
+            .map_or(0.0, |last| stamp_ns.saturating_sub(last) as f32 * 1e-9);
+        self.last_stamp_ns = Some(stamp_ns);
+
+        for track in &mut self.tracks {
+            track.filter.predict(dt);
+        }
+
+        let (high, low): (Vec<usize>, Vec<usize>) = (0..detections.len()).partition(|&i| {
+            detections[i]
+                .score
+                .is_none_or(|score| score >= self.config.high_score)
+        });
+
+        // first pass: all the tracks with the confident detections
+        let all_tracks = (0..self.tracks.len()).collect::<Vec<_>>();
+        let (matches, unmatched_tracks, unmatched_high) =
+            self.associate(&all_tracks, &high, detections);
+
+        // second pass: the remaining confirmed tracks with the low score detections
+        let remaining = unmatched_tracks
+            .into_iter()
+            .filter(|&t| self.tracks[t].status != TrackStatus::Tentative)
+            .collect::<Vec<_>>();
+        let (low_matches, _, _) = self.associate(&remaining, &low, detections);
+
+        let mut updated = vec![false; self.tracks.len()];
+        for (t, d) in matches.into_iter().chain(low_matches) {
+            let track = &mut self.tracks[t];
+            track.filter.update(&detections[d].bbox);
+            track.hits += 1;
+            track.last_seen_ns = stamp_ns;
+            if track.status == TrackStatus::Lost || track.hits >= self.config.min_hits {
+                track.status = TrackStatus::Confirmed;
+            }
+            updated[t] = true;
+        }
+
+        // the tracks without detection are lost, the tentative ones are dropped
+        let max_lost_ns = self.config.max_lost_ms * 1_000_000;
+        let mut index = 0;
+        self.tracks.retain_mut(|track| {
+            let is_updated = updated[index];
+            index += 1;
+            if is_updated {
+                return true;
+            }
+            if track.status == TrackStatus::Tentative {
+                return false;
+            }
+            track.status = TrackStatus::Lost;
+            stamp_ns.saturating_sub(track.last_seen_ns) <= max_lost_ns
+        });
+
+        // the confident detections without track start new ones
+        for d in unmatched_high {
+            let detection = &detections[d];
+            let status = if self.config.min_hits <= 1 {
+                TrackStatus::Confirmed
+            } else {
+                TrackStatus::Tentative
+            };
+            self.tracks.push(TrackState {
+                track_id: self.next_id,
+                label: detection.label.clone(),
+                filter: KalmanBox::new(&detection.bbox),
+                status,
+                hits: 1,
+                first_seen_ns: stamp_ns,
+                last_seen_ns: stamp_ns,
+            });
+            self.next_id += 1;
+        }
+
+        self.tracks
+            .iter()
+            .filter(|track| track.status != TrackStatus::Tentative)
+            .map(|track| track.to_track(stamp_ns))
+            .collect()
+    }
+
+    /// Greedily match the tracks and the detections of the same label by decreasing IoU
+    fn associate(
+        &self,
+        tracks: &[usize],
+        candidates: &[usize],
+        detections: &[Detection],
+    ) -> Association {
+        let mut pairs = Vec::new();
+        for &t in tracks {
+            let track = &self.tracks[t];
+            let bbox = track.filter.bbox();
+            for &d in candidates {
+                if detections[d].label != track.label {
+                    continue;
+                }
+                let iou = box_iou(&bbox, &detections[d].bbox);
+                if iou >= self.config.iou_threshold {
+                    pairs.push((iou, t, d));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut matches = Vec::new();
+        let mut track_used = tracks.iter().map(|&t| (t, false)).collect::<Vec<_>>();
+        let mut detection_used = candidates.iter().map(|&d| (d, false)).collect::<Vec<_>>();
+        for (_, t, d) in pairs {
+            let track = track_used.iter_mut().find(|(id, _)| *id == t);
+            let detection = detection_used.iter_mut().find(|(id, _)| *id == d);
+            if let (Some((_, track_used)), Some((_, detection_used))) = (track, detection) {
+                if !*track_used && !*detection_used {
+                    *track_used = true;
+                    *detection_used = true;
+                    matches.push((t, d));
+                }
+            }
+        }
+
+        let unmatched = |used: Vec<(usize, bool)>| {
+            used.into_iter()
+                .filter(|(_, used)| !used)
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>()
+        };
+
+        (matches, unmatched(track_used), unmatched(detection_used))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The interval between two frames, 100 ms
+    const FRAME_NS: u64 = 100_000_000;
+
+    fn detection(label: &str, bbox: [f32; 4], score: Option<f32>) -> Detection {
+        Detection {
+            label: label.to_string(),
+            bbox,
+            seg_codes: None,
+            score,
+        }
+    }
+
+    fn person(score: Option<f32>) -> Detection {
+        detection("person", [0.2, 0.2, 0.4, 0.6], score)
+    }
+
+    /// A tracker with a confirmed person track after `min_hits` frames
+    fn confirmed_tracker() -> Tracker {
+        let mut tracker = Tracker::new(TrackerConfig::default());
+        for frame in 0..3 {
+            tracker.update(frame * FRAME_NS, &[person(Some(0.9))]);
+        }
+        tracker
+    }
+
+    #[test]
+    fn tracks_are_reported_once_confirmed() {
+        let mut tracker = Tracker::new(TrackerConfig::default());
+
+        assert!(tracker.update(0, &[person(Some(0.9))]).is_empty());
+        assert!(tracker.update(FRAME_NS, &[person(Some(0.9))]).is_empty());
+
+        let tracks = tracker.update(2 * FRAME_NS, &[person(Some(0.9))]);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].track_id, 1);
+        assert_eq!(tracks[0].status, TrackStatus::Confirmed);
+        assert_eq!(tracks[0].hits, 3);
+        assert_eq!(tracks[0].age_ms, 200);
+    }
+
+    #[test]
+    fn track_ids_are_stable_across_frames() {
+        let mut tracker = Tracker::new(TrackerConfig::default());
+
+        for frame in 0..10u64 {
+            // a person walks right while a car is parked
+            let x = frame as f32 * 0.01;
+            let tracks = tracker.update(
+                frame * FRAME_NS,
+                &[
+                    detection("car", [0.6, 0.6, 0.9, 0.8], None),
+                    detection("person", [x, 0.2, x + 0.2, 0.6], None),
+                ],
+            );
+            if frame < 2 {
+                assert!(tracks.is_empty());
+                continue;
+            }
+
+            let ids = tracks
+                .iter()
+                .map(|track| (track.label.as_str(), track.track_id))
+                .collect::<Vec<_>>();
+            assert_eq!(ids, [("car", 1), ("person", 2)]);
+        }
+    }
+
+    #[test]
+    fn detections_of_another_label_do_not_extend_tracks() {
+        let mut tracker = confirmed_tracker();
+
+        let tracks = tracker.update(3 * FRAME_NS, &[detection("dog", person(None).bbox, None)]);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].track_id, 1);
+        assert_eq!(tracks[0].status, TrackStatus::Lost);
+    }
+
+    #[test]
+    fn lost_tracks_are_removed_after_max_lost_ms() {
+        let mut tracker = confirmed_tracker();
+        let last_seen_ns = 2 * FRAME_NS;
+
+        let tracks = tracker.update(last_seen_ns + FRAME_NS, &[]);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].status, TrackStatus::Lost);
+        assert_eq!(tracks[0].lost_ms, 100);
+
+        let tracks = tracker.update(last_seen_ns + 1_000_000_000, &[]);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].lost_ms, 1000);
+
+        assert!(tracker
+            .update(last_seen_ns + 1_000_000_000 + FRAME_NS, &[])
+            .is_empty());
+    }
+
+    #[test]
+    fn lost_tracks_are_confirmed_again_with_the_same_id() {
+        let mut tracker = confirmed_tracker();
+
+        let tracks = tracker.update(3 * FRAME_NS, &[]);
+        assert_eq!(tracks[0].status, TrackStatus::Lost);
+
+        let tracks = tracker.update(4 * FRAME_NS, &[person(Some(0.9))]);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].track_id, 1);
+        assert_eq!(tracks[0].status, TrackStatus::Confirmed);
+        assert_eq!(tracks[0].hits, 4);
+        assert_eq!(tracks[0].lost_ms, 0);
+    }
+
+    #[test]
+    fn missed_tentative_tracks_are_dropped() {
+        let mut tracker = Tracker::new(TrackerConfig::default());
+
+        tracker.update(0, &[person(Some(0.9))]);
+        tracker.update(FRAME_NS, &[]);
+        for frame in 2..4 {
+            assert!(tracker
+                .update(frame * FRAME_NS, &[person(Some(0.9))])
+                .is_empty());
+        }
+
+        let tracks = tracker.update(4 * FRAME_NS, &[person(Some(0.9))]);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].track_id, 2);
+    }
+
+    #[test]
+    fn low_score_detections_do_not_start_tracks() {
+        let mut tracker = Tracker::new(TrackerConfig::default());
+
+        for frame in 0..5 {
+            assert!(tracker
+                .update(frame * FRAME_NS, &[person(Some(0.3))])
+                .is_empty());
+        }
+
+        // no track was started, the next id is the first one
+        tracker.update(5 * FRAME_NS, &[person(Some(0.9))]);
+        tracker.update(6 * FRAME_NS, &[person(Some(0.9))]);
+        let tracks = tracker.update(7 * FRAME_NS, &[person(Some(0.9))]);
+        assert_eq!(tracks[0].track_id, 1);
+    }
+
+    #[test]
+    fn low_score_detections_extend_the_confirmed_tracks() {
+        let mut tracker = confirmed_tracker();
+
+        // e.g. the person is partially occluded
+        for frame in 3..6 {
+            let tracks = tracker.update(frame * FRAME_NS, &[person(Some(0.3))]);
+            assert_eq!(tracks.len(), 1);
+            assert_eq!(tracks[0].track_id, 1);
+            assert_eq!(tracks[0].status, TrackStatus::Confirmed);
+            assert_eq!(tracks[0].hits, frame as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn low_score_detections_do_not_extend_the_tentative_tracks() {
+        let mut tracker = Tracker::new(TrackerConfig::default());
+
+        tracker.update(0, &[person(Some(0.9))]);
+        tracker.update(FRAME_NS, &[person(Some(0.3))]);
+        tracker.update(2 * FRAME_NS, &[person(Some(0.9))]);
+        tracker.update(3 * FRAME_NS, &[person(Some(0.9))]);
+
+        // the tentative track was dropped, a new one was confirmed after three hits
+        let tracks = tracker.update(4 * FRAME_NS, &[person(Some(0.9))]);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].track_id, 2);
+    }
+
+    #[test]
+    fn tracks_are_confirmed_at_once_with_a_single_hit() {
+        let mut tracker = Tracker::new(TrackerConfig {
+            min_hits: 1,
+            ..Default::default()
+        });
+
+        let tracks = tracker.update(0, &[person(None)]);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].status, TrackStatus::Confirmed);
+    }
+}
@@ -0,0 +1,449 @@
+use crate::api::models::webhooks::{
+    WebhookConfig, WebhookDeadLetter, WebhookEvent, WebhookPayload,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+};
+
+/// The header with the kind of the event
+const EVENT_HEADER: &str = "X-Bubbaloop-Event";
+
+/// The header with the id of the delivery
+const DELIVERY_HEADER: &str = "X-Bubbaloop-Delivery";
+
+/// The header with the hex encoded HMAC-SHA256 of the body
+const SIGNATURE_HEADER: &str = "X-Bubbaloop-Signature";
+
+/// The time to wait for a webhook to answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The counter making the delivery ids unique within the same millisecond
+static NEXT_DELIVERY: AtomicU64 = AtomicU64::new(0);
+
+/// Global store of the webhooks and the queue of events to deliver
+#[derive(Clone)]
+pub struct WebhookStore {
+    // the webhooks indexed by their id
+    hooks: Arc<Mutex<BTreeMap<String, WebhookConfig>>>,
+    tx: UnboundedSender<WebhookPayload>,
+    // NOTE: taken by the dispatcher when it starts
+    rx: Arc<Mutex<Option<UnboundedReceiver<WebhookPayload>>>>,
+}
+
+impl Default for WebhookStore {
+    fn default() -> Self {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        Self {
+            hooks: Arc::new(Mutex::new(BTreeMap::new())),
+            tx,
+            rx: Arc::new(Mutex::new(Some(rx))),
+        }
+    }
+}
+
+impl WebhookStore {
+    /// Add a webhook or replace the webhook with the same id
+    pub fn add_hook(&self, hook: WebhookConfig) {
+        self.hooks.lock().unwrap().insert(hook.id.clone(), hook);
+    }
+
+    /// Remove a webhook, returns false if the webhook does not exist
+    pub fn remove_hook(&self, webhook_id: &str) -> bool {
+        self.hooks.lock().unwrap().remove(webhook_id).is_some()
+    }
+
+    /// List all the webhooks
+    pub fn hooks(&self) -> Vec<WebhookConfig> {
+        self.hooks.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Queue an event for the webhooks subscribed to it
+    ///
+    /// This never blocks, so it can be called from the pipeline tasks.
+    pub fn notify(&self, event: WebhookEvent) {
+        let kind = event.kind();
+        if !self.hooks.lock().unwrap().values().any(|h| h.accepts(kind)) {
+            return;
+        }
+
+        let stamp_ns = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+
+        let _ = self.tx.send(WebhookPayload {
+            delivery_id: format!(
+                "{}-{}",
+                stamp_ns / 1_000_000,
+                NEXT_DELIVERY.fetch_add(1, Ordering::Relaxed)
+            ),
+            stamp_ns,
+            event: kind.to_string(),
+            data: event,
+        });
+    }
+
+    /// Spawn the task delivering the queued events in the current tokio runtime
+    ///
+    /// # Arguments
+    ///
+    /// * `dead_letter_path` - The jsonl file where the failed deliveries are appended
+    ///
+    /// # Returns
+    ///
+    /// The handle of the task, `None` if the dispatcher is already running
+    pub fn spawn_dispatcher(
+        &self,
+        dead_letter_path: PathBuf,
+    ) -> Option<tokio::task::JoinHandle<()>> {
+        let mut rx = self.rx.lock().unwrap().take()?;
+        let hooks = self.hooks.clone();
+
+        Some(tokio::spawn(async move {
+            let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+                Ok(client) => client,
+                Err(e) => {
+                    log::error!("Failed to create webhook client: {}", e);
+                    return;
+                }
+            };
+
+            while let Some(payload) = rx.recv().await {
+                let targets = hooks
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .filter(|hook| hook.accepts(&payload.event))
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                // deliver to each webhook separately so that the retries do not block the others
+                for hook in targets {
+                    tokio::spawn(deliver(
+                        client.clone(),
+                        hook,
+                        payload.clone(),
+                        dead_letter_path.clone(),
+                    ));
+                }
+            }
+        }))
+    }
+}
+
+/// Post the payload to the webhook, retrying with an exponential backoff
+async fn deliver(
+    client: reqwest::Client,
+    hook: WebhookConfig,
+    payload: WebhookPayload,
+    dead_letter_path: PathBuf,
+) {
+    let body = match serde_json::to_vec(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            log::error!("Failed to serialize webhook payload: {}", e);
+            return;
+        }
+    };
+
+    let signature = hook.secret.as_ref().map(|secret| sign(secret, &body));
+
+    let mut attempts = 0;
+    let error = loop {
+        attempts += 1;
+
+        let mut request = client
+            .post(&hook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &payload.event)
+            .header(DELIVERY_HEADER, &payload.delivery_id)
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={}", signature));
+        }
+
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                log::debug!("Delivered {} to webhook {}", payload.delivery_id, hook.id);
+                return;
+            }
+            Ok(response) => {
+                let status = response.status();
+                // NOTE: the client errors will not be fixed by retrying, except rate limits
+                if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    break format!("Webhook answered with status {}", status);
+                }
+                format!("Webhook answered with status {}", status)
+            }
+            Err(e) => e.to_string(),
+        };
+
+        if attempts > hook.max_retries {
+            break error;
+        }
+
+        let backoff = Duration::from_secs(1 << (attempts - 1).min(6));
+        log::warn!(
+            "Failed to deliver {} to webhook {}, retrying in {:?}: {}",
+            payload.delivery_id,
+            hook.id,
+            backoff,
+            error
+        );
+        tokio::time::sleep(backoff).await;
+    };
+
+    log::error!(
+        "Giving up delivering {} to webhook {} after {} attempts: {}",
+        payload.delivery_id,
+        hook.id,
+        attempts,
+        error
+    );
+
+    let dead_letter = WebhookDeadLetter {
+        webhook_id: hook.id,
+        url: hook.url,
+        attempts,
+        error,
+        payload,
+    };
+
+    if let Err(e) = write_dead_letter(&dead_letter_path, &dead_letter).await {
+        log::error!("Failed to write webhook dead letter: {}", e);
+    }
+}
+
+/// Append the failed delivery as a json line to the dead-letter log
+async fn write_dead_letter(
+    path: &Path,
+    dead_letter: &WebhookDeadLetter,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut line = serde_json::to_vec(dead_letter)?;
+    line.push(b'\n');
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&line).await?;
+    // NOTE: the write completes in the background unless the file is flushed
+    file.flush().await?;
+
+    Ok(())
+}
+
+/// The hex encoded HMAC-SHA256 of the body with the secret
+fn sign(secret: &str, body: &[u8]) -> String {
+    // SAFETY: HMAC accepts keys of any size
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::time::Instant;
+
+    /// A request received by the test webhook
+    struct Received {
+        headers: HeaderMap,
+        body: Bytes,
+        at: Instant,
+    }
+
+    #[derive(Clone)]
+    struct TestWebhook {
+        // the statuses answered in order, the last one is repeated
+        statuses: Arc<Vec<StatusCode>>,
+        received: Arc<Mutex<Vec<Received>>>,
+    }
+
+    async fn receive(
+        State(webhook): State<TestWebhook>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> StatusCode {
+        let mut received = webhook.received.lock().unwrap();
+        received.push(Received {
+            headers,
+            body,
+            at: Instant::now(),
+        });
+        webhook.statuses[(received.len() - 1).min(webhook.statuses.len() - 1)]
+    }
+
+    /// Serve a webhook on a local port answering with the given statuses
+    async fn serve(statuses: &[StatusCode]) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let webhook = TestWebhook {
+            statuses: Arc::new(statuses.to_vec()),
+            received: Default::default(),
+        };
+        let received = webhook.received.clone();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(webhook);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (url, received)
+    }
+
+    fn hook(url: &str, secret: Option<&str>, max_retries: u32) -> WebhookConfig {
+        WebhookConfig {
+            id: "test".to_string(),
+            url: url.to_string(),
+            secret: secret.map(str::to_string),
+            events: Vec::new(),
+            max_retries,
+        }
+    }
+
+    fn payload() -> WebhookPayload {
+        WebhookPayload {
+            delivery_id: "1700000000000-0".to_string(),
+            stamp_ns: 1_700_000_000_000_000_000,
+            event: "recording_stopped".to_string(),
+            data: WebhookEvent::RecordingStopped {
+                session_id: "session".to_string(),
+                manifest: "/tmp/session/manifest.json".to_string(),
+            },
+        }
+    }
+
+    fn client() -> reqwest::Client {
+        // NOTE: the local webhook must not go through the proxy of the environment
+        reqwest::Client::builder().no_proxy().build().unwrap()
+    }
+
+    fn dead_letter_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "bubbaloop_webhooks_{}_{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn dead_letters(path: &Path) -> Vec<WebhookDeadLetter> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sign_is_the_hex_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn delivery_is_signed_with_the_secret() {
+        let (url, received) = serve(&[StatusCode::OK]).await;
+        let path = dead_letter_path("signed");
+
+        deliver(
+            client(),
+            hook(&url, Some("s3cret"), 3),
+            payload(),
+            path.clone(),
+        )
+        .await;
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let request = &received[0];
+        assert_eq!(request.headers[EVENT_HEADER], "recording_stopped");
+        assert_eq!(request.headers[DELIVERY_HEADER], "1700000000000-0");
+
+        let signature = request.headers[SIGNATURE_HEADER].to_str().unwrap();
+        let signature = hex::decode(signature.strip_prefix("sha256=").unwrap()).unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"s3cret").unwrap();
+        mac.update(&request.body);
+        assert!(mac.verify_slice(&signature).is_ok());
+
+        let body: WebhookPayload = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body.delivery_id, "1700000000000-0");
+        assert!(dead_letters(&path).is_empty());
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_with_backoff() {
+        let (url, received) = serve(&[StatusCode::SERVICE_UNAVAILABLE, StatusCode::OK]).await;
+        let path = dead_letter_path("retried");
+
+        deliver(client(), hook(&url, None, 3), payload(), path.clone()).await;
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert!(received[1].at - received[0].at >= Duration::from_secs(1));
+        assert!(!received[0].headers.contains_key(SIGNATURE_HEADER));
+        assert_eq!(
+            received[0].headers[DELIVERY_HEADER],
+            received[1].headers[DELIVERY_HEADER]
+        );
+        assert!(dead_letters(&path).is_empty());
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (url, received) = serve(&[StatusCode::BAD_REQUEST, StatusCode::OK]).await;
+        let path = dead_letter_path("client_error");
+
+        deliver(client(), hook(&url, None, 3), payload(), path.clone()).await;
+
+        assert_eq!(received.lock().unwrap().len(), 1);
+        let dead_letters = dead_letters(&path);
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].attempts, 1);
+        assert!(dead_letters[0].error.contains("400"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_dead_lettered_after_the_retries() {
+        let (url, received) = serve(&[StatusCode::INTERNAL_SERVER_ERROR]).await;
+        let path = dead_letter_path("dead_letter");
+
+        deliver(client(), hook(&url, None, 1), payload(), path.clone()).await;
+
+        assert_eq!(received.lock().unwrap().len(), 2);
+        let dead_letters = dead_letters(&path);
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].webhook_id, "test");
+        assert_eq!(dead_letters[0].url, url);
+        assert_eq!(dead_letters[0].attempts, 2);
+        assert!(dead_letters[0].error.contains("500"));
+        assert_eq!(dead_letters[0].payload.delivery_id, "1700000000000-0");
+        let _ = std::fs::remove_file(&path);
+    }
+}